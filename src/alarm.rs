@@ -0,0 +1,115 @@
+use bevy::{
+    core::Time,
+    ecs::{
+        component::Component,
+        entity::Entity,
+        system::{Query, Res},
+    },
+    prelude::Transform,
+    reflect::TypeUuid,
+    render::renderer::RenderResources,
+    window::Windows,
+};
+use std::collections::HashMap;
+
+use crate::{cursor_world_position, grid::SpatialGrid, CursorPursuit, Velocity};
+
+/// How startled a boid is, from 0 for calm to 1 for as startled as can be
+///
+/// Startled boids flash brighter and fly faster, and pass their alarm on to the boids around
+/// them so a startle ripples out through the flock. This is handed to FRAGMENT_SHADER as a
+/// uniform, so anything drawn with the boid pipeline needs one even if it never gets startled
+#[derive(Component, RenderResources, Default, TypeUuid)]
+#[uuid = "9a4c2d7e-3b1f-4e85-a6d0-5c8e7f2b1a94"]
+pub struct Alarm {
+    pub level: f32,
+}
+
+/// What startles a boid out of nowhere, as opposed to being alarmed by its neighbors
+#[derive(Clone, Copy)]
+pub enum AlarmTrigger {
+    Never,
+    /// The cursor coming within this distance of the boid
+    CursorWithin(f32),
+    /// The cursor coming within this distance of the boid while boids are evading it, see
+    /// CursorPursuit
+    EvadedCursorWithin(f32),
+}
+
+pub struct AlarmParams {
+    pub trigger: AlarmTrigger,
+    /// How much a boid's alarm drops every second
+    pub decay: f32,
+    /// How close another boid has to be to catch a boid's alarm
+    pub spread_radius: f32,
+    /// The fraction of a boid's alarm its neighbors catch, below 1 so the alarm fades with every
+    /// boid it passes through and a ripple can't keep itself going forever
+    pub spread_falloff: f32,
+    /// A boid less alarmed than this doesn't pass its alarm on at all, which stops a ripple once
+    /// it has faded enough instead of it trickling on at barely visible levels
+    pub min_spread: f32,
+    /// How much faster than its usual Velocity.max a fully alarmed boid can fly, as a fraction
+    pub speed_boost: f32,
+}
+
+impl Default for AlarmParams {
+    fn default() -> Self {
+        AlarmParams {
+            trigger: AlarmTrigger::EvadedCursorWithin(50.0),
+            decay: 1.5,
+            spread_radius: 30.0,
+            spread_falloff: 0.7,
+            min_spread: 0.1,
+            speed_boost: 0.5,
+        }
+    }
+}
+
+/// This has to run after Velocity.max is settled by modulate_speed_by_crowding and before any
+/// steering system steers towards it
+pub fn spread_alarm(
+    time: Res<Time>,
+    params: Res<AlarmParams>,
+    pursuit: Res<CursorPursuit>,
+    windows: Res<Windows>,
+    grid: Res<SpatialGrid>,
+    mut query: Query<(Entity, &Transform, &mut Alarm, &mut Velocity)>,
+) {
+    let cursor = windows
+        .get_primary()
+        .and_then(|window| cursor_world_position(window));
+    let trigger_radius = match (params.trigger, &*pursuit) {
+        (AlarmTrigger::CursorWithin(radius), _)
+        | (AlarmTrigger::EvadedCursorWithin(radius), CursorPursuit::Evade) => Some(radius),
+        _ => None,
+    };
+
+    // Alarms only spread from how things were at the start of the frame, otherwise how far a
+    // ripple got in a single frame would depend on the order boids happen to be visited in
+    let previous = query
+        .iter_mut()
+        .map(|(entity, _, alarm, _)| (entity, alarm.level))
+        .collect::<HashMap<_, _>>();
+
+    for (entity, Transform { translation, .. }, mut alarm, mut velocity) in query.iter_mut() {
+        let position = translation.truncate();
+        let mut level = (alarm.level - params.decay * time.delta_seconds()).max(0.0);
+
+        if let (Some(radius), Some(cursor)) = (trigger_radius, cursor) {
+            if position.distance_squared(cursor) <= radius * radius {
+                level = 1.0;
+            }
+        }
+
+        let caught = grid
+            .within(position, params.spread_radius)
+            .filter(|&(other, _)| other != entity)
+            .filter_map(|(other, _)| previous.get(&other).copied())
+            .filter(|&other| other >= params.min_spread)
+            .fold(0.0, f32::max)
+            * params.spread_falloff;
+        alarm.level = level.max(caught).min(1.0);
+
+        velocity.max *= 1.0 + params.speed_boost * alarm.level;
+    }
+}
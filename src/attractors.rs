@@ -0,0 +1,129 @@
+use bevy::{
+    ecs::{
+        component::Component,
+        entity::Entity,
+        system::{Commands, Local, Query, Res},
+    },
+    input::{keyboard::KeyCode, mouse::MouseButton, Input},
+    math::Vec2,
+    prelude::Transform,
+    window::Windows,
+};
+use std::collections::VecDeque;
+
+use crate::{cursor_world_position, stagger::SteeringStagger, BehaviorToggles, Force, SpawnIndex};
+
+/// A fixed point in the world that pulls boids towards it, or pushes them away from it when its
+/// strength is negative
+///
+/// Unlike the cursor any number of these can exist at once, and they stay put until cleared
+#[derive(Component)]
+pub struct Attractor {
+    pub pos: Vec2,
+    /// How hard the attractor pulls as a fraction of a boid's Force.max, negative strengths push
+    pub strength: f32,
+}
+
+pub struct AttractorParams {
+    /// The strength given to attractors placed by clicking, repulsors get the negative of it
+    pub strength: f32,
+    /// The distance at which an attractor's pull has dropped to half its strength
+    pub falloff: f32,
+    /// The most attractors that can exist at once, placing another one removes the oldest
+    pub max: usize,
+}
+
+impl Default for AttractorParams {
+    fn default() -> Self {
+        AttractorParams {
+            strength: 0.5,
+            falloff: 200.0,
+            max: 16,
+        }
+    }
+}
+
+/// Left clicking places an attractor under the cursor, shift left clicking places a repulsor
+/// and pressing C clears them all
+///
+/// Control left clicking is left to teleport_flock and alt left clicking to spray_boids
+pub fn place_attractors(
+    mut commands: Commands,
+    params: Res<AttractorParams>,
+    windows: Res<Windows>,
+    buttons: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    // Every placed attractor, oldest first
+    mut placed: Local<VecDeque<Entity>>,
+) {
+    if keys.just_pressed(KeyCode::C) {
+        for attractor in placed.drain(..) {
+            commands.entity(attractor).despawn();
+        }
+    }
+
+    let control = keys.pressed(KeyCode::LControl) || keys.pressed(KeyCode::RControl);
+    let alt = keys.pressed(KeyCode::LAlt) || keys.pressed(KeyCode::RAlt);
+    if !buttons.just_pressed(MouseButton::Left) || control || alt {
+        return;
+    }
+    let cursor = match windows
+        .get_primary()
+        .and_then(|window| cursor_world_position(window))
+    {
+        Some(cursor) => cursor,
+        None => return,
+    };
+
+    let repulsor = keys.pressed(KeyCode::LShift) || keys.pressed(KeyCode::RShift);
+    let strength = if repulsor {
+        -params.strength
+    } else {
+        params.strength
+    };
+    placed.push_back(
+        commands
+            .spawn()
+            .insert(Attractor {
+                pos: cursor,
+                strength,
+            })
+            .id(),
+    );
+
+    while placed.len() > params.max {
+        if let Some(oldest) = placed.pop_front() {
+            commands.entity(oldest).despawn();
+        }
+    }
+}
+
+pub fn attract(
+    toggles: Res<BehaviorToggles>,
+    stagger: Res<SteeringStagger>,
+    params: Res<AttractorParams>,
+    attractors: Query<&Attractor>,
+    mut boids: Query<(&mut Force, &Transform, &SpawnIndex)>,
+) {
+    if !toggles.attractors {
+        return;
+    }
+
+    for (mut force, Transform { translation, .. }, index) in boids.iter_mut() {
+        if !stagger.is_due(index) {
+            continue;
+        }
+        let force = force.as_mut();
+        let position = translation.truncate();
+        let pull = attractors
+            .iter()
+            .map(|attractor| {
+                let offset = attractor.pos - position;
+                let distance = offset.length() / params.falloff.max(f32::EPSILON);
+                offset.normalize_or_zero() * attractor.strength / (1.0 + distance * distance)
+            })
+            .fold(Vec2::ZERO, |pull, attractor| pull + attractor);
+        // However many attractors are around, together they pull no harder than a boid could
+        force.vector += Vec2::clamp_length_max(pull * force.max, force.max);
+    }
+}
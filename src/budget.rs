@@ -0,0 +1,100 @@
+use bevy::{
+    core::Time,
+    ecs::system::{Local, Res, ResMut},
+};
+
+use crate::{DespawnExcess, TargetPopulation};
+
+/// Keeps the frame rate up by growing and shrinking the TargetPopulation, boids get despawned
+/// while frames take longer than target_frame_time and spawned back while there's room to spare
+///
+/// Frame times are smoothed before they're compared, and nothing changes while they're within
+/// hysteresis of the target, so a single slow frame doesn't cost any boids and the population
+/// doesn't keep bouncing around a count that sits right at the budget
+pub struct FrameBudget {
+    pub enabled: bool,
+    /// How long a frame should take in seconds, 1/60 for 60fps
+    pub target_frame_time: f32,
+    /// How far off the target frame time is allowed to get before the population is adjusted,
+    /// as a fraction of target_frame_time, 0.1 leaves frames anywhere between 90% and 110% of
+    /// the target alone
+    pub hysteresis: f32,
+    /// How much of every new frame time goes into the smoothed one, smaller is smoother but
+    /// slower to notice changes
+    pub smoothing: f32,
+    /// How many boids are added or taken away per adjustment
+    pub step: usize,
+    /// Seconds to wait after an adjustment before the next one, to give the smoothed frame time
+    /// a chance to settle on the new population
+    pub cooldown: f32,
+    /// The population never gets adjusted below this many boids
+    pub min_population: usize,
+    /// The population never gets adjusted above this many boids
+    pub max_population: usize,
+}
+
+impl Default for FrameBudget {
+    fn default() -> Self {
+        FrameBudget {
+            enabled: false,
+            target_frame_time: 1.0 / 60.0,
+            hysteresis: 0.1,
+            smoothing: 0.05,
+            step: 10,
+            cooldown: 1.0,
+            min_population: 10,
+            max_population: 5000,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct BudgetState {
+    smoothed: Option<f32>,
+    cooldown: f32,
+}
+
+/// Adjusts the TargetPopulation towards the FrameBudget, this turns on DespawnExcess while it's
+/// enabled since shrinking the target wouldn't do anything otherwise
+pub fn fit_population_to_budget(
+    time: Res<Time>,
+    budget: Res<FrameBudget>,
+    mut target: ResMut<TargetPopulation>,
+    mut despawn_excess: ResMut<DespawnExcess>,
+    mut state: Local<BudgetState>,
+) {
+    if !budget.enabled {
+        state.smoothed = None;
+        return;
+    }
+    despawn_excess.0 = true;
+
+    let frame_time = time.delta_seconds();
+    let smoothed = match state.smoothed {
+        Some(smoothed) => smoothed + (frame_time - smoothed) * budget.smoothing.clamp(0.0, 1.0),
+        None => frame_time,
+    };
+    state.smoothed = Some(smoothed);
+
+    if state.cooldown > 0.0 {
+        state.cooldown -= frame_time;
+        return;
+    }
+
+    let band = budget.target_frame_time * budget.hysteresis;
+    let adjusted = if smoothed > budget.target_frame_time + band {
+        target
+            .0
+            .saturating_sub(budget.step)
+            .max(budget.min_population)
+    } else if smoothed < budget.target_frame_time - band {
+        (target.0 + budget.step).min(budget.max_population)
+    } else {
+        target.0
+    };
+
+    if adjusted != target.0 {
+        target.0 = adjusted;
+        state.cooldown = budget.cooldown;
+    }
+}
@@ -0,0 +1,460 @@
+use bevy::{
+    app::{App, CoreStage, Plugin},
+    ecs::{
+        system::{Res, ResMut},
+        world::World,
+    },
+    input::{keyboard::KeyCode, Input},
+    render::{
+        color::Color,
+        pass::{
+            LoadOp, Operations, PassDescriptor, RenderPassColorAttachment,
+            RenderPassDepthStencilAttachment, TextureAttachment,
+        },
+        render_graph::{
+            base, Node, PassNode, RenderGraph, ResourceSlotInfo, ResourceSlots, WindowTextureNode,
+        },
+        renderer::{
+            BufferId, BufferInfo, BufferMapMode, BufferUsage, RenderContext, RenderResourceContext,
+            RenderResourceType,
+        },
+        texture::{Extent3d, TextureDescriptor, TextureFormat, TextureUsage},
+    },
+    window::{WindowId, Windows},
+};
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    fs::{self, File},
+    io::BufWriter,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+const CAPTURE_TEXTURE: &str = "frame_capture_texture";
+const CAPTURE_PASS: &str = "frame_capture_pass";
+const CAPTURE_COPY: &str = "frame_capture_copy";
+
+/// wgpu only copies textures into buffers whose rows are a multiple of this many bytes long
+const COPY_BYTES_PER_ROW_ALIGNMENT: u32 = 256;
+
+/// Saves rendered frames as PNGs, F12 saves a single screenshot and Shift+F12 starts or stops
+/// recording every `every`th frame to a numbered sequence, for turning into gifs. Nothing is
+/// written until one of them is pressed
+///
+/// The swap chain can only be drawn into, so frames that are being saved have the main pass drawn
+/// a second time into a window sized texture that can be copied out of. Every other frame skips
+/// that pass, so having the feature on costs nothing while nothing is being captured. Copies are
+/// read back and encoded on the next frame, which holds that frame up for as long as it takes to
+/// write the PNG
+pub struct FrameCapture {
+    /// Where frames are saved, it's created the first time a frame is saved into it
+    pub directory: PathBuf,
+    /// How many frames go by between saved frames while recording, 1 saves every frame
+    pub every: u32,
+    recording: bool,
+    /// How many frames ago the last recorded frame was
+    phase: u32,
+    /// How many screenshots and recorded frames have been saved so far, for numbering them
+    screenshots: u32,
+    frames: u32,
+    captures: Arc<Mutex<Captures>>,
+}
+
+impl Default for FrameCapture {
+    fn default() -> Self {
+        FrameCapture {
+            directory: PathBuf::from("frames"),
+            every: 1,
+            recording: false,
+            phase: 0,
+            screenshots: 0,
+            frames: 0,
+            captures: Arc::default(),
+        }
+    }
+}
+
+impl FrameCapture {
+    /// The name this frame is saved as if it gets saved at all, a screenshot taken while
+    /// recording is saved as a screenshot and the recording skips that frame
+    fn next_file_name(&mut self, screenshot: bool) -> Option<String> {
+        if screenshot {
+            self.screenshots += 1;
+            return Some(format!("screenshot-{:04}.png", self.screenshots));
+        }
+        if !self.recording {
+            return None;
+        }
+        self.phase = (self.phase + 1) % self.every.max(1);
+        if self.phase != 0 {
+            return None;
+        }
+        self.frames += 1;
+        Some(format!("frame-{:05}.png", self.frames))
+    }
+}
+
+/// What FrameCapture and the render graph's copy node hand back and forth
+#[derive(Default)]
+struct Captures {
+    /// What the next rendered frame is saved as, if it's saved
+    requested: Option<String>,
+    /// Frames copied into buffers, waiting for the GPU to be done with them
+    copied: Vec<CopiedFrame>,
+}
+
+struct CopiedFrame {
+    file_name: String,
+    buffer: BufferId,
+    width: u32,
+    height: u32,
+    bytes_per_row: u32,
+}
+
+pub struct FrameCapturePlugin;
+
+impl Plugin for FrameCapturePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FrameCapture>()
+            .add_startup_system(add_capture_nodes)
+            .add_system_to_stage(CoreStage::First, save_captured_frames)
+            .add_system_to_stage(CoreStage::PreUpdate, request_frame_capture);
+    }
+}
+
+/// How many bytes each row of a frame width pixels wide takes up once copied into a buffer
+fn padded_bytes_per_row(width: u32) -> u32 {
+    let bytes = width * 4;
+    let alignment = COPY_BYTES_PER_ROW_ALIGNMENT;
+    (bytes + alignment - 1) / alignment * alignment
+}
+
+/// The BGRA rows of a copied frame as tightly packed RGBA, which is what PNGs want
+fn bgra_to_rgba(data: &[u8], width: u32, height: u32, bytes_per_row: u32) -> Vec<u8> {
+    let row_length = width as usize * 4;
+    let mut pixels = Vec::with_capacity(row_length * height as usize);
+    for row in data.chunks(bytes_per_row as usize).take(height as usize) {
+        for bgra in row[..row_length].chunks_exact(4) {
+            pixels.extend_from_slice(&[bgra[2], bgra[1], bgra[0], bgra[3]]);
+        }
+    }
+    pixels
+}
+
+fn write_png(
+    path: &Path,
+    width: u32,
+    height: u32,
+    pixels: &[u8],
+) -> Result<(), png::EncodingError> {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header()?.write_image_data(pixels)
+}
+
+/// Draws everything the main pass draws a second time into a texture that can be copied out of
+/// and copies it out, on frames that are being saved
+fn add_capture_nodes(mut render_graph: ResMut<RenderGraph>, capture: Res<FrameCapture>) {
+    // The main pass's pipelines are built for a single sample and the default format, so this
+    // pass has to match them
+    render_graph.add_node(
+        CAPTURE_TEXTURE,
+        WindowTextureNode::new(
+            WindowId::primary(),
+            TextureDescriptor {
+                size: Extent3d::new(1, 1, 1),
+                format: TextureFormat::Bgra8UnormSrgb,
+                usage: TextureUsage::RENDER_ATTACHMENT | TextureUsage::COPY_SRC,
+                ..Default::default()
+            },
+        ),
+    );
+
+    let mut pass = PassNode::<&base::MainPass>::new(PassDescriptor {
+        color_attachments: vec![RenderPassColorAttachment {
+            attachment: TextureAttachment::Input("color_attachment".to_string()),
+            resolve_target: None,
+            ops: Operations {
+                load: LoadOp::Clear(Color::BLACK),
+                store: true,
+            },
+        }],
+        depth_stencil_attachment: Some(RenderPassDepthStencilAttachment {
+            attachment: TextureAttachment::Input("depth".to_string()),
+            depth_ops: Some(Operations {
+                load: LoadOp::Clear(1.0),
+                store: true,
+            }),
+            stencil_ops: None,
+        }),
+        sample_count: 1,
+    });
+    pass.use_default_clear_color(0);
+    pass.add_camera(base::camera::CAMERA_2D);
+    render_graph.add_node(
+        CAPTURE_PASS,
+        CapturePassNode {
+            pass,
+            captures: capture.captures.clone(),
+        },
+    );
+    render_graph
+        .add_slot_edge(
+            CAPTURE_TEXTURE,
+            WindowTextureNode::OUT_TEXTURE,
+            CAPTURE_PASS,
+            "color_attachment",
+        )
+        .unwrap();
+    // The main pass is done with its depth texture by now, so it's cleared and reused
+    render_graph
+        .add_slot_edge(
+            base::node::MAIN_DEPTH_TEXTURE,
+            WindowTextureNode::OUT_TEXTURE,
+            CAPTURE_PASS,
+            "depth",
+        )
+        .unwrap();
+    render_graph
+        .add_node_edge(base::node::MAIN_PASS, CAPTURE_PASS)
+        .unwrap();
+
+    render_graph.add_node(
+        CAPTURE_COPY,
+        FrameCopyNode {
+            captures: capture.captures.clone(),
+        },
+    );
+    render_graph
+        .add_slot_edge(
+            CAPTURE_TEXTURE,
+            WindowTextureNode::OUT_TEXTURE,
+            CAPTURE_COPY,
+            FrameCopyNode::IN_TEXTURE,
+        )
+        .unwrap();
+    render_graph
+        .add_node_edge(CAPTURE_PASS, CAPTURE_COPY)
+        .unwrap();
+}
+
+/// The main pass drawn again into the capture texture, only on frames that have been requested
+struct CapturePassNode {
+    pass: PassNode<&'static base::MainPass>,
+    captures: Arc<Mutex<Captures>>,
+}
+
+impl Node for CapturePassNode {
+    fn input(&self) -> &[ResourceSlotInfo] {
+        self.pass.input()
+    }
+
+    fn prepare(&mut self, world: &mut World) {
+        self.pass.prepare(world);
+    }
+
+    fn update(
+        &mut self,
+        world: &World,
+        render_context: &mut dyn RenderContext,
+        input: &ResourceSlots,
+        output: &mut ResourceSlots,
+    ) {
+        if self.captures.lock().unwrap().requested.is_none() {
+            return;
+        }
+        self.pass.update(world, render_context, input, output);
+    }
+}
+
+/// Copies the capture texture into a buffer the CPU can read whenever a frame has been requested
+struct FrameCopyNode {
+    captures: Arc<Mutex<Captures>>,
+}
+
+impl FrameCopyNode {
+    const IN_TEXTURE: &'static str = "texture";
+}
+
+impl Node for FrameCopyNode {
+    fn input(&self) -> &[ResourceSlotInfo] {
+        static INPUT: &[ResourceSlotInfo] = &[ResourceSlotInfo {
+            name: Cow::Borrowed(FrameCopyNode::IN_TEXTURE),
+            resource_type: RenderResourceType::Texture,
+        }];
+        INPUT
+    }
+
+    fn update(
+        &mut self,
+        world: &World,
+        render_context: &mut dyn RenderContext,
+        input: &ResourceSlots,
+        _output: &mut ResourceSlots,
+    ) {
+        let mut captures = self.captures.lock().unwrap();
+        let file_name = match captures.requested.take() {
+            Some(file_name) => file_name,
+            None => return,
+        };
+        // The capture texture is always as big as the window, a minimized one has nothing in it
+        let size = world
+            .get_resource::<Windows>()
+            .and_then(|windows| windows.get_primary())
+            .map(|window| (window.physical_width(), window.physical_height()))
+            .filter(|&(width, height)| width > 0 && height > 0);
+        let texture = input
+            .get(FrameCopyNode::IN_TEXTURE)
+            .and_then(|resource| resource.get_texture());
+        let ((width, height), texture) = match size.zip(texture) {
+            Some(capture) => capture,
+            None => return,
+        };
+
+        let bytes_per_row = padded_bytes_per_row(width);
+        let buffer = render_context.resources().create_buffer(BufferInfo {
+            size: (bytes_per_row * height) as usize,
+            buffer_usage: BufferUsage::MAP_READ | BufferUsage::COPY_DST,
+            mapped_at_creation: false,
+        });
+        render_context.copy_texture_to_buffer(
+            texture,
+            [0, 0, 0],
+            0,
+            buffer,
+            0,
+            bytes_per_row,
+            Extent3d::new(width, height, 1),
+        );
+        captures.copied.push(CopiedFrame {
+            file_name,
+            buffer,
+            width,
+            height,
+            bytes_per_row,
+        });
+    }
+}
+
+pub fn request_frame_capture(keys: Res<Input<KeyCode>>, mut capture: ResMut<FrameCapture>) {
+    let capture = capture.as_mut();
+    let shift = keys.pressed(KeyCode::LShift) || keys.pressed(KeyCode::RShift);
+    let f12 = keys.just_pressed(KeyCode::F12);
+    if f12 && shift {
+        capture.recording = !capture.recording;
+        capture.phase = 0;
+        eprintln!(
+            "{} recording frames to {}",
+            if capture.recording {
+                "Started"
+            } else {
+                "Stopped"
+            },
+            capture.directory.display()
+        );
+    }
+
+    // A frame that's still waiting to be rendered, when rendering is being throttled, keeps its
+    // place so the sequence doesn't skip any numbers
+    let mut captures = capture.captures.lock().unwrap();
+    if captures.requested.is_some() {
+        return;
+    }
+    captures.requested = capture.next_file_name(f12 && !shift);
+}
+
+/// Reads back the frames copied last frame and writes them out, the GPU has been handed the copy
+/// by now and mapping the buffers waits for it to finish
+pub fn save_captured_frames(
+    capture: Res<FrameCapture>,
+    render_resources: Res<Box<dyn RenderResourceContext>>,
+) {
+    let copied = std::mem::take(&mut capture.captures.lock().unwrap().copied);
+    if copied.is_empty() {
+        return;
+    }
+    let directory = match fs::create_dir_all(&capture.directory) {
+        Ok(()) => Some(&capture.directory),
+        Err(error) => {
+            eprintln!(
+                "Couldn't create {} to save frames in: {}",
+                capture.directory.display(),
+                error
+            );
+            None
+        }
+    };
+
+    for frame in copied {
+        if let Some(directory) = directory {
+            render_resources.map_buffer(frame.buffer, BufferMapMode::Read);
+            let pixels = RefCell::new(Vec::new());
+            render_resources.read_mapped_buffer(
+                frame.buffer,
+                0..(frame.bytes_per_row * frame.height) as u64,
+                &|data, _| {
+                    *pixels.borrow_mut() =
+                        bgra_to_rgba(data, frame.width, frame.height, frame.bytes_per_row);
+                },
+            );
+            render_resources.unmap_buffer(frame.buffer);
+
+            let path = directory.join(&frame.file_name);
+            if let Err(error) = write_png(&path, frame.width, frame.height, &pixels.into_inner()) {
+                eprintln!("Couldn't save {}: {}", path.display(), error);
+            }
+        }
+        render_resources.remove_buffer(frame.buffer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_are_padded_to_the_copy_alignment() {
+        assert_eq!(padded_bytes_per_row(1), 256);
+        assert_eq!(padded_bytes_per_row(64), 256);
+        assert_eq!(padded_bytes_per_row(65), 512);
+        assert_eq!(padded_bytes_per_row(800), 3328);
+    }
+
+    #[test]
+    fn padding_is_dropped_and_channels_swapped() {
+        // Two rows of one pixel each, padded out to 8 bytes a row
+        let data = [1, 2, 3, 4, 0, 0, 0, 0, 5, 6, 7, 8, 0, 0, 0, 0];
+        assert_eq!(bgra_to_rgba(&data, 1, 2, 8), vec![3, 2, 1, 4, 7, 6, 5, 8]);
+    }
+
+    #[test]
+    fn recording_saves_every_nth_frame() {
+        let mut capture = FrameCapture {
+            every: 3,
+            ..Default::default()
+        };
+        assert_eq!(capture.next_file_name(false), None);
+
+        capture.recording = true;
+        let saved = (0..9)
+            .filter_map(|_| capture.next_file_name(false))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            saved,
+            ["frame-00001.png", "frame-00002.png", "frame-00003.png"]
+        );
+    }
+
+    #[test]
+    fn screenshots_are_numbered_apart_from_recordings() {
+        let mut capture = FrameCapture {
+            recording: true,
+            ..Default::default()
+        };
+        assert_eq!(capture.next_file_name(false).unwrap(), "frame-00001.png");
+        assert_eq!(capture.next_file_name(true).unwrap(), "screenshot-0001.png");
+        assert_eq!(capture.next_file_name(false).unwrap(), "frame-00002.png");
+    }
+}
@@ -0,0 +1,156 @@
+use bevy::{
+    asset::Handle,
+    ecs::{
+        entity::Entity,
+        query::With,
+        system::{Local, Query, Res},
+    },
+    math::Vec2,
+    prelude::Transform,
+    render::{
+        color::Color,
+        draw::Visible,
+        mesh::{Indices, Mesh},
+        pipeline::PrimitiveTopology,
+    },
+};
+use std::collections::HashSet;
+
+use crate::{
+    alarm::Alarm,
+    color::vertex_color,
+    create_boid_mesh_bundle,
+    debug::{DebugOverlay, OverlayDrawing},
+    flocking::{FlockingParams, Neighbors},
+    CometTail, Tint, Velocity, ATTRIBUTE_TAIL,
+};
+
+/// Draws a line between every boid and each of its Neighbors, toggled with
+/// DebugOverlays::neighbor_lines
+///
+/// Lines fade out the further apart the two boids are, so tight knots of boids light up while
+/// boids just barely in range of each other hardly show. Every line goes into a single mesh
+/// that's rebuilt every frame, there can be a lot of them with a big flock so only up to
+/// max_lines are drawn
+pub struct NeighborLines {
+    /// How many pixels wide the lines are
+    pub width: f32,
+    pub max_lines: usize,
+    pub color: Color,
+}
+
+impl Default for NeighborLines {
+    fn default() -> Self {
+        NeighborLines {
+            width: 1.0,
+            max_lines: 5000,
+            color: Color::CYAN,
+        }
+    }
+}
+
+/// The entity showing the lines and the mesh handle it draws with, which keeps being reused
+pub struct NeighborLinesMesh {
+    entity: Entity,
+    mesh: Handle<Mesh>,
+}
+
+pub fn draw_neighbor_lines(
+    drawing: OverlayDrawing,
+    lines: Res<NeighborLines>,
+    params: Res<FlockingParams>,
+    mut drawn: Local<Option<NeighborLinesMesh>>,
+    // Whether the warning about going over max_lines was printed already, it would flood the
+    // output otherwise
+    mut warned: Local<bool>,
+    mut visibility: Query<&mut Visible>,
+    boids: Query<(Entity, &Transform, &Neighbors), With<Velocity>>,
+) {
+    let OverlayDrawing {
+        overlays,
+        mut commands,
+        assets,
+        mut meshes,
+    } = drawing;
+    let show = overlays.shows(DebugOverlay::NeighborLines);
+    let drawn = match drawn.as_ref() {
+        Some(drawn) => drawn,
+        None if show => {
+            let mesh = meshes.add(Mesh::new(PrimitiveTopology::TriangleList));
+            let mut bundle =
+                create_boid_mesh_bundle(assets.pipeline.clone(), mesh.clone(), Vec2::ZERO);
+            bundle.transform.translation.z = DebugOverlay::NeighborLines.z();
+            bundle.visible.is_visible = false;
+            // The lines show up from the next frame on, once they have actually been spawned
+            let entity = commands
+                .spawn_bundle(bundle)
+                .insert(CometTail::default())
+                .insert(Tint { color: lines.color })
+                .insert(Alarm::default())
+                .id();
+            *drawn = Some(NeighborLinesMesh { entity, mesh });
+            return;
+        }
+        None => return,
+    };
+
+    if let Ok(mut visible) = visibility.get_mut(drawn.entity) {
+        visible.is_visible = show;
+    }
+    if !show {
+        return;
+    }
+
+    let fade_distance = params.perception_radius().max(f32::EPSILON);
+    // Two boids that perceive each other only get one line between them
+    let mut connected = HashSet::new();
+    let mut wanted = 0;
+    let mut positions = Vec::new();
+    let mut colors = Vec::new();
+    for (boid, transform, neighbors) in boids.iter() {
+        let from = transform.translation.truncate();
+        for &neighbor in neighbors.0.iter() {
+            let pair = (boid.min(neighbor), boid.max(neighbor));
+            if !connected.insert(pair) {
+                continue;
+            }
+            let to = match boids.get(neighbor) {
+                Ok((_, transform, _)) => transform.translation.truncate(),
+                Err(_) => continue,
+            };
+            wanted += 1;
+            if wanted > lines.max_lines {
+                continue;
+            }
+
+            let side = (to - from).normalize_or_zero().perp() * lines.width / 2.0;
+            let brightness = 1.0 - (from.distance(to) / fade_distance).clamp(0.0, 1.0);
+            for corner in [from - side, to - side, to + side, from + side] {
+                positions.push([corner.x, corner.y, 0.0]);
+                colors.push(vertex_color(Color::rgb(brightness, brightness, brightness)));
+            }
+        }
+    }
+
+    if wanted > lines.max_lines && !*warned {
+        eprintln!(
+            "The flock has {} neighbor connections, only drawing the first {} of them",
+            wanted, lines.max_lines
+        );
+        *warned = true;
+    }
+
+    if let Some(mesh) = meshes.get_mut(&drawn.mesh) {
+        let vertices = positions.len() as u32;
+        let indices = (0..vertices)
+            .step_by(4)
+            .flat_map(|i| [i, i + 1, i + 2, i + 2, i + 3, i])
+            .collect();
+        mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        // The color comes from the Tint, the vertex colors only fade it
+        mesh.set_attribute(Mesh::ATTRIBUTE_COLOR, colors);
+        // The boid pipeline expects a comet tail, the lines just don't have one
+        mesh.set_attribute(ATTRIBUTE_TAIL, vec![0.0; vertices as usize]);
+        mesh.set_indices(Some(Indices::U32(indices)));
+    }
+}
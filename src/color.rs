@@ -0,0 +1,32 @@
+use bevy::{math::Vec4, render::color::Color};
+
+/// The value a mesh's vertex color attribute has to hold for it to show up as color
+///
+/// Colors are written in sRGB, which is what Color::rgb and friends take, but the shaders blend
+/// and output vertex colors as they are, in linear space, and the framebuffer converts them back
+/// to sRGB. Handing the shader sRGB values directly would come out washed out
+pub fn vertex_color(color: Color) -> [f32; 3] {
+    Vec4::from(color.as_rgba_linear()).truncate().to_array()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn srgb_colors_convert_to_linear() {
+        let linear = vertex_color(Color::rgb(1.0, 0.5, 0.02));
+        // Full and no intensity are the same either way, the rest darkens along the sRGB curve
+        // with its short straight stretch near black
+        let expected = [1.0, 0.214_041_14, 0.02 / 12.92];
+        for (channel, expected) in linear.iter().zip(expected) {
+            assert!(
+                (channel - expected).abs() < 1e-5,
+                "{:?} != {:?}",
+                linear,
+                expected
+            );
+        }
+        assert_eq!(vertex_color(Color::BLACK), [0.0, 0.0, 0.0]);
+    }
+}
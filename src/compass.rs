@@ -0,0 +1,248 @@
+use bevy::{
+    ecs::{
+        entity::Entity,
+        query::Without,
+        system::{Local, Query, Res, SystemParam},
+    },
+    math::{Quat, Vec2, Vec3},
+    prelude::Transform,
+    render::{camera::OrthographicProjection, color::Color, draw::Visible},
+    window::Windows,
+};
+use std::f32::consts::FRAC_PI_2;
+
+use crate::{
+    alarm::Alarm,
+    create_boid_mesh_bundle,
+    debug::{DebugOverlay, OverlayDrawing},
+    metrics::FlockMetrics,
+    shapes, CometTail, Tint,
+};
+
+/// Which corner of the window the compass (or anything else kept in a corner) sits in
+#[derive(Clone, Copy)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+impl Corner {
+    /// Where the center of something reaching extent out from it to either side goes to sit
+    /// margin in from this corner's edges, in pixels from the middle of a window of size
+    pub fn place(&self, size: Vec2, extent: Vec2, margin: f32) -> Vec2 {
+        let inset = (size / 2.0 - extent - Vec2::splat(margin)).max(Vec2::ZERO);
+        match self {
+            Corner::TopLeft => Vec2::new(-inset.x, inset.y),
+            Corner::TopRight => inset,
+            Corner::BottomLeft => -inset,
+            Corner::BottomRight => Vec2::new(inset.x, -inset.y),
+        }
+    }
+}
+
+/// The primary window and the camera looking into it, for whatever is drawn in pixels fixed to
+/// the window however the camera is panned, zoomed or rotated
+#[derive(SystemParam)]
+pub struct ScreenSpace<'a> {
+    windows: Res<'a, Windows>,
+    cameras: Query<'a, (&'static Transform, &'static OrthographicProjection)>,
+}
+
+/// How pixels out from the middle of the window map onto the world
+pub struct PixelMapping {
+    /// The size of the window in pixels
+    pub size: Vec2,
+    /// The camera's rotation, which turns everything laid out in pixels along with it
+    pub rotation: Quat,
+    /// How many world units a pixel covers
+    pub scale: f32,
+    center: Vec2,
+}
+
+impl PixelMapping {
+    /// The point in the world that's pixels out from the middle of the window
+    pub fn point(&self, pixels: Vec2) -> Vec2 {
+        self.center + (self.rotation * (pixels * self.scale).extend(0.0)).truncate()
+    }
+}
+
+impl<'a> ScreenSpace<'a> {
+    /// There's nowhere to put anything without a window and a camera looking into it
+    pub fn mapping(&self) -> Option<PixelMapping> {
+        let window = self.windows.get_primary()?;
+        let (camera, projection) = self.cameras.iter().next()?;
+        if window.width() <= 0.0 || window.height() <= 0.0 {
+            return None;
+        }
+        Some(PixelMapping {
+            size: Vec2::new(window.width(), window.height()),
+            rotation: camera.rotation,
+            scale: projection.scale,
+            center: camera.translation.truncate(),
+        })
+    }
+}
+
+/// A small compass fixed in a corner of the window however the camera is panned, zoomed or
+/// rotated, toggled with DebugOverlays::compass
+///
+/// Its axes point along the world's +x and +y, and an arrow points along the flock's average
+/// heading. The arrow is as long as the flock is polarized, reaching the ring when every boid
+/// heads the same way, and disappears when there's no heading at all
+pub struct Compass {
+    pub corner: Corner,
+    /// The compass's radius in pixels
+    pub size: f32,
+    /// How far in from the edges of the window the compass sits, in pixels
+    pub margin: f32,
+    /// How wide the axes and the heading arrow are, in pixels
+    pub thickness: f32,
+    pub ring_color: Color,
+    pub x_axis_color: Color,
+    pub y_axis_color: Color,
+    pub heading_color: Color,
+}
+
+impl Default for Compass {
+    fn default() -> Self {
+        Compass {
+            corner: Corner::BottomRight,
+            size: 40.0,
+            margin: 20.0,
+            thickness: 2.0,
+            ring_color: Color::GRAY,
+            x_axis_color: Color::RED,
+            y_axis_color: Color::GREEN,
+            heading_color: Color::WHITE,
+        }
+    }
+}
+
+impl Compass {
+    /// Where the compass's center is in pixels from the middle of a window of size
+    fn center(&self, size: Vec2) -> Vec2 {
+        self.corner.place(size, Vec2::splat(self.size), self.margin)
+    }
+}
+
+/// The entities the compass is drawn with
+pub struct CompassParts {
+    ring: Entity,
+    x_axis: Entity,
+    y_axis: Entity,
+    heading: Entity,
+}
+
+impl CompassParts {
+    fn all(&self) -> [Entity; 4] {
+        [self.ring, self.x_axis, self.y_axis, self.heading]
+    }
+}
+
+pub fn draw_compass(
+    drawing: OverlayDrawing,
+    compass: Res<Compass>,
+    metrics: Res<FlockMetrics>,
+    screen: ScreenSpace,
+    mut drawn: Local<Option<CompassParts>>,
+    mut parts: Query<(&mut Transform, &mut Visible, &mut Tint), Without<OrthographicProjection>>,
+) {
+    let OverlayDrawing {
+        overlays,
+        mut commands,
+        assets,
+        mut meshes,
+    } = drawing;
+    let show = overlays.shows(DebugOverlay::Compass);
+    let drawn = match drawn.as_ref() {
+        Some(drawn) => drawn,
+        None if show => {
+            let ring = meshes.add(shapes::create_ring_mesh(32, 0.05));
+            let arrow = meshes.add(shapes::create_arrow_mesh());
+            let mut spawn = |mesh, color| {
+                let mut bundle = create_boid_mesh_bundle(assets.pipeline.clone(), mesh, Vec2::ZERO);
+                bundle.visible.is_visible = false;
+                commands
+                    .spawn_bundle(bundle)
+                    .insert(CometTail::default())
+                    .insert(Tint { color })
+                    .insert(Alarm::default())
+                    .id()
+            };
+            // The compass shows up from the next frame on, once it has actually been spawned
+            *drawn = Some(CompassParts {
+                ring: spawn(ring, compass.ring_color),
+                x_axis: spawn(arrow.clone(), compass.x_axis_color),
+                y_axis: spawn(arrow.clone(), compass.y_axis_color),
+                heading: spawn(arrow, compass.heading_color),
+            });
+            return;
+        }
+        None => return,
+    };
+
+    let mapping = match screen.mapping() {
+        Some(mapping) if show => mapping,
+        _ => {
+            for part in drawn.all() {
+                if let Ok((_, mut visible, _)) = parts.get_mut(part) {
+                    visible.is_visible = false;
+                }
+            }
+            return;
+        }
+    };
+
+    // Everything is laid out in pixels, the camera's rotation turns the window's corner along
+    // with it while the compass itself keeps pointing along the world's axes
+    let scale = mapping.scale;
+    let center = mapping.point(compass.center(mapping.size));
+    let translation = center.extend(DebugOverlay::Compass.z());
+    let radius = compass.size * scale;
+    let width = compass.thickness * scale;
+    let heading = metrics.heading;
+    let arrow = |angle: f32, length: f32| Transform {
+        translation,
+        rotation: Quat::from_rotation_z(angle),
+        scale: Vec3::new(length, width, 1.0),
+    };
+
+    for (part, transform, color, visible) in [
+        (
+            drawn.ring,
+            Transform {
+                translation,
+                rotation: Quat::IDENTITY,
+                scale: Vec3::new(radius, radius, 1.0),
+            },
+            compass.ring_color,
+            true,
+        ),
+        (drawn.x_axis, arrow(0.0, radius), compass.x_axis_color, true),
+        (
+            drawn.y_axis,
+            arrow(FRAC_PI_2, radius),
+            compass.y_axis_color,
+            true,
+        ),
+        (
+            drawn.heading,
+            // Drawn over the axes, it'd often be hiding underneath one otherwise
+            Transform {
+                translation: translation + Vec3::Z * 0.5,
+                ..arrow(heading.y.atan2(heading.x), heading.length() * radius)
+            },
+            compass.heading_color,
+            // A flock with no heading has nothing to point at
+            heading.length() > f32::EPSILON,
+        ),
+    ] {
+        if let Ok((mut part_transform, mut part_visible, mut tint)) = parts.get_mut(part) {
+            *part_transform = transform;
+            part_visible.is_visible = visible;
+            tint.color = color;
+        }
+    }
+}
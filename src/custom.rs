@@ -0,0 +1,170 @@
+use bevy::{
+    ecs::{
+        query::Without,
+        system::{Query, Res},
+    },
+    math::Vec2,
+    prelude::Transform,
+};
+
+use crate::{
+    flocking::Neighbors, grid::SpatialGrid, sleep::Sleeping, stagger::SteeringStagger,
+    BehaviorToggles, Force, SpawnIndex, Velocity,
+};
+
+/// What a custom steering function gets to see of a boid
+pub struct BoidState<'a> {
+    pub position: Vec2,
+    pub velocity: Vec2,
+    /// The boid's Velocity.max
+    pub max_speed: f32,
+    /// The boid's Force.max, whatever the function returns is cut down to this
+    pub max_force: f32,
+    /// Every boid it perceives, nearest first, see Neighbors
+    pub neighbors: &'a [NeighborState],
+}
+
+pub struct NeighborState {
+    /// Where the neighbor is as seen from the boid, which across a wrapping edge is just past the
+    /// edge rather than all the way on the other side of the world
+    pub position: Vec2,
+    pub velocity: Vec2,
+}
+
+/// The signature of a custom steering function, it gets a boid's state and returns the force it
+/// should be pushed with on top of every other behavior
+pub type SteeringFn = dyn Fn(&BoidState) -> Vec2 + Send + Sync;
+
+/// A steering behavior of your own, run for every boid right after all of the built in ones
+/// without having to touch any of them, e.g. `CustomSteering::new(spiral_inward)`
+#[derive(Default)]
+pub struct CustomSteering(Option<Box<SteeringFn>>);
+
+impl CustomSteering {
+    pub fn new(steer: impl Fn(&BoidState) -> Vec2 + Send + Sync + 'static) -> Self {
+        CustomSteering(Some(Box::new(steer)))
+    }
+}
+
+/// An example of a custom steering function, boids circle clockwise around the origin
+/// while slowly closing in on it, falling in with whoever is right next to them so they spiral
+/// in along lanes rather than cutting across each other
+pub fn spiral_inward(boid: &BoidState) -> Vec2 {
+    let inward = -boid.position.normalize_or_zero();
+    // Mostly around the origin and only a little towards it
+    let around = (inward.perp() * 0.9 + inward * 0.3).normalize_or_zero() * boid.max_speed;
+    let (pace, count) = boid
+        .neighbors
+        .iter()
+        .filter(|neighbor| neighbor.position.distance(boid.position) < 20.0)
+        .fold((Vec2::ZERO, 0), |(pace, count), neighbor| {
+            (pace + neighbor.velocity, count + 1)
+        });
+    let desired_velocity = if count > 0 {
+        (around + pace / count as f32) / 2.0
+    } else {
+        around
+    };
+    (desired_velocity - boid.velocity).clamp_length_max(boid.max_force)
+}
+
+pub fn custom_steering(
+    toggles: Res<BehaviorToggles>,
+    custom: Res<CustomSteering>,
+    stagger: Res<SteeringStagger>,
+    grid: Res<SpatialGrid>,
+    mut query: Query<
+        (&mut Force, &Velocity, &Transform, &Neighbors, &SpawnIndex),
+        Without<Sleeping>,
+    >,
+    others: Query<(&Transform, &Velocity)>,
+) {
+    let steer = match &custom.0 {
+        Some(steer) if !toggles.all_off() => steer,
+        _ => return,
+    };
+    let metric = grid.metric();
+    // Reused between boids so there's only ever the one allocation
+    let mut neighbors = Vec::new();
+    for (mut force, velocity, transform, boid_neighbors, index) in query.iter_mut() {
+        if !stagger.is_due(index) {
+            continue;
+        }
+        let position = transform.translation.truncate();
+        neighbors.clear();
+        neighbors.extend(boid_neighbors.0.iter().filter_map(|&neighbor| {
+            let (other, other_velocity) = others.get(neighbor).ok()?;
+            Some(NeighborState {
+                position: position + metric.offset(position, other.translation.truncate()),
+                velocity: other_velocity.vector,
+            })
+        }));
+
+        let force = force.as_mut();
+        let push = steer(&BoidState {
+            position,
+            velocity: velocity.vector,
+            max_speed: velocity.max,
+            max_force: force.max,
+            neighbors: &neighbors,
+        });
+        // A NaN would get into the boid's velocity and position and never leave
+        if push.is_finite() {
+            force.vector += push.clamp_length_max(force.max);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_world::test_world;
+    use bevy::ecs::{
+        entity::Entity,
+        schedule::{Stage, SystemStage},
+        world::World,
+    };
+
+    fn spawn_boid(world: &mut World, position: Vec2, velocity: Vec2, index: u64) -> Entity {
+        world
+            .spawn()
+            .insert_bundle((
+                Transform::from_xyz(position.x, position.y, 0.0),
+                Velocity {
+                    vector: velocity,
+                    max: 1.0,
+                },
+                Force {
+                    vector: Vec2::ZERO,
+                    max: 0.5,
+                },
+                Neighbors::default(),
+                SpawnIndex(index),
+            ))
+            .id()
+    }
+
+    #[test]
+    fn custom_steering_sees_each_boids_neighbors() {
+        let mut world = test_world(0);
+        // Heads for wherever its nearest neighbor is going to be next
+        world.insert_resource(CustomSteering::new(|boid| match boid.neighbors.first() {
+            Some(neighbor) => neighbor.position + neighbor.velocity - boid.position,
+            None => Vec2::ZERO,
+        }));
+        let boid = spawn_boid(&mut world, Vec2::ZERO, Vec2::ZERO, 0);
+        let neighbor = spawn_boid(&mut world, Vec2::new(0.2, 0.0), Vec2::new(0.0, 0.1), 1);
+        let loner = spawn_boid(&mut world, Vec2::new(100.0, 0.0), Vec2::ZERO, 2);
+        world.get_mut::<Neighbors>(boid).unwrap().0 = vec![neighbor];
+
+        SystemStage::single(custom_steering).run(&mut world);
+
+        let force = world.get::<Force>(boid).unwrap().vector;
+        assert!((force - Vec2::new(0.2, 0.1)).length() < 1e-6, "{}", force);
+        // Whatever a function asks for is still cut down to the boid's Force.max
+        world.get_mut::<Neighbors>(loner).unwrap().0 = vec![boid];
+        SystemStage::single(custom_steering).run(&mut world);
+        let force = world.get::<Force>(loner).unwrap().vector;
+        assert!((force - Vec2::new(-0.5, 0.0)).length() < 1e-6, "{}", force);
+    }
+}
@@ -0,0 +1,113 @@
+use bevy::{
+    asset::Assets,
+    ecs::system::{Commands, Res, ResMut, SystemParam},
+    input::{keyboard::KeyCode, Input},
+    render::mesh::Mesh,
+};
+
+use crate::BoidAssets;
+
+/// Boids are drawn at a z of 0, every overlay gets its own slot in a band well above them so
+/// overlays never end up underneath boids or fighting each other over which draws on top
+const DEBUG_LAYER_Z: f32 = 100.0;
+
+/// The debug visualizations that can be drawn over the flock
+#[derive(Clone, Copy)]
+pub enum DebugOverlay {
+    /// The steering force acting on every boid, see force_arrows
+    ForceArrows,
+    /// The ring showing how far CursorReach reaches around the cursor
+    CursorReach,
+    /// The lines between every boid and its neighbors, see chatter
+    NeighborLines,
+    /// The separation, alignment and cohesion radii around the Selected boid
+    PerceptionRadii,
+    /// The field of view in front of the Selected boid
+    VisionCone,
+    /// The world's axes and the flock's heading in a corner of the window, see compass
+    Compass,
+    /// How calm or frantic the flock is, for a little while after the mood changes, see mood
+    MoodGauge,
+}
+
+impl DebugOverlay {
+    /// The z an overlay is drawn at, later overlays draw over earlier ones
+    pub fn z(&self) -> f32 {
+        DEBUG_LAYER_Z + *self as u32 as f32
+    }
+}
+
+/// Which debug overlays are drawn
+///
+/// Each overlay has its own toggle while enabled hides all of them at once without forgetting
+/// which ones were on, handy for a clean recording. The backquote key toggles enabled, G the force
+/// arrows, R the cursor's reach, N the neighbor lines, P the Selected boid's perception radii, K
+/// its vision cone, M the compass and H the mood gauge
+pub struct DebugOverlays {
+    pub enabled: bool,
+    pub force_arrows: bool,
+    pub cursor_reach: bool,
+    pub neighbor_lines: bool,
+    pub perception_radii: bool,
+    pub vision_cone: bool,
+    pub compass: bool,
+    pub mood_gauge: bool,
+}
+
+impl Default for DebugOverlays {
+    fn default() -> Self {
+        DebugOverlays {
+            enabled: true,
+            force_arrows: false,
+            cursor_reach: false,
+            neighbor_lines: false,
+            perception_radii: false,
+            vision_cone: false,
+            compass: false,
+            mood_gauge: true,
+        }
+    }
+}
+
+impl DebugOverlays {
+    /// Whether an overlay should currently be drawn
+    pub fn shows(&self, overlay: DebugOverlay) -> bool {
+        self.enabled
+            && match overlay {
+                DebugOverlay::ForceArrows => self.force_arrows,
+                DebugOverlay::CursorReach => self.cursor_reach,
+                DebugOverlay::NeighborLines => self.neighbor_lines,
+                DebugOverlay::PerceptionRadii => self.perception_radii,
+                DebugOverlay::VisionCone => self.vision_cone,
+                DebugOverlay::Compass => self.compass,
+                DebugOverlay::MoodGauge => self.mood_gauge,
+            }
+    }
+}
+
+/// Everything an overlay needs to know whether it's shown and to spawn what it's drawn with
+#[derive(SystemParam)]
+pub struct OverlayDrawing<'a> {
+    pub overlays: Res<'a, DebugOverlays>,
+    pub commands: Commands<'a>,
+    pub assets: Res<'a, BoidAssets>,
+    pub meshes: ResMut<'a, Assets<Mesh>>,
+}
+
+pub fn toggle_debug_overlays(keys: Res<Input<KeyCode>>, mut overlays: ResMut<DebugOverlays>) {
+    let overlays = overlays.as_mut();
+    for (key, toggle) in [
+        (KeyCode::Grave, &mut overlays.enabled),
+        (KeyCode::G, &mut overlays.force_arrows),
+        (KeyCode::R, &mut overlays.cursor_reach),
+        (KeyCode::N, &mut overlays.neighbor_lines),
+        (KeyCode::P, &mut overlays.perception_radii),
+        (KeyCode::K, &mut overlays.vision_cone),
+        (KeyCode::M, &mut overlays.compass),
+        (KeyCode::H, &mut overlays.mood_gauge),
+    ] {
+        if keys.just_pressed(key) {
+            *toggle = !*toggle;
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! Q16.16 fixed-point math for moving boids, compiled in with the `fixed-point` feature
+//!
+//! With the feature on apply_force integrates velocities and positions using nothing but integer
+//! math. Values are rounded to the nearest 1/65536 on the way in and the range is about ±32768,
+//! plenty for a world measured in pixels
+//!
+//! The guarantee this gives is narrower than "the same run on every machine". Boids still keep
+//! their positions in Transform and their velocities in Velocity, both floats, so the fixed-point
+//! values only live for the length of one apply_force. Converting to and from f32 is exact IEEE
+//! arithmetic, so what is guaranteed is that one integration step turns the same float force,
+//! velocity and position into the same float velocity and position on every machine. Everything
+//! around it is still floats: steering (with trig functions that can differ in their last bit
+//! between platforms), the edges, obstacles and separate_positions nudging positions, and the
+//! rotation update_boids gives boids. A whole run only comes out the same everywhere as far as
+//! those do, which in practice is usually but not always
+//!
+//! SpeedCapMode::Soft needs tanh, which has no fixed-point version here, so with the feature on
+//! it caps speeds exactly like SpeedCapMode::Hard does
+
+use bevy::math::Vec2;
+use std::ops::{Add, AddAssign, Div, Mul, Sub};
+
+const FRACTION_BITS: u32 = 16;
+const ONE: i64 = 1 << FRACTION_BITS;
+
+/// A Q16.16 fixed-point number, 16 bits of integer part and 16 bits of fraction
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub struct Fixed(i32);
+
+impl Fixed {
+    pub const ZERO: Fixed = Fixed(0);
+
+    /// The nearest fixed-point number, values out of range saturate
+    pub fn from_f32(value: f32) -> Self {
+        Fixed((value * ONE as f32).round() as i32)
+    }
+
+    pub fn to_f32(self) -> f32 {
+        self.0 as f32 / ONE as f32
+    }
+}
+
+fn saturate(raw: i64) -> i32 {
+    raw.clamp(i32::MIN as i64, i32::MAX as i64) as i32
+}
+
+/// The largest integer whose square is at most value, worked out bit by bit
+fn isqrt(value: u64) -> u64 {
+    let mut remainder = value;
+    let mut root = 0;
+    let mut bit = 1 << 62;
+    while bit > value {
+        bit >>= 2;
+    }
+    while bit != 0 {
+        if remainder >= root + bit {
+            remainder -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+    root
+}
+
+impl Add for Fixed {
+    type Output = Fixed;
+    fn add(self, other: Fixed) -> Fixed {
+        Fixed(self.0.saturating_add(other.0))
+    }
+}
+
+impl Sub for Fixed {
+    type Output = Fixed;
+    fn sub(self, other: Fixed) -> Fixed {
+        Fixed(self.0.saturating_sub(other.0))
+    }
+}
+
+impl Mul for Fixed {
+    type Output = Fixed;
+    fn mul(self, other: Fixed) -> Fixed {
+        Fixed(saturate((self.0 as i64 * other.0 as i64) >> FRACTION_BITS))
+    }
+}
+
+/// Dividing by zero saturates instead of panicking
+impl Div for Fixed {
+    type Output = Fixed;
+    fn div(self, other: Fixed) -> Fixed {
+        if other.0 == 0 {
+            return Fixed(if self.0 < 0 { i32::MIN } else { i32::MAX });
+        }
+        Fixed(saturate(
+            ((self.0 as i64) << FRACTION_BITS) / other.0 as i64,
+        ))
+    }
+}
+
+/// A Vec2 made of Fixed
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct FixedVec2 {
+    pub x: Fixed,
+    pub y: Fixed,
+}
+
+impl FixedVec2 {
+    pub fn from_vec2(vector: Vec2) -> Self {
+        FixedVec2 {
+            x: Fixed::from_f32(vector.x),
+            y: Fixed::from_f32(vector.y),
+        }
+    }
+
+    pub fn to_vec2(self) -> Vec2 {
+        Vec2::new(self.x.to_f32(), self.y.to_f32())
+    }
+
+    pub fn length(self) -> Fixed {
+        // The squares are Q32.32, and the square root of a Q32.32 is a Q16.16
+        let (x, y) = (self.x.0 as i64, self.y.0 as i64);
+        Fixed(saturate(isqrt((x * x + y * y) as u64) as i64))
+    }
+
+    /// The same vector with its length changed to length, a zero vector stays zero
+    pub fn with_length(self, length: Fixed) -> Self {
+        let current = self.length();
+        if current == Fixed::ZERO {
+            return self;
+        }
+        let scale = length / current;
+        self * scale
+    }
+}
+
+impl Add for FixedVec2 {
+    type Output = FixedVec2;
+    fn add(self, other: FixedVec2) -> FixedVec2 {
+        FixedVec2 {
+            x: self.x + other.x,
+            y: self.y + other.y,
+        }
+    }
+}
+
+impl AddAssign for FixedVec2 {
+    fn add_assign(&mut self, other: FixedVec2) {
+        *self = *self + other;
+    }
+}
+
+impl Mul<Fixed> for FixedVec2 {
+    type Output = FixedVec2;
+    fn mul(self, scale: Fixed) -> FixedVec2 {
+        FixedVec2 {
+            x: self.x * scale,
+            y: self.y * scale,
+        }
+    }
+}
+
+/// The fastest and slowest a boid can go, max is None when its speed isn't capped
+pub struct SpeedLimits {
+    pub max: Option<f32>,
+    pub min: f32,
+}
+
+/// Moves a boid through substeps steps of acceleration, capping its speed to the limits after
+/// every step the same as apply_force does with floats, and handing its position and velocity
+/// to collide after every step it moves. Returns the boid's new position and velocity
+pub fn integrate(
+    position: Vec2,
+    velocity: Vec2,
+    acceleration: Vec2,
+    substeps: u32,
+    limits: SpeedLimits,
+    moving: bool,
+    mut collide: impl FnMut(&mut Vec2, &mut Vec2) -> bool,
+) -> (Vec2, Vec2) {
+    let step = Fixed::from_f32(1.0 / substeps as f32);
+    let acceleration = FixedVec2::from_vec2(acceleration) * step;
+    let max_speed = limits.max.map(Fixed::from_f32);
+    let min_speed = Fixed::from_f32(limits.min);
+    let mut position = FixedVec2::from_vec2(position);
+    let mut velocity = FixedVec2::from_vec2(velocity);
+
+    for _ in 0..substeps {
+        velocity += acceleration;
+        let speed = velocity.length();
+        if let Some(max_speed) = max_speed {
+            if speed > max_speed {
+                velocity = velocity.with_length(max_speed);
+            }
+        }
+        if speed > Fixed::ZERO && speed < min_speed {
+            velocity = velocity.with_length(min_speed);
+        }
+        if moving {
+            position += velocity * step;
+            // Collisions are worked out in floats, the boid only picks up their rounding when it
+            // actually hits something
+            let (mut collided_position, mut collided_velocity) =
+                (position.to_vec2(), velocity.to_vec2());
+            if collide(&mut collided_position, &mut collided_velocity) {
+                position = FixedVec2::from_vec2(collided_position);
+                velocity = FixedVec2::from_vec2(collided_velocity);
+            }
+        }
+    }
+
+    (position.to_vec2(), velocity.to_vec2())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The same steps apply_force takes without the feature, with a hard speed cap
+    fn integrate_float(
+        mut position: Vec2,
+        mut velocity: Vec2,
+        acceleration: Vec2,
+        substeps: u32,
+        max_speed: f32,
+        min_speed: f32,
+    ) -> (Vec2, Vec2) {
+        let step = 1.0 / substeps as f32;
+        for _ in 0..substeps {
+            velocity += acceleration * step;
+            velocity = Vec2::clamp_length_max(velocity, max_speed);
+            let speed = velocity.length();
+            if speed > 0.0 && speed < min_speed {
+                velocity *= min_speed / speed;
+            }
+            position += velocity * step;
+        }
+        (position, velocity)
+    }
+
+    #[test]
+    fn fixed_point_stays_close_to_floats() {
+        let (mut fixed_position, mut fixed_velocity) =
+            (Vec2::new(-120.0, 35.5), Vec2::new(1.5, -0.75));
+        let (mut float_position, mut float_velocity) = (fixed_position, fixed_velocity);
+        for frame in 0..200 {
+            // A force that keeps turning so the speed cap and the minimum speed both come up
+            let angle = frame as f32 * 0.1;
+            let acceleration = Vec2::new(angle.cos(), angle.sin()) * 0.3;
+            let limits = SpeedLimits {
+                max: Some(4.0),
+                min: 0.5,
+            };
+            let (position, velocity) = integrate(
+                fixed_position,
+                fixed_velocity,
+                acceleration,
+                4,
+                limits,
+                true,
+                |_, _| false,
+            );
+            fixed_position = position;
+            fixed_velocity = velocity;
+            let (position, velocity) =
+                integrate_float(float_position, float_velocity, acceleration, 4, 4.0, 0.5);
+            float_position = position;
+            float_velocity = velocity;
+
+            assert!(
+                fixed_velocity.distance(float_velocity) < 0.01,
+                "velocities drifted apart on frame {}: {} and {}",
+                frame,
+                fixed_velocity,
+                float_velocity
+            );
+            assert!(
+                fixed_position.distance(float_position) < 0.5,
+                "positions drifted apart on frame {}: {} and {}",
+                frame,
+                fixed_position,
+                float_position
+            );
+        }
+    }
+
+    #[test]
+    fn dividing_by_zero_saturates() {
+        assert_eq!(Fixed::from_f32(3.0) / Fixed::ZERO, Fixed(i32::MAX));
+        assert_eq!(Fixed::from_f32(-3.0) / Fixed::ZERO, Fixed(i32::MIN));
+        assert_eq!(
+            Fixed::from_f32(3.0) / Fixed::from_f32(2.0),
+            Fixed::from_f32(1.5)
+        );
+    }
+}
@@ -0,0 +1,1904 @@
+use bevy::{
+    core::Time,
+    ecs::{
+        component::Component,
+        entity::Entity,
+        query::{With, Without},
+        system::{Local, Query, Res, ResMut, SystemParam},
+    },
+    input::{keyboard::KeyCode, Input},
+    math::Vec2,
+    prelude::Transform,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    cmp::Ordering,
+    collections::{BinaryHeap, HashMap},
+    f32::consts::TAU,
+    num::FpCategory,
+};
+
+use crate::{
+    flocks::FlockId,
+    force_log::SteeringTotals,
+    grid::{query_neighbors, SpatialGrid},
+    inspect::Pinned,
+    metrics::FlockMetrics,
+    seek_force,
+    sleep::Sleeping,
+    stagger::SteeringStagger,
+    BehaviorToggles, DeterministicMode, Force, SimRng, SpawnIndex, Velocity,
+};
+
+/// Parameters shared by the flocking behaviors of the whole flock
+#[derive(Clone)]
+pub struct FlockingParams {
+    /// How close another boid has to be for a boid with Separation to steer away from it
+    pub separation_radius: f32,
+    /// How close another boid has to be for a boid with Alignment to match its heading
+    pub alignment_radius: f32,
+    /// Boids with Alignment only match the heading of neighbors faster than themselves, chasing
+    /// whoever is ahead rather than settling on the average, so a burst of speed at one end of
+    /// the flock ripples through to the other end like a wave
+    ///
+    /// A boid with no faster neighbors doesn't align at all
+    pub align_with_faster_only: bool,
+    /// How close two boids have to be to each other to be considered part of the same flock
+    pub cohesion_radius: f32,
+    /// How the pull towards the center of a boid's neighbors changes with the distance to it
+    pub cohesion_falloff: Falloff,
+    /// Real flocking animals only keep track of a handful of their closest flockmates rather
+    /// than of everyone around them, a boid only ever perceives up to this many neighbors
+    pub max_perceived_neighbors: usize,
+    /// How far off, from 0 to 1, a boid can misjudge its neighbors, a boid sees a neighbor up to
+    /// this fraction of the distance between them away from where it really is and moving up to
+    /// this fraction of its speed off from how it really moves
+    ///
+    /// A little of this loosens up the flock's otherwise perfectly synchronized turns, it still
+    /// holds together but is measurably less polarized
+    pub perception_noise: f32,
+    /// How wide a boid's field of view is around its heading, boids in the blind spot behind it
+    /// aren't perceived at all. 360 sees all the way around
+    pub fov_degrees: f32,
+}
+
+impl FlockingParams {
+    /// The furthest any of the flocking behaviors can see
+    pub fn perception_radius(&self) -> f32 {
+        self.separation_radius
+            .max(self.alignment_radius)
+            .max(self.cohesion_radius)
+    }
+
+    /// Blends between two sets of parameters, t of 0 is all self and t of 1 is all other
+    ///
+    /// The falloff and align_with_faster_only can't be blended so they switch over halfway through
+    pub fn lerp(&self, other: &FlockingParams, t: f32) -> FlockingParams {
+        let lerp = |from: f32, to: f32| from + (to - from) * t;
+        FlockingParams {
+            separation_radius: lerp(self.separation_radius, other.separation_radius),
+            alignment_radius: lerp(self.alignment_radius, other.alignment_radius),
+            align_with_faster_only: if t < 0.5 {
+                self.align_with_faster_only
+            } else {
+                other.align_with_faster_only
+            },
+            cohesion_radius: lerp(self.cohesion_radius, other.cohesion_radius),
+            cohesion_falloff: if t < 0.5 {
+                self.cohesion_falloff
+            } else {
+                other.cohesion_falloff
+            },
+            max_perceived_neighbors: lerp(
+                self.max_perceived_neighbors as f32,
+                other.max_perceived_neighbors as f32,
+            )
+            .round() as usize,
+            perception_noise: lerp(self.perception_noise, other.perception_noise),
+            fov_degrees: lerp(self.fov_degrees, other.fov_degrees),
+        }
+    }
+}
+
+impl Default for FlockingParams {
+    fn default() -> Self {
+        FlockingParams {
+            separation_radius: 25.0,
+            alignment_radius: 50.0,
+            align_with_faster_only: false,
+            cohesion_radius: 50.0,
+            cohesion_falloff: Falloff::Linear,
+            max_perceived_neighbors: 7,
+            perception_noise: 0.0,
+            fov_degrees: 360.0,
+        }
+    }
+}
+
+/// How separate works out how hard each neighbor pushes, both come out to the same push of
+/// 1 / distance away from the neighbor up to float rounding
+#[derive(Clone, Copy)]
+pub enum SeparationMath {
+    /// Normalizes every offset and divides it by its length, taking two square roots per neighbor
+    Exact,
+    /// Divides every offset by its squared length and compares squared distances against the
+    /// squared radius, which skips the square roots in the per neighbor loop entirely
+    Squared,
+}
+
+impl Default for SeparationMath {
+    fn default() -> Self {
+        SeparationMath::Exact
+    }
+}
+
+/// How much say a neighbor gets in alignment and cohesion depending on how far away it is,
+/// relative to the behavior's radius. Neighbors are averaged by these weights, so closer
+/// neighbors steering a boid more makes for a flock that responds more to what's right around
+/// each boid
+///
+/// This only changes how neighbors are averaged and not how hard a boid steers, a boid whose
+/// neighbors all get a weight of 0 doesn't steer at all though
+#[derive(Clone, Copy)]
+pub enum NeighborWeighting {
+    /// Every neighbor counts the same
+    Uniform,
+    /// Falls off from 1 right on top of the boid to 0 at the radius
+    Linear,
+    /// A bell curve that's 1 right on top of the boid, width is its standard deviation as a
+    /// fraction of the radius
+    Gaussian { width: f32 },
+}
+
+impl Default for NeighborWeighting {
+    fn default() -> Self {
+        NeighborWeighting::Uniform
+    }
+}
+
+impl NeighborWeighting {
+    pub fn weight(&self, distance: f32, radius: f32) -> f32 {
+        let t = distance / radius.max(f32::EPSILON);
+        match *self {
+            NeighborWeighting::Uniform => 1.0,
+            NeighborWeighting::Linear => (1.0 - t).clamp(0.0, 1.0),
+            NeighborWeighting::Gaussian { width } => {
+                let t = t / width.max(f32::EPSILON);
+                (-0.5 * t * t).exp()
+            }
+        }
+    }
+}
+
+/// Fast boids need more room to react, with this on each boid's separation radius grows with
+/// its speed instead of being separation_radius, from min_radius for a boid standing still up to
+/// max_radius for one flying at its Velocity.max
+///
+/// Neighbors are gathered out to max_radius so even the fastest boid sees everyone it should
+/// keep away from
+pub struct SpeedScaledSeparation {
+    pub enabled: bool,
+    pub min_radius: f32,
+    pub max_radius: f32,
+}
+
+impl Default for SpeedScaledSeparation {
+    fn default() -> Self {
+        SpeedScaledSeparation {
+            enabled: false,
+            min_radius: 15.0,
+            max_radius: 40.0,
+        }
+    }
+}
+
+impl SpeedScaledSeparation {
+    /// The separation radius of a boid with velocity
+    pub fn radius(&self, params: &FlockingParams, velocity: &Velocity) -> f32 {
+        if !self.enabled {
+            return params.separation_radius;
+        }
+        let speed = (velocity.vector.length() / velocity.max.max(f32::EPSILON)).clamp(0.0, 1.0);
+        self.min_radius + (self.max_radius - self.min_radius) * speed
+    }
+}
+
+/// Named FlockingParams tuned for a few recognizable kinds of flocks, picked with F1 to F3
+#[derive(Clone, Copy)]
+pub enum FlockingPreset {
+    /// Tight, fast turning schools where everyone keeps close and swims the same way, small
+    /// separation and wide alignment make for a high polarization and a small spread
+    SchoolingFish,
+    /// A loose buzzing cloud, boids barely look at each other's heading and are only reeled back
+    /// once they drift far out, giving a low polarization around a roughly stable spread
+    SwarmingGnats,
+    /// Wide, evenly spaced flocks that hold a heading for a long time, large separation and very
+    /// wide alignment keep polarization high while the flock spreads out in long lines
+    MigratingBirds,
+}
+
+impl FlockingPreset {
+    /// The FlockingParams this preset is made of
+    pub fn params(&self) -> FlockingParams {
+        match self {
+            FlockingPreset::SchoolingFish => FlockingParams {
+                separation_radius: 15.0,
+                alignment_radius: 60.0,
+                align_with_faster_only: false,
+                cohesion_radius: 60.0,
+                cohesion_falloff: Falloff::Linear,
+                max_perceived_neighbors: 7,
+                perception_noise: 0.0,
+                fov_degrees: 360.0,
+            },
+            FlockingPreset::SwarmingGnats => FlockingParams {
+                separation_radius: 10.0,
+                alignment_radius: 10.0,
+                align_with_faster_only: false,
+                cohesion_radius: 80.0,
+                cohesion_falloff: Falloff::Quadratic,
+                max_perceived_neighbors: 12,
+                perception_noise: 0.0,
+                fov_degrees: 360.0,
+            },
+            FlockingPreset::MigratingBirds => FlockingParams {
+                separation_radius: 30.0,
+                alignment_radius: 100.0,
+                align_with_faster_only: false,
+                cohesion_radius: 40.0,
+                cohesion_falloff: Falloff::Inverse,
+                max_perceived_neighbors: 7,
+                perception_noise: 0.0,
+                fov_degrees: 360.0,
+            },
+        }
+    }
+}
+
+/// The preset currently picked, if any, and how long FlockingParams take to blend over to it
+pub struct PresetSelection {
+    pub preset: Option<FlockingPreset>,
+    /// Seconds to blend from the current FlockingParams to the preset's, 0 switches instantly
+    pub transition: f32,
+}
+
+impl Default for PresetSelection {
+    fn default() -> Self {
+        PresetSelection {
+            preset: None,
+            transition: 2.0,
+        }
+    }
+}
+
+pub fn select_flocking_preset(keys: Res<Input<KeyCode>>, mut selection: ResMut<PresetSelection>) {
+    for (key, preset) in [
+        (KeyCode::F1, FlockingPreset::SchoolingFish),
+        (KeyCode::F2, FlockingPreset::SwarmingGnats),
+        (KeyCode::F3, FlockingPreset::MigratingBirds),
+    ] {
+        if keys.just_pressed(key) {
+            selection.preset = Some(preset);
+        }
+    }
+}
+
+/// A blend from the parameters the flock had when a preset was picked to the preset's
+pub struct PresetTransition {
+    from: FlockingParams,
+    to: FlockingParams,
+    elapsed: f32,
+}
+
+pub fn apply_flocking_preset(
+    time: Res<Time>,
+    selection: Res<PresetSelection>,
+    mut params: ResMut<FlockingParams>,
+    mut transition: Local<Option<PresetTransition>>,
+) {
+    if selection.is_changed() {
+        if let Some(preset) = selection.preset {
+            *transition = Some(PresetTransition {
+                from: params.clone(),
+                to: preset.params(),
+                elapsed: 0.0,
+            });
+        }
+    }
+
+    let done = match transition.as_mut() {
+        Some(transition) => {
+            transition.elapsed += time.delta_seconds();
+            let t = if selection.transition > 0.0 {
+                (transition.elapsed / selection.transition).min(1.0)
+            } else {
+                1.0
+            };
+            *params = transition.from.lerp(&transition.to, t);
+            t >= 1.0
+        }
+        None => return,
+    };
+    if done {
+        *transition = None;
+    }
+}
+
+/// Shifts the flock from exploring to exploiting over the course of a run, for a flock that's
+/// searching for something
+///
+/// Boids start out barely paying attention to each other so they scatter over a wide area, then
+/// over duration seconds the FlockingParams and every boid's Cohesion interest blend over to
+/// exploit, which pulls the flock in around wherever it found its way to. Unlike the presets this
+/// keeps going for the whole duration, and while it's enabled it overrides them
+pub struct ExploreSchedule {
+    pub enabled: bool,
+    pub duration: f32,
+    pub explore: FlockingParams,
+    pub exploit: FlockingParams,
+    pub explore_cohesion: f32,
+    pub exploit_cohesion: f32,
+}
+
+impl Default for ExploreSchedule {
+    fn default() -> Self {
+        ExploreSchedule {
+            enabled: false,
+            duration: 60.0,
+            explore: FlockingParams {
+                separation_radius: 30.0,
+                alignment_radius: 20.0,
+                align_with_faster_only: false,
+                cohesion_radius: 20.0,
+                cohesion_falloff: Falloff::Linear,
+                max_perceived_neighbors: 4,
+                perception_noise: 0.0,
+                fov_degrees: 360.0,
+            },
+            exploit: FlockingParams {
+                separation_radius: 15.0,
+                alignment_radius: 60.0,
+                align_with_faster_only: false,
+                cohesion_radius: 120.0,
+                cohesion_falloff: Falloff::Linear,
+                max_perceived_neighbors: 12,
+                perception_noise: 0.0,
+                fov_degrees: 360.0,
+            },
+            explore_cohesion: 0.2,
+            exploit_cohesion: 1.0,
+        }
+    }
+}
+
+/// Blends the flock along the ExploreSchedule, how far along it is goes into
+/// FlockMetrics::exploitation
+pub fn follow_explore_schedule(
+    time: Res<Time>,
+    schedule: Res<ExploreSchedule>,
+    mut params: ResMut<FlockingParams>,
+    mut metrics: ResMut<FlockMetrics>,
+    // Seconds since the schedule was enabled
+    mut elapsed: Local<f32>,
+    mut boids: Query<&mut Cohesion>,
+) {
+    if !schedule.enabled {
+        *elapsed = 0.0;
+        metrics.exploitation = 0.0;
+        return;
+    }
+
+    *elapsed += time.delta_seconds();
+    let t = if schedule.duration > 0.0 {
+        (*elapsed / schedule.duration).min(1.0)
+    } else {
+        1.0
+    };
+    metrics.exploitation = t;
+    *params = schedule.explore.lerp(&schedule.exploit, t);
+    let interest =
+        schedule.explore_cohesion + (schedule.exploit_cohesion - schedule.explore_cohesion) * t;
+    for mut cohesion in boids.iter_mut() {
+        cohesion.interest = interest;
+    }
+}
+
+/// How a force's strength changes with distance, going from nothing at all to its max (or the
+/// other way around) over a radius
+#[derive(Clone, Copy)]
+pub enum Falloff {
+    /// Grows evenly with distance, reaching its max at the edge of the radius
+    Linear,
+    /// Barely there up close, growing quicker and quicker to reach its max at the edge of the
+    /// radius, letting boids mill about loosely while still reeling in ones drifting away
+    Quadratic,
+    /// Strongest up close and dropping off sharply like 1 / distance does, normalized to fade
+    /// out to nothing at the edge of the radius, binding tight clumps tighter while hardly
+    /// holding on to boids drifting away
+    Inverse,
+}
+
+/// How sharply Falloff::Inverse drops off, it's down to a sixth of its max halfway out
+const INVERSE_FALLOFF_STEEPNESS: f32 = 4.0;
+
+impl Falloff {
+    /// The strength of a force with max strength max at distance within radius, anything
+    /// beyond the radius isn't affected at all
+    pub fn strength(&self, distance: f32, radius: f32, max: f32) -> f32 {
+        if distance > radius || radius <= 0.0 {
+            return 0.0;
+        }
+        let t = distance / radius;
+        let scale = match self {
+            Falloff::Linear => t,
+            Falloff::Quadratic => t * t,
+            // 1 / (1 + k * t) shifted and scaled to go from 1 right on top to 0 at the radius
+            Falloff::Inverse => (1.0 - t) / (1.0 + INVERSE_FALLOFF_STEEPNESS * t),
+        };
+        scale * max
+    }
+}
+
+/// The boids a boid perceives around it, nearest first, gathered once per frame so that every
+/// flocking behavior can share them
+#[derive(Component, Default)]
+pub struct Neighbors(pub Vec<Entity>);
+
+/// Where in the unit circle a boid's misjudgement of each of its neighbors' position and velocity
+/// falls this time it looked around, scaled up by FlockingParams::perception_noise when the
+/// behaviors look at the neighbor. Boids that have been faded out of Neighbors are seen exactly
+#[derive(Component, Default)]
+pub struct PerceptionNoise(HashMap<Entity, (Vec2, Vec2)>);
+
+impl PerceptionNoise {
+    /// How far off from where it really is the boid sees a neighbor this far away
+    pub fn position_error(&self, neighbor: Entity, distance: f32, noise: f32) -> Vec2 {
+        self.0
+            .get(&neighbor)
+            .map_or(Vec2::ZERO, |&(position, _)| position * distance * noise)
+    }
+
+    /// How far off from how it really moves the boid sees a neighbor moving at this speed
+    pub fn velocity_error(&self, neighbor: Entity, speed: f32, noise: f32) -> Vec2 {
+        self.0
+            .get(&neighbor)
+            .map_or(Vec2::ZERO, |&(_, velocity)| velocity * speed * noise)
+    }
+}
+
+/// A uniformly random point in the unit circle
+fn random_in_unit_circle(rng: &mut StdRng) -> Vec2 {
+    let angle = rng.gen_range(0.0..TAU);
+    let distance = rng.gen_range(0.0f32..1.0).sqrt();
+    Vec2::new(angle.cos(), angle.sin()) * distance
+}
+
+/// How much attention a boid pays to each boid it perceives or has just stopped perceiving, from 0
+/// to 1, see AwarenessFade. Separation, alignment and cohesion weigh every neighbor's say by this
+#[derive(Component, Default)]
+pub struct Awareness(pub Vec<(Entity, f32)>);
+
+/// Without fading a neighbor counts fully the moment it's perceived and not at all the moment it
+/// isn't, which jerks boids around whenever someone slips in or out of their nearest few. With it
+/// a new neighbor's weight ramps up from 0 to 1 and a departed one's ramps back down to 0 at
+/// `rate` per second, so the steering averages change smoothly
+///
+/// A rate of 0 turns fading off, neighbors then count fully or not at all like before
+///
+/// Neighbors fading out still take up one of the max_perceived_neighbors slots until they're
+/// gone, the slots go to whoever a boid is paying the most attention to. So a boid that has its
+/// full count only starts fading in a newcomer once someone fading out has given up their slot
+pub struct AwarenessFade {
+    pub rate: f32,
+}
+
+impl Default for AwarenessFade {
+    fn default() -> Self {
+        AwarenessFade { rate: 0.0 }
+    }
+}
+
+pub fn fade_awareness(
+    time: Res<Time>,
+    fade: Res<AwarenessFade>,
+    params: Res<FlockingParams>,
+    mut query: Query<(&Neighbors, &mut Awareness)>,
+) {
+    let max = params.max_perceived_neighbors;
+    // Fading off snaps everyone straight to 1 or 0
+    let step = if fade.rate > 0.0 {
+        fade.rate * time.delta_seconds()
+    } else {
+        1.0
+    };
+    for (neighbors, mut awareness) in query.iter_mut() {
+        let awareness = &mut awareness.as_mut().0;
+        let previous = std::mem::take(awareness);
+        let weight_of = |entity: Entity| {
+            previous
+                .iter()
+                .find(|&&(other, _)| other == entity)
+                .map_or(0.0, |&(_, weight)| weight)
+        };
+        // Perceived neighbors keep their nearest first order and fade in, the rest fade out after
+        // them until they're gone
+        awareness.extend(
+            neighbors
+                .0
+                .iter()
+                .map(|&neighbor| (neighbor, (weight_of(neighbor) + step).min(1.0))),
+        );
+        awareness.extend(
+            previous
+                .iter()
+                .filter(|(other, _)| !neighbors.0.contains(other))
+                .map(|&(other, weight)| (other, weight - step))
+                .filter(|&(_, weight)| weight > 0.0),
+        );
+
+        if awareness.len() > max {
+            // A stable sort, so between equal weights the nearer perceived neighbors win
+            let mut order = (0..awareness.len()).collect::<Vec<_>>();
+            order.sort_by(|&a, &b| {
+                awareness[b]
+                    .1
+                    .partial_cmp(&awareness[a].1)
+                    .unwrap_or(Ordering::Equal)
+            });
+            let mut kept = vec![false; awareness.len()];
+            for &slot in &order[..max] {
+                kept[slot] = true;
+            }
+            let mut kept = kept.into_iter();
+            awareness.retain(|_| kept.next().unwrap_or(false));
+        }
+    }
+}
+
+/// A boid with Separation steers away from neighbors within `separation_radius` of it
+#[derive(Component)]
+pub struct Separation {
+    /// A number between 0 and 1 to determine how interested the boid is in keeping its distance,
+    /// see Seek's interest for how it interacts with other behaviors
+    pub interest: f32,
+}
+
+/// A boid with Alignment steers to match the average heading of neighbors within
+/// `alignment_radius` of it
+#[derive(Component)]
+pub struct Alignment {
+    /// A number between 0 and 1 to determine how interested the boid is in matching heading,
+    /// see Seek's interest for how it interacts with other behaviors
+    pub interest: f32,
+}
+
+/// A boid with Cohesion steers towards the center of neighbors within `cohesion_radius` of it
+#[derive(Component)]
+pub struct Cohesion {
+    /// A number between 0 and 1 to determine how interested the boid is in staying close,
+    /// see Seek's interest for how it interacts with other behaviors
+    pub interest: f32,
+}
+
+/// A boid with Regroup that has strayed from the flock (perceives no neighbors at all) steers
+/// back towards the center of the whole flock until it rejoins it
+///
+/// Cohesion can't do this on its own since it only acts on neighbors within `cohesion_radius`
+#[derive(Component)]
+pub struct Regroup {
+    /// A number between 0 and 1 to determine how interested a stray is in rejoining the flock,
+    /// see Seek's interest for how it interacts with other behaviors
+    ///
+    /// Keeping this lower than the other behaviors' interest makes strays drift back naturally
+    /// rather than rocketing to the flock
+    pub interest: f32,
+}
+
+/// A stray is a boid that can't see any other boid around it
+pub fn is_stray(neighbors: &Neighbors) -> bool {
+    neighbors.0.is_empty()
+}
+
+/// A boid in range of being perceived, ordered by distance so that the furthest one sits on top
+/// of the max-heap and can be dropped as soon as a closer one is found
+///
+/// Ties are broken by spawn order so equally distant boids are always picked the same way,
+/// rather than depending on the order the grid happens to hand them out in
+struct Candidate {
+    distance_squared: f32,
+    index: SpawnIndex,
+    entity: Entity,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance_squared
+            .partial_cmp(&other.distance_squared)
+            .unwrap_or(Ordering::Equal)
+            .then(self.index.cmp(&other.index))
+    }
+}
+
+/// Boids slow down in crowds and speed up when there's nobody around, like real flocks do, by
+/// scaling their Velocity.max off of their CruiseSpeed depending on how many boids are around
+///
+/// Unlike Separation this doesn't push boids anywhere, it only changes how fast they can go
+pub struct ComfortZone {
+    pub enabled: bool,
+    /// How close other boids have to be to count towards the crowd around a boid
+    pub radius: f32,
+    /// How many boids around make for a full crowd, any more than that doesn't slow a boid down
+    /// any further
+    pub crowd: usize,
+    /// The fraction of its CruiseSpeed a boid in a full crowd slows down to
+    pub crowded_speed: f32,
+    /// The fraction of its CruiseSpeed a boid with nobody around speeds up to
+    pub isolated_speed: f32,
+}
+
+impl Default for ComfortZone {
+    fn default() -> Self {
+        ComfortZone {
+            enabled: false,
+            radius: 40.0,
+            crowd: 10,
+            crowded_speed: 0.6,
+            isolated_speed: 1.3,
+        }
+    }
+}
+
+/// The Velocity.max a boid has without ComfortZone changing it, its Genes are already part of it
+///
+/// This is the base every boid's Velocity.max is worked out from again each frame, so whatever
+/// should change a boid's top speed for good (like the mood) goes here rather than in Velocity.max
+#[derive(Component)]
+pub struct CruiseSpeed(pub f32);
+
+pub fn modulate_speed_by_crowding(
+    zone: Res<ComfortZone>,
+    grid: Res<SpatialGrid>,
+    mut query: Query<(Entity, &Transform, &mut Velocity, &CruiseSpeed)>,
+) {
+    // Neither way should a boid freeze in place or speed off uncontrollably
+    let crowded_speed = zone.crowded_speed.clamp(0.1, 1.0);
+    let isolated_speed = zone.isolated_speed.clamp(1.0, 3.0);
+    for (entity, Transform { translation, .. }, mut velocity, cruise_speed) in query.iter_mut() {
+        let scale = if zone.enabled {
+            let crowd = query_neighbors(&grid, translation.truncate(), zone.radius)
+                .into_iter()
+                .filter(|&other| other != entity)
+                .count();
+            let crowding = (crowd as f32 / zone.crowd.max(1) as f32).min(1.0);
+            isolated_speed + (crowded_speed - isolated_speed) * crowding
+        } else {
+            1.0
+        };
+        velocity.max = cruise_speed.0 * scale;
+    }
+}
+
+pub fn gather_neighbors(
+    params: Res<FlockingParams>,
+    stagger: Res<SteeringStagger>,
+    grid: Res<SpatialGrid>,
+    scaled_separation: Res<SpeedScaledSeparation>,
+    mut rng: ResMut<SimRng>,
+    // Sleeping boids don't steer, so there's no point in them looking around
+    mut query: Query<
+        (
+            Entity,
+            &Transform,
+            &Velocity,
+            &mut Neighbors,
+            &mut PerceptionNoise,
+            &SpawnIndex,
+        ),
+        Without<Sleeping>,
+    >,
+    indices: Query<&SpawnIndex>,
+) {
+    let radius = if scaled_separation.enabled {
+        params.perception_radius().max(scaled_separation.max_radius)
+    } else {
+        params.perception_radius()
+    };
+    let max = params.max_perceived_neighbors;
+    let metric = grid.metric();
+    let blind_spot = params.fov_degrees < 360.0;
+    let fov_cos = (params.fov_degrees.max(0.0).to_radians() / 2.0).cos();
+    // Boids aren't visited in any particular order, so rather than drawing from SimRng as they
+    // come every boid draws from its own generator seeded by its spawn index, keeping noisy runs
+    // with the same seed the same
+    let noise_seed = if params.perception_noise > 0.0 {
+        Some(rng.0.gen::<u64>())
+    } else {
+        None
+    };
+    // Keeping only the closest few in a heap bounded to max + 1 elements is much cheaper than
+    // sorting everything in range when a boid is deep within a dense flock
+    let mut heap = BinaryHeap::new();
+    for (entity, Transform { translation, .. }, velocity, mut neighbors, mut noise, index) in
+        query.iter_mut()
+    {
+        // Only boids steering this frame look at their neighbors
+        if !stagger.is_due(index) {
+            continue;
+        }
+        let position = translation.truncate();
+        let heading = velocity.vector.normalize_or_zero();
+        for (other, other_position) in grid.within(position, radius) {
+            if other == entity {
+                continue;
+            }
+            let offset = metric.offset(position, other_position);
+            // A boid that isn't going anywhere has no heading to have a blind spot behind
+            if blind_spot
+                && heading != Vec2::ZERO
+                && heading.dot(offset.normalize_or_zero()) < fov_cos
+            {
+                continue;
+            }
+            let index = match indices.get(other) {
+                Ok(&index) => index,
+                Err(_) => continue,
+            };
+            heap.push(Candidate {
+                distance_squared: offset.length_squared(),
+                index,
+                entity: other,
+            });
+            if heap.len() > max {
+                heap.pop();
+            }
+        }
+
+        let neighbors = &mut neighbors.as_mut().0;
+        neighbors.clear();
+        neighbors.extend(
+            std::mem::take(&mut heap)
+                .into_sorted_vec()
+                .into_iter()
+                .map(|candidate| candidate.entity),
+        );
+
+        let noise = &mut noise.as_mut().0;
+        noise.clear();
+        if let Some(seed) = noise_seed {
+            let mut rng = StdRng::seed_from_u64(seed ^ index.0);
+            noise.extend(neighbors.iter().map(|&neighbor| {
+                (
+                    neighbor,
+                    (
+                        random_in_unit_circle(&mut rng),
+                        random_in_unit_circle(&mut rng),
+                    ),
+                )
+            }));
+        }
+    }
+}
+
+/// What separate, align and cohere all steer with, and the SteeringTotals they add to
+#[derive(SystemParam)]
+pub struct FlockSteering<'a> {
+    toggles: Res<'a, BehaviorToggles>,
+    stagger: Res<'a, SteeringStagger>,
+    params: Res<'a, FlockingParams>,
+    grid: Res<'a, SpatialGrid>,
+    totals: ResMut<'a, SteeringTotals>,
+}
+
+pub fn separate(
+    steering: FlockSteering,
+    math: Res<SeparationMath>,
+    scaled_separation: Res<SpeedScaledSeparation>,
+    layers: Res<CollisionMatrix>,
+    mut query: Query<
+        (
+            &mut Force,
+            &Velocity,
+            &Transform,
+            &Awareness,
+            &PerceptionNoise,
+            &Separation,
+            &CollisionLayer,
+            &SpawnIndex,
+        ),
+        Without<Sleeping>,
+    >,
+    others: Query<(&Transform, &CollisionLayer)>,
+) {
+    let FlockSteering {
+        toggles,
+        stagger,
+        params,
+        grid,
+        mut totals,
+    } = steering;
+    if !toggles.separation {
+        return;
+    }
+    let metric = grid.metric();
+    let misjudgement = params.perception_noise.clamp(0.0, 1.0);
+    for (
+        mut force,
+        velocity,
+        Transform { translation, .. },
+        awareness,
+        noise,
+        separation,
+        &layer,
+        index,
+    ) in query.iter_mut()
+    {
+        if !stagger.is_due(index) {
+            continue;
+        }
+        let radius = scaled_separation.radius(&params, velocity);
+        let radius_squared = radius * radius;
+        let force = force.as_mut();
+        let position = translation.truncate();
+        // Every neighbor pushes away from itself, the closer it is the harder it pushes
+        let offsets = awareness
+            .0
+            .iter()
+            .filter_map(|&(neighbor, weight)| Some((neighbor, others.get(neighbor).ok()?, weight)))
+            // Boids on layers that don't collide pass right through each other
+            .filter(|&(_, (_, &other_layer), _)| layers.collides(layer, other_layer))
+            .map(|(neighbor, (other, _), weight)| {
+                let offset = metric.offset(other.translation.truncate(), position);
+                let error = noise.position_error(neighbor, offset.length(), misjudgement);
+                (offset - error, weight)
+            });
+        let (away, total_weight) = match *math {
+            SeparationMath::Exact => offsets
+                .filter(|(offset, _)| offset.length() <= radius)
+                .fold((Vec2::ZERO, 0.0), |(away, total), (offset, weight)| {
+                    let push = offset.normalize_or_zero() / offset.length().max(f32::EPSILON);
+                    (away + push * weight, total + weight)
+                }),
+            // offset / |offset|^2 is the same as offset.normalize() / |offset|
+            SeparationMath::Squared => offsets
+                .map(|(offset, weight)| (offset, offset.length_squared(), weight))
+                .filter(|&(_, length_squared, _)| length_squared <= radius_squared)
+                .fold(
+                    (Vec2::ZERO, 0.0),
+                    |(away, total), (offset, length_squared, weight)| {
+                        let push = offset / length_squared.max(f32::EPSILON);
+                        (away + push * weight, total + weight)
+                    },
+                ),
+        };
+
+        if away != Vec2::ZERO {
+            let desired_velocity = away.normalize() * velocity.max;
+            // A lone neighbor that's only half noticed only gets half a reaction
+            let push = Vec2::clamp_length_max(desired_velocity - velocity.vector, force.max)
+                * separation.interest
+                * f32::min(total_weight, 1.0);
+            force.vector += push;
+            totals.separation += push.length();
+        }
+    }
+}
+
+/// The average speed the whole flock should fly at, see SpeedController
+pub struct TargetAvgSpeed(pub f32);
+
+impl Default for TargetAvgSpeed {
+    fn default() -> Self {
+        TargetAvgSpeed(0.8)
+    }
+}
+
+/// Speeds the whole flock up or slows it down until its average speed settles on the
+/// TargetAvgSpeed, every boid gets the same push straight along its heading so nobody gets turned
+///
+/// The push is gain times how far the average is off from the target, a higher gain gets there
+/// quicker but boids' own steering then has less room to change their speed. A target of 0 lets
+/// the flock glide to a stop, a boid is never slowed down by more than it's moving so it can't
+/// get pushed into reverse
+pub struct SpeedController {
+    pub enabled: bool,
+    pub gain: f32,
+}
+
+impl Default for SpeedController {
+    fn default() -> Self {
+        SpeedController {
+            enabled: false,
+            gain: 0.5,
+        }
+    }
+}
+
+pub fn control_average_speed(
+    toggles: Res<BehaviorToggles>,
+    controller: Res<SpeedController>,
+    target: Res<TargetAvgSpeed>,
+    metrics: Res<FlockMetrics>,
+    stagger: Res<SteeringStagger>,
+    mut query: Query<(&mut Force, &Velocity, &SpawnIndex), Without<Sleeping>>,
+) {
+    if !controller.enabled || toggles.all_off() {
+        return;
+    }
+    let average = metrics.average_speed.current;
+    let target = target.0.max(0.0);
+
+    let push = (target - average) * controller.gain;
+    for (mut force, velocity, index) in query.iter_mut() {
+        if !stagger.is_due(index) {
+            continue;
+        }
+        let speed = velocity.vector.length();
+        if speed.classify() == FpCategory::Zero {
+            continue;
+        }
+        let force = force.as_mut();
+        let push = push.clamp(-speed.min(force.max), force.max);
+        force.vector += velocity.vector / speed * push;
+    }
+}
+
+/// Boids that aren't being steered anywhere drift back towards cruise_speed rather than coasting
+/// along at whatever speed they were left at, which gives a flock with nothing going on a natural
+/// resting pace
+///
+/// Only a boid pushed by less than idle_force (as a fraction of its Force.max) by everything else
+/// counts as idle, and it gets pushed straight along its heading by gain times how far off its
+/// speed is. A boid at a standstill has no heading to speed up along, so it's left for other
+/// behaviors to get it going
+pub struct IdleCruising {
+    pub enabled: bool,
+    pub cruise_speed: f32,
+    pub gain: f32,
+    pub idle_force: f32,
+}
+
+impl Default for IdleCruising {
+    fn default() -> Self {
+        IdleCruising {
+            enabled: false,
+            cruise_speed: 0.6,
+            gain: 0.1,
+            idle_force: 0.1,
+        }
+    }
+}
+
+/// This has to run after every steering system, whether a boid is idle is decided by its total
+/// force
+pub fn cruise_when_idle(
+    toggles: Res<BehaviorToggles>,
+    cruising: Res<IdleCruising>,
+    stagger: Res<SteeringStagger>,
+    mut query: Query<(&mut Force, &Velocity, &SpawnIndex), Without<Sleeping>>,
+) {
+    if !cruising.enabled || toggles.all_off() {
+        return;
+    }
+    let target = cruising.cruise_speed.max(0.0);
+    for (mut force, velocity, index) in query.iter_mut() {
+        if !stagger.is_due(index) {
+            continue;
+        }
+        let force = force.as_mut();
+        if force.vector.length() > cruising.idle_force * force.max {
+            continue;
+        }
+        let speed = velocity.vector.length();
+        if speed.classify() == FpCategory::Zero {
+            continue;
+        }
+        // Just like SpeedController a boid is never slowed down by more than it's moving
+        let push = ((target - speed) * cruising.gain).clamp(-speed.min(force.max), force.max);
+        force.vector += velocity.vector / speed * push;
+    }
+}
+
+/// Two boids flying straight at each other can end up in a standoff, with separation pushing
+/// each back exactly as hard as whatever steers it forwards so both hover in place facing each
+/// other. A boid that's barely moving and barely being pushed while it has a neighbor is taken to
+/// be stuck like that and gets nudged sideways
+///
+/// Stuck boids always sidestep to the right of their nearest neighbor, so two boids stuck facing
+/// each other step out of each other's way rather than both into the same side. A boid with its
+/// neighbor right on top of it has no right to speak of and goes a direction picked from its
+/// SpawnIndex instead, which is the same every run
+pub struct StalemateBreaking {
+    pub enabled: bool,
+    /// A boid slower than this counts as stalled
+    pub stall_speed: f32,
+    /// A stalled boid with a total force smaller than this counts as stuck
+    pub stall_force: f32,
+    /// How hard stuck boids get nudged as a fraction of their Force.max
+    pub nudge: f32,
+}
+
+impl Default for StalemateBreaking {
+    fn default() -> Self {
+        StalemateBreaking {
+            enabled: false,
+            stall_speed: 0.05,
+            stall_force: 0.02,
+            nudge: 0.2,
+        }
+    }
+}
+
+/// This has to run after every other steering system, a boid is only stuck if all of its
+/// behaviors together cancel out
+pub fn break_stalemates(
+    toggles: Res<BehaviorToggles>,
+    breaking: Res<StalemateBreaking>,
+    stagger: Res<SteeringStagger>,
+    grid: Res<SpatialGrid>,
+    mut query: Query<
+        (&mut Force, &Velocity, &Transform, &Neighbors, &SpawnIndex),
+        Without<Sleeping>,
+    >,
+    others: Query<&Transform>,
+) {
+    if !breaking.enabled || toggles.all_off() {
+        return;
+    }
+    let metric = grid.metric();
+    for (mut force, velocity, transform, neighbors, index) in query.iter_mut() {
+        if !stagger.is_due(index)
+            || velocity.vector.length() >= breaking.stall_speed
+            || force.vector.length() >= breaking.stall_force
+        {
+            continue;
+        }
+        let nearest = match neighbors
+            .0
+            .first()
+            .and_then(|&other| others.get(other).ok())
+        {
+            Some(nearest) => nearest,
+            None => continue,
+        };
+
+        let toward = metric
+            .offset(
+                transform.translation.truncate(),
+                nearest.translation.truncate(),
+            )
+            .normalize_or_zero();
+        let side = if toward != Vec2::ZERO {
+            -toward.perp()
+        } else {
+            // Spreads consecutive indices far apart around the circle
+            let angle = index.0 as f32 * std::f32::consts::PI * (3.0 - 5f32.sqrt());
+            Vec2::new(angle.cos(), angle.sin())
+        };
+        let force = force.as_mut();
+        force.vector += side * force.max * breaking.nudge;
+    }
+}
+
+pub fn align(
+    steering: FlockSteering,
+    weighting: Res<NeighborWeighting>,
+    mut query: Query<
+        (
+            &mut Force,
+            &Velocity,
+            &Transform,
+            &Awareness,
+            &PerceptionNoise,
+            &Alignment,
+            &SpawnIndex,
+        ),
+        Without<Sleeping>,
+    >,
+    others: Query<(&Transform, &Velocity)>,
+) {
+    let FlockSteering {
+        toggles,
+        stagger,
+        params,
+        grid,
+        mut totals,
+    } = steering;
+    if !toggles.alignment {
+        return;
+    }
+    let metric = grid.metric();
+    let misjudgement = params.perception_noise.clamp(0.0, 1.0);
+    for (mut force, velocity, Transform { translation, .. }, awareness, noise, alignment, index) in
+        query.iter_mut()
+    {
+        if !stagger.is_due(index) {
+            continue;
+        }
+        let force = force.as_mut();
+        let position = translation.truncate();
+        let (heading, total_weight) = awareness
+            .0
+            .iter()
+            .filter_map(|&(neighbor, weight)| {
+                let (other, other_velocity) = others.get(neighbor).ok()?;
+                let offset = metric.offset(position, other.translation.truncate());
+                let distance = offset.length();
+                let perceived_distance =
+                    (offset + noise.position_error(neighbor, distance, misjudgement)).length();
+                let speed = other_velocity.vector.length();
+                let perceived_velocity =
+                    other_velocity.vector + noise.velocity_error(neighbor, speed, misjudgement);
+                Some((perceived_distance, perceived_velocity, weight))
+            })
+            .filter(|&(distance, _, _)| distance <= params.alignment_radius)
+            .filter(|(_, other, _)| {
+                !params.align_with_faster_only
+                    || other.length_squared() > velocity.vector.length_squared()
+            })
+            .fold(
+                (Vec2::ZERO, 0.0),
+                |(heading, total), (distance, other, weight)| {
+                    let closeness = weighting.weight(distance, params.alignment_radius);
+                    (heading + other * weight * closeness, total + weight)
+                },
+            );
+
+        if heading != Vec2::ZERO {
+            let desired_velocity = heading.normalize() * velocity.max;
+            let steer = Vec2::clamp_length_max(desired_velocity - velocity.vector, force.max)
+                * alignment.interest
+                * f32::min(total_weight, 1.0);
+            force.vector += steer;
+            totals.alignment += steer.length();
+        }
+    }
+}
+
+pub fn cohere(
+    steering: FlockSteering,
+    weighting: Res<NeighborWeighting>,
+    mut query: Query<
+        (
+            &mut Force,
+            &Transform,
+            &Awareness,
+            &PerceptionNoise,
+            &Cohesion,
+            &SpawnIndex,
+        ),
+        Without<Sleeping>,
+    >,
+    others: Query<&Transform>,
+) {
+    let FlockSteering {
+        toggles,
+        stagger,
+        params,
+        grid,
+        mut totals,
+    } = steering;
+    if !toggles.cohesion {
+        return;
+    }
+    let metric = grid.metric();
+    let misjudgement = params.perception_noise.clamp(0.0, 1.0);
+    for (mut force, Transform { translation, .. }, awareness, noise, cohesion, index) in
+        query.iter_mut()
+    {
+        if !stagger.is_due(index) {
+            continue;
+        }
+        let force = force.as_mut();
+        let position = translation.truncate();
+        let (sum, total_weight, weights) = awareness
+            .0
+            .iter()
+            .filter_map(|&(neighbor, weight)| Some((neighbor, others.get(neighbor).ok()?, weight)))
+            .map(|(neighbor, other, weight)| {
+                let offset = metric.offset(position, other.translation.truncate());
+                let error = noise.position_error(neighbor, offset.length(), misjudgement);
+                (offset + error, weight)
+            })
+            .filter(|(offset, _)| offset.length() <= params.cohesion_radius)
+            .fold(
+                (Vec2::ZERO, 0.0, 0.0),
+                |(sum, total, weights), (offset, weight)| {
+                    let weight_closeness =
+                        weight * weighting.weight(offset.length(), params.cohesion_radius);
+                    (
+                        sum + offset * weight_closeness,
+                        total + weight,
+                        weights + weight_closeness,
+                    )
+                },
+            );
+
+        if weights > 0.0 {
+            // Averaging offsets rather than positions keeps the center on the right side of a
+            // wrapping edge
+            let to_center = sum / weights;
+            let strength = params.cohesion_falloff.strength(
+                to_center.length(),
+                params.cohesion_radius,
+                force.max,
+            );
+            let pull = to_center.normalize_or_zero()
+                * strength
+                * cohesion.interest
+                * f32::min(total_weight, 1.0);
+            force.vector += pull;
+            totals.cohesion += pull.length();
+        }
+    }
+}
+
+pub fn regroup(
+    toggles: Res<BehaviorToggles>,
+    stagger: Res<SteeringStagger>,
+    deterministic: Res<DeterministicMode>,
+    mut query: Query<
+        (
+            &mut Force,
+            &Velocity,
+            &Transform,
+            &Neighbors,
+            &Regroup,
+            &SpawnIndex,
+        ),
+        Without<Sleeping>,
+    >,
+    boids: Query<(&Transform, &SpawnIndex), With<Velocity>>,
+) {
+    if !toggles.regroup {
+        return;
+    }
+    let mut positions = boids
+        .iter()
+        .map(|(transform, &index)| (index, transform.translation.truncate()))
+        .collect::<Vec<_>>();
+    if deterministic.0 {
+        positions.sort_unstable_by_key(|&(index, _)| index);
+    }
+    let (sum, count) = positions
+        .iter()
+        .fold((Vec2::ZERO, 0), |(sum, count), (_, position)| {
+            (sum + *position, count + 1)
+        });
+    // A lone boid is its own flock, there's nothing to regroup with
+    if count < 2 {
+        return;
+    }
+    let centroid = sum / count as f32;
+
+    for (mut force, velocity, Transform { translation, .. }, neighbors, regroup, index) in
+        query.iter_mut()
+    {
+        if !stagger.is_due(index) || !is_stray(neighbors) {
+            continue;
+        }
+        let force = force.as_mut();
+        force.vector += seek_force(
+            centroid,
+            translation.truncate(),
+            velocity.vector,
+            force.max,
+            regroup.interest,
+        );
+    }
+}
+
+/// Which collision layers a boid is on, a bit for each of up to 32 layers. Boids only separate
+/// from and keep their MinimumSpacing to boids on layers that CollisionMatrix says collide with
+/// one of theirs, the rest pass right through them. Every boid starts out alone on the layer
+/// numbered after its FlockId, so passing `--no-collide 0,1` lets the first two flocks fly
+/// through each other
+///
+/// This only decides who bumps into who, boids still align and cohere with everyone they see
+/// whatever their layers, see FlockId for splitting boids into flocks that ignore each other
+#[derive(Component, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CollisionLayer(pub u32);
+
+impl Default for CollisionLayer {
+    fn default() -> Self {
+        CollisionLayer(1)
+    }
+}
+
+impl CollisionLayer {
+    /// The layer boids of flock start out on, flocks past the 32nd share layers with the ones
+    /// before them
+    pub fn of_flock(flock: FlockId) -> Self {
+        CollisionLayer(1 << (flock.0 % 32))
+    }
+}
+
+/// Which collision layers collide with which, see CollisionLayer. By default every layer collides
+/// with every other layer, including itself
+pub struct CollisionMatrix {
+    /// The layers each layer collides with, as a bitmask, kept symmetric by set
+    collides: [u32; 32],
+}
+
+impl Default for CollisionMatrix {
+    fn default() -> Self {
+        CollisionMatrix {
+            collides: [u32::MAX; 32],
+        }
+    }
+}
+
+impl CollisionMatrix {
+    /// The matrix asked for on the command line, every `--no-collide a,b` stops layers a and b
+    /// from colliding with each other and anything that isn't understood is skipped with a
+    /// warning
+    pub fn from_args() -> CollisionMatrix {
+        let args = std::env::args().collect::<Vec<_>>();
+        let layer = |layer: &str| {
+            layer
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|&layer| layer < 32)
+        };
+        let mut matrix = CollisionMatrix::default();
+        for pair in args.windows(2).filter(|pair| pair[0] == "--no-collide") {
+            let layers = pair[1]
+                .split_once(',')
+                .and_then(|(a, b)| Some((layer(a)?, layer(b)?)));
+            match layers {
+                Some((a, b)) => matrix.set(a, b, false),
+                None => eprintln!(
+                    "Couldn't understand --no-collide {:?}, ignoring it",
+                    pair[1]
+                ),
+            }
+        }
+        matrix
+    }
+
+    /// Sets whether layers a and b collide with each other, both of them numbered 0 to 31
+    pub fn set(&mut self, a: usize, b: usize, collide: bool) {
+        if a >= 32 || b >= 32 {
+            return;
+        }
+        if collide {
+            self.collides[a] |= 1 << b;
+            self.collides[b] |= 1 << a;
+        } else {
+            self.collides[a] &= !(1 << b);
+            self.collides[b] &= !(1 << a);
+        }
+    }
+
+    /// Whether boids on layers a and b collide, which they do when any of a's layers collides
+    /// with any of b's
+    pub fn collides(&self, a: CollisionLayer, b: CollisionLayer) -> bool {
+        (0..32).any(|layer| a.0 & (1 << layer) != 0 && self.collides[layer] & b.0 != 0)
+    }
+}
+
+/// Keeps boids at least distance apart from each other no matter what, unlike Separation which
+/// only steers boids away from each other and can be overpowered by whatever else they're
+/// steering towards, useful for densely packed flocks that should still never overlap
+///
+/// Every overlapping pair is pushed apart directly, half the overlap each, or all of it for the
+/// one that isn't Pinned when the other one is. Pushing one pair apart can shove either boid into
+/// another one, so this goes over all the pairs iterations times, with more iterations leaving
+/// fewer overlaps behind in tightly packed clumps
+pub struct MinimumSpacing {
+    pub enabled: bool,
+    pub distance: f32,
+    pub iterations: u32,
+}
+
+impl Default for MinimumSpacing {
+    fn default() -> Self {
+        MinimumSpacing {
+            enabled: false,
+            distance: 6.0,
+            iterations: 4,
+        }
+    }
+}
+
+/// This has to run after boids have moved for the frame, otherwise their movement could push
+/// them right back together
+pub fn separate_positions(
+    spacing: Res<MinimumSpacing>,
+    grid: Res<SpatialGrid>,
+    layers: Res<CollisionMatrix>,
+    mut boids: Query<
+        (
+            Entity,
+            &mut Transform,
+            &CollisionLayer,
+            &SpawnIndex,
+            Option<&Pinned>,
+        ),
+        With<Velocity>,
+    >,
+) {
+    if !spacing.enabled || spacing.distance <= 0.0 {
+        return;
+    }
+    let metric = grid.metric();
+
+    // Pairs are always resolved in spawn order so the result is the same from run to run
+    let mut positions = boids
+        .iter_mut()
+        .map(|(entity, transform, &layer, &index, pinned)| {
+            // A Pinned boid doesn't budge, whoever it overlaps is pushed all the way out instead
+            let give = if pinned.is_some() { 0.0 } else { 1.0 };
+            (index, entity, transform.translation.truncate(), layer, give)
+        })
+        .collect::<Vec<_>>();
+    positions.sort_unstable_by_key(|&(index, ..)| index);
+    let slots = positions
+        .iter()
+        .enumerate()
+        .map(|(slot, &(_, entity, ..))| (entity, slot))
+        .collect::<HashMap<_, _>>();
+
+    // The grid was built before boids moved this frame, so it's searched twice as far out to
+    // still catch boids that have moved closer together since
+    let pairs = positions
+        .iter()
+        .enumerate()
+        .flat_map(|(slot, &(_, _, position, layer, _))| {
+            let positions = &positions;
+            let layers = &layers;
+            query_neighbors(&grid, position, spacing.distance * 2.0)
+                .into_iter()
+                .filter_map(|other| slots.get(&other).copied())
+                .filter(move |&other| other > slot && layers.collides(layer, positions[other].3))
+                .map(move |other| (slot, other))
+        })
+        .collect::<Vec<_>>();
+
+    for _ in 0..spacing.iterations {
+        for &(a, b) in pairs.iter() {
+            let offset = metric.offset(positions[a].2, positions[b].2);
+            let distance = offset.length();
+            if distance >= spacing.distance {
+                continue;
+            }
+            // Boids sitting exactly on top of each other don't have a direction to be pushed
+            // apart in, so they're split along x
+            let direction = if distance > f32::EPSILON {
+                offset / distance
+            } else {
+                Vec2::X
+            };
+            let give = positions[a].4 + positions[b].4;
+            // Two Pinned boids stay overlapping, neither of them can move
+            if give <= 0.0 {
+                continue;
+            }
+            let push = direction * (spacing.distance - distance) / give;
+            positions[a].2 -= push * positions[a].4;
+            positions[b].2 += push * positions[b].4;
+        }
+    }
+
+    for (entity, mut transform, ..) in boids.iter_mut() {
+        if let Some(&slot) = slots.get(&entity) {
+            let position = positions[slot].2;
+            transform.translation.x = position.x;
+            transform.translation.y = position.y;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{grid::SpatialIndex, test_world::test_world};
+    use bevy::ecs::{
+        schedule::{Stage, SystemStage},
+        world::World,
+    };
+    use std::time::Instant;
+
+    #[test]
+    fn boids_perceive_at_most_max_perceived_neighbors() {
+        let mut world = test_world(0);
+        world.insert_resource(FlockingParams {
+            max_perceived_neighbors: 3,
+            ..Default::default()
+        });
+        // A row of boids 5 apart, every one of them well within perception of every other
+        let positions = (0..10)
+            .map(|i| Vec2::new(i as f32 * 5.0, 0.0))
+            .collect::<Vec<_>>();
+        let boids = positions
+            .iter()
+            .enumerate()
+            .map(|(i, position)| {
+                world
+                    .spawn()
+                    .insert_bundle((
+                        Transform::from_xyz(position.x, position.y, 0.0),
+                        Velocity {
+                            vector: Vec2::ZERO,
+                            max: 1.0,
+                        },
+                        Neighbors::default(),
+                        PerceptionNoise::default(),
+                        SpawnIndex(i as u64),
+                    ))
+                    .id()
+            })
+            .collect::<Vec<_>>();
+        let mut grid = SpatialGrid::default();
+        grid.rebuild(
+            SpatialIndex::UniformGrid,
+            boids.iter().copied().zip(positions).collect(),
+        );
+        world.insert_resource(grid);
+
+        SystemStage::single(gather_neighbors).run(&mut world);
+
+        for &boid in &boids {
+            assert_eq!(world.get::<Neighbors>(boid).unwrap().0.len(), 3);
+        }
+        // And the ones kept are the closest, nearest first
+        assert_eq!(world.get::<Neighbors>(boids[0]).unwrap().0, boids[1..4]);
+
+        // A neighbor that's fading out takes up one of the 3 as well, the first boid was only
+        // paying attention to the far end of the row until now
+        world.insert_resource(AwarenessFade { rate: 1.0 });
+        for &boid in &boids {
+            world.entity_mut(boid).insert(Awareness::default());
+        }
+        world
+            .entity_mut(boids[0])
+            .insert(Awareness(vec![(boids[9], 1.0)]));
+        // The first update only starts the clock, fading needs some time to have gone by
+        for _ in 0..2 {
+            std::thread::sleep(std::time::Duration::from_millis(2));
+            world.get_resource_mut::<Time>().unwrap().update();
+        }
+
+        SystemStage::single(fade_awareness).run(&mut world);
+
+        for &boid in &boids {
+            assert!(world.get::<Awareness>(boid).unwrap().0.len() <= 3);
+        }
+        let awareness = &world.get::<Awareness>(boids[0]).unwrap().0;
+        let aware_of = awareness
+            .iter()
+            .map(|&(entity, _)| entity)
+            .collect::<Vec<_>>();
+        assert_eq!(aware_of, [boids[1], boids[2], boids[9]]);
+    }
+
+    /// The force separate gives each boid at a position with a velocity on a layer, every boid
+    /// fully aware of every other one
+    fn separation_forces(world: &mut World, boids: &[(Vec2, Vec2, CollisionLayer)]) -> Vec<Vec2> {
+        let entities = boids
+            .iter()
+            .enumerate()
+            .map(|(i, &(position, velocity, layer))| {
+                world
+                    .spawn()
+                    .insert_bundle((
+                        Transform::from_xyz(position.x, position.y, 0.0),
+                        Velocity {
+                            vector: velocity,
+                            max: 1.0,
+                        },
+                        Force {
+                            vector: Vec2::ZERO,
+                            max: 1.0,
+                        },
+                        PerceptionNoise::default(),
+                        Separation { interest: 1.0 },
+                        layer,
+                        SpawnIndex(i as u64),
+                    ))
+                    .id()
+            })
+            .collect::<Vec<_>>();
+        for &boid in &entities {
+            let others = entities.iter().filter(|&&other| other != boid);
+            world
+                .entity_mut(boid)
+                .insert(Awareness(others.map(|&other| (other, 1.0)).collect()));
+        }
+
+        SystemStage::single(separate).run(world);
+
+        entities
+            .iter()
+            .map(|&boid| world.get::<Force>(boid).unwrap().vector)
+            .collect()
+    }
+
+    #[test]
+    fn squared_separation_steers_the_same_as_exact() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let boids = (0..200)
+            .map(|_| {
+                let position =
+                    Vec2::new(rng.gen_range(-100.0..100.0), rng.gen_range(-100.0..100.0));
+                (position, Vec2::ZERO, CollisionLayer::default())
+            })
+            .collect::<Vec<_>>();
+        let mut world = test_world(0);
+        world.insert_resource(SeparationMath::Exact);
+        let exact = separation_forces(&mut world, &boids);
+        let mut world = test_world(0);
+        world.insert_resource(SeparationMath::Squared);
+        let squared = separation_forces(&mut world, &boids);
+
+        // Crowded enough that plenty of boids actually get pushed
+        assert!(exact.iter().filter(|force| **force != Vec2::ZERO).count() > 100);
+        for (exact, squared) in exact.iter().zip(&squared) {
+            assert!(
+                exact.distance(*squared) < 1e-4,
+                "{} and {} steer differently",
+                exact,
+                squared
+            );
+        }
+    }
+
+    /// How long separate takes with each SeparationMath on a large flock, every boid of which
+    /// is aware of every other one, which is what SeparationMath::Squared is meant to speed up
+    ///
+    /// Timings only mean something in release, run it with
+    /// `cargo test --release separation_math -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_separation_math() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let boids = (0..1000)
+            .map(|_| {
+                let position =
+                    Vec2::new(rng.gen_range(-100.0..100.0), rng.gen_range(-100.0..100.0));
+                (position, Vec2::ZERO, CollisionLayer::default())
+            })
+            .collect::<Vec<_>>();
+        let rounds = 20;
+
+        for math in [SeparationMath::Exact, SeparationMath::Squared] {
+            let mut world = test_world(0);
+            world.insert_resource(math);
+            // Spawns the flock and gets the first run out of the way
+            separation_forces(&mut world, &boids);
+            let mut stage = SystemStage::single(separate);
+            let start = Instant::now();
+            for _ in 0..rounds {
+                stage.run(&mut world);
+            }
+            let name = match math {
+                SeparationMath::Exact => "exact",
+                SeparationMath::Squared => "squared",
+            };
+            println!("{}: {:?} per frame", name, start.elapsed() / rounds);
+        }
+    }
+
+    #[test]
+    fn fast_boids_keep_more_room_around_them() {
+        let mut world = test_world(0);
+        world.insert_resource(SpeedScaledSeparation {
+            enabled: true,
+            ..Default::default()
+        });
+        // 30 apart is within the radius of a boid at full speed but not of one standing still
+        let forces = separation_forces(
+            &mut world,
+            &[
+                (Vec2::ZERO, Vec2::new(1.0, 0.0), CollisionLayer::default()),
+                (Vec2::new(0.0, 30.0), Vec2::ZERO, CollisionLayer::default()),
+            ],
+        );
+        let (fast, slow) = (forces[0], forces[1]);
+        // So only the fast one backs off, away from the slow one
+        assert!(fast.y < 0.0, "{}", fast);
+        assert_eq!(slow, Vec2::ZERO);
+    }
+
+    /// How hard cohere pulls a boid towards a lone neighbor distance away, with a Force.max of 1
+    fn cohesion_pull(falloff: Falloff, distance: f32) -> f32 {
+        let mut world = test_world(0);
+        world.insert_resource(FlockingParams {
+            cohesion_falloff: falloff,
+            ..Default::default()
+        });
+        world.insert_resource(NeighborWeighting::Uniform);
+        let neighbor = world
+            .spawn()
+            .insert(Transform::from_xyz(distance, 0.0, 0.0))
+            .id();
+        let boid = world
+            .spawn()
+            .insert_bundle((
+                Transform::default(),
+                Force {
+                    vector: Vec2::ZERO,
+                    max: 1.0,
+                },
+                Awareness(vec![(neighbor, 1.0)]),
+                PerceptionNoise::default(),
+                Cohesion { interest: 1.0 },
+                SpawnIndex(0),
+            ))
+            .id();
+        SystemStage::single(cohere).run(&mut world);
+        world.get::<Force>(boid).unwrap().vector.length()
+    }
+
+    #[test]
+    fn cohesion_follows_its_falloff_curve() {
+        // A tenth, half and four fifths of the way out to the default cohesion_radius of 50
+        let distances = [5.0, 25.0, 40.0];
+        for (falloff, expected) in [
+            (Falloff::Linear, [0.1, 0.5, 0.8]),
+            (Falloff::Quadratic, [0.01, 0.25, 0.64]),
+            (Falloff::Inverse, [0.9 / 1.4, 0.5 / 3.0, 0.2 / 4.2]),
+        ] {
+            for (distance, expected) in distances.iter().zip(expected) {
+                let pull = cohesion_pull(falloff, *distance);
+                assert!(
+                    (pull - expected).abs() < 1e-4,
+                    "{} away pulls {} instead of {}",
+                    distance,
+                    pull,
+                    expected
+                );
+            }
+            // And nothing past cohesion_radius pulls at all
+            assert_eq!(cohesion_pull(falloff, 60.0), 0.0);
+        }
+    }
+
+    #[test]
+    fn only_boids_on_colliding_layers_separate() {
+        let mut matrix = CollisionMatrix::default();
+        matrix.set(0, 1, false);
+        let mut world = test_world(0);
+        world.insert_resource(matrix);
+        let (first, second) = (
+            CollisionLayer::of_flock(FlockId(0)),
+            CollisionLayer::of_flock(FlockId(1)),
+        );
+        // Right on top of each other, well within the separation radius
+        let forces = separation_forces(
+            &mut world,
+            &[
+                (Vec2::new(0.0, 0.0), Vec2::ZERO, first),
+                (Vec2::new(2.0, 0.0), Vec2::ZERO, second),
+                (Vec2::new(1000.0, 0.0), Vec2::ZERO, first),
+                (Vec2::new(1002.0, 0.0), Vec2::ZERO, first),
+            ],
+        );
+
+        // Across the layers that don't collide nobody moves out of the way
+        assert_eq!(forces[0], Vec2::ZERO);
+        assert_eq!(forces[1], Vec2::ZERO);
+        // While the pair on the same layer pushes apart
+        assert!(forces[2].x < 0.0, "{}", forces[2]);
+        assert!(forces[3].x > 0.0, "{}", forces[3]);
+    }
+
+    #[test]
+    fn separate_positions_pushes_a_packed_cluster_apart() {
+        let mut world = test_world(0);
+        let spacing = MinimumSpacing {
+            enabled: true,
+            distance: 6.0,
+            iterations: 100,
+        };
+        // Piled up in a box small enough that every boid starts out overlapping its neighbors,
+        // and that every pair is close enough for the pass to look at
+        let mut rng = StdRng::seed_from_u64(3);
+        let positions = (0..12)
+            .map(|_| Vec2::new(rng.gen_range(-4.0..4.0), rng.gen_range(-4.0..4.0)))
+            .collect::<Vec<_>>();
+        let boids = positions
+            .iter()
+            .enumerate()
+            .map(|(i, position)| {
+                world
+                    .spawn()
+                    .insert_bundle((
+                        Transform::from_xyz(position.x, position.y, 0.0),
+                        Velocity {
+                            vector: Vec2::ZERO,
+                            max: 1.0,
+                        },
+                        CollisionLayer::default(),
+                        SpawnIndex(i as u64),
+                    ))
+                    .id()
+            })
+            .collect::<Vec<_>>();
+        let mut grid = SpatialGrid::default();
+        grid.rebuild(
+            SpatialIndex::UniformGrid,
+            boids.iter().copied().zip(positions).collect(),
+        );
+        world.insert_resource(grid);
+        world.insert_resource(spacing);
+
+        SystemStage::single(separate_positions).run(&mut world);
+
+        let positions = boids
+            .iter()
+            .map(|&boid| world.get::<Transform>(boid).unwrap().translation.truncate())
+            .collect::<Vec<_>>();
+        for (i, a) in positions.iter().enumerate() {
+            for b in &positions[i + 1..] {
+                assert!(
+                    a.distance(*b) >= 6.0 - 1e-3,
+                    "{} and {} are only {} apart",
+                    a,
+                    b,
+                    a.distance(*b)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn boids_slow_down_in_a_crowd_and_recover_alone() {
+        let mut world = test_world(0);
+        world.insert_resource(ComfortZone {
+            enabled: true,
+            ..Default::default()
+        });
+        // Faster than the default, as if its Genes had sped it up
+        let boid = world
+            .spawn()
+            .insert_bundle((
+                Transform::default(),
+                Velocity {
+                    vector: Vec2::ZERO,
+                    max: 1.0,
+                },
+                CruiseSpeed(1.2),
+            ))
+            .id();
+        // A full crowd, all of them within the radius
+        let crowd = (0..10)
+            .map(|i| {
+                let position = Vec2::new(3.0 * i as f32 - 15.0, 10.0);
+                (world.spawn().id(), position)
+            })
+            .collect::<Vec<_>>();
+        let mut stage = SystemStage::single(modulate_speed_by_crowding);
+
+        let mut grid = SpatialGrid::default();
+        let mut entries = crowd.clone();
+        entries.push((boid, Vec2::ZERO));
+        grid.rebuild(SpatialIndex::UniformGrid, entries);
+        world.insert_resource(grid);
+        stage.run(&mut world);
+        assert!((world.get::<Velocity>(boid).unwrap().max - 1.2 * 0.6).abs() < 1e-6);
+
+        // Once the crowd has flown off it speeds back up, from its own CruiseSpeed every time
+        let mut grid = SpatialGrid::default();
+        grid.rebuild(SpatialIndex::UniformGrid, vec![(boid, Vec2::ZERO)]);
+        world.insert_resource(grid);
+        for _ in 0..2 {
+            stage.run(&mut world);
+            assert!((world.get::<Velocity>(boid).unwrap().max - 1.2 * 1.3).abs() < 1e-6);
+        }
+    }
+}
@@ -0,0 +1,102 @@
+use bevy::{
+    ecs::{
+        component::Component,
+        system::{Query, Res, ResMut},
+    },
+    render::color::Color,
+};
+use std::collections::HashMap;
+
+use crate::{ColorMode, Tint};
+
+/// Which flock a boid belongs to, boids are split evenly between FlockCount flocks as they spawn
+#[derive(Component, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct FlockId(pub usize);
+
+/// How many flocks boids are split between
+pub struct FlockCount(pub usize);
+
+impl Default for FlockCount {
+    fn default() -> Self {
+        FlockCount(1)
+    }
+}
+
+/// Colors every boid by the flock it belongs to, so flocks can be told apart at a glance
+///
+/// Flocks without a color of their own get one from a palette spread evenly around the color
+/// wheel, so any number of flocks all look different without picking colors by hand. Used with
+/// ColorMode::Flock
+pub struct FlockColors {
+    pub colors: HashMap<FlockId, Color>,
+}
+
+impl Default for FlockColors {
+    fn default() -> Self {
+        FlockColors {
+            colors: HashMap::new(),
+        }
+    }
+}
+
+/// count colors as different from each other as possible
+pub fn palette(count: usize) -> Vec<Color> {
+    (0..count)
+        .map(|i| Color::hsl(i as f32 * 360.0 / count as f32, 0.8, 0.5))
+        .collect()
+}
+
+pub fn color_by_flock(
+    mode: Res<ColorMode>,
+    count: Res<FlockCount>,
+    mut flock_colors: ResMut<FlockColors>,
+    mut query: Query<(&FlockId, &mut Tint)>,
+) {
+    if *mode != ColorMode::Flock {
+        return;
+    }
+    let colors = &mut flock_colors.colors;
+    for (i, color) in palette(count.0).into_iter().enumerate() {
+        colors.entry(FlockId(i)).or_insert(color);
+    }
+
+    // This runs every frame rather than once at spawn so boids moved to another flock change
+    // color right away
+    for (flock, mut tint) in query.iter_mut() {
+        if let Some(&color) = colors.get(flock) {
+            tint.color = color;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::{
+        schedule::{Stage, SystemStage},
+        world::World,
+    };
+
+    #[test]
+    fn boids_moved_to_another_flock_change_color() {
+        let mut world = World::new();
+        world.insert_resource(ColorMode::Flock);
+        world.insert_resource(FlockCount(2));
+        world.insert_resource(FlockColors::default());
+        let boid = world
+            .spawn()
+            .insert_bundle((FlockId(0), Tint::default()))
+            .id();
+        let mut stage = SystemStage::single(color_by_flock);
+
+        stage.run(&mut world);
+        let first = world.get::<Tint>(boid).unwrap().color;
+        assert_eq!(first, palette(2)[0]);
+
+        *world.get_mut::<FlockId>(boid).unwrap() = FlockId(1);
+        stage.run(&mut world);
+        let second = world.get::<Tint>(boid).unwrap().color;
+        assert_eq!(second, palette(2)[1]);
+        assert_ne!(first, second);
+    }
+}
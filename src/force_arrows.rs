@@ -0,0 +1,126 @@
+use bevy::{
+    asset::{Assets, Handle},
+    ecs::{
+        entity::Entity,
+        system::{Commands, Local, Query, Res, ResMut},
+    },
+    math::{Quat, Vec2, Vec3},
+    prelude::Transform,
+    render::{color::Color, mesh::Mesh},
+};
+use std::collections::HashMap;
+
+use crate::{
+    alarm::Alarm,
+    create_boid_mesh_bundle,
+    debug::{DebugOverlay, DebugOverlays},
+    shapes, BoidAssets, CometTail, Force, Tint,
+};
+
+/// Draws the steering force each boid is currently being pushed by as an arrow, toggled with
+/// DebugOverlays::force_arrows
+///
+/// Unlike its velocity this is what a boid *wants* to do this frame, which makes it easy to see
+/// which behavior is winning when a boid steers somewhere unexpected
+pub struct ForceArrows {
+    /// How many pixels long an arrow is for every unit of force
+    pub scale: f32,
+    pub color: Color,
+}
+
+impl Default for ForceArrows {
+    fn default() -> Self {
+        ForceArrows {
+            // Forces are tiny next to velocities so they need quite the boost to be visible
+            scale: 200.0,
+            color: Color::RED,
+        }
+    }
+}
+
+/// The force acting on every boid and where that boid was, captured right before apply_force
+/// zeroes the forces out
+#[derive(Default)]
+pub struct CapturedForces(HashMap<Entity, (Vec2, Vec2)>);
+
+pub struct ForceArrowAssets {
+    mesh: Handle<Mesh>,
+}
+
+pub fn setup_force_arrows(mut commands: Commands, mut meshes: ResMut<Assets<Mesh>>) {
+    commands.insert_resource(ForceArrowAssets {
+        mesh: meshes.add(shapes::create_arrow_mesh()),
+    });
+}
+
+/// This has to run after every steering system and before apply_force, otherwise the forces are
+/// either still missing some behaviors or already zeroed
+pub fn capture_forces(
+    overlays: Res<DebugOverlays>,
+    mut captured: ResMut<CapturedForces>,
+    query: Query<(Entity, &Transform, &Force)>,
+) {
+    let captured = &mut captured.as_mut().0;
+    captured.clear();
+    if !overlays.shows(DebugOverlay::ForceArrows) {
+        return;
+    }
+    captured.extend(query.iter().map(|(entity, transform, force)| {
+        (entity, (transform.translation.truncate(), force.vector))
+    }));
+}
+
+pub fn draw_force_arrows(
+    mut commands: Commands,
+    arrows: Res<ForceArrows>,
+    captured: Res<CapturedForces>,
+    boid_assets: Res<BoidAssets>,
+    arrow_assets: Res<ForceArrowAssets>,
+    // The arrow entity drawn for every boid
+    mut drawn: Local<HashMap<Entity, Entity>>,
+    mut query: Query<(&mut Transform, &mut Tint)>,
+) {
+    // Arrows of boids whose force wasn't captured this frame, either because they're gone or
+    // because the arrows were toggled off
+    drawn.retain(|boid, arrow| {
+        let keep = captured.0.contains_key(boid);
+        if !keep {
+            commands.entity(*arrow).despawn();
+        }
+        keep
+    });
+
+    for (&boid, &(position, force)) in captured.0.iter() {
+        let transform = Transform {
+            translation: position.extend(DebugOverlay::ForceArrows.z()),
+            rotation: Quat::from_rotation_z(force.y.atan2(force.x)),
+            scale: Vec3::new(force.length() * arrows.scale, 1.0, 1.0),
+        };
+
+        match drawn.get(&boid).map(|&arrow| query.get_mut(arrow)) {
+            Some(Ok((mut arrow_transform, mut tint))) => {
+                *arrow_transform = transform;
+                tint.color = arrows.color;
+            }
+            // The arrow was despawned by something else, there's nothing left to draw it with
+            Some(Err(_)) => {}
+            None => {
+                let mut bundle = create_boid_mesh_bundle(
+                    boid_assets.pipeline.clone(),
+                    arrow_assets.mesh.clone(),
+                    position,
+                );
+                bundle.transform = transform;
+                let arrow = commands
+                    .spawn_bundle(bundle)
+                    .insert(CometTail::default())
+                    .insert(Tint {
+                        color: arrows.color,
+                    })
+                    .insert(Alarm::default())
+                    .id();
+                drawn.insert(boid, arrow);
+            }
+        }
+    }
+}
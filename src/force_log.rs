@@ -0,0 +1,122 @@
+use bevy::{
+    app::AppExit,
+    core::Time,
+    ecs::{
+        event::EventReader,
+        system::{Local, Res, ResMut},
+    },
+};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+/// Writes how hard separation, alignment and cohesion are pushing the whole flock to a CSV file,
+/// one row per frame, for digging into how the behaviors balance out after the fact
+///
+/// Every row has the frame number, seconds since startup and the summed up length of the force
+/// each behavior added across all boids that frame. Rows are buffered and only hit the file every
+/// so often, and everything left is flushed once the app exits
+pub struct ForceLog {
+    pub enabled: bool,
+    pub path: String,
+}
+
+impl Default for ForceLog {
+    fn default() -> Self {
+        ForceLog {
+            enabled: false,
+            path: "forces.csv".to_string(),
+        }
+    }
+}
+
+/// What each behavior has added to the boids' forces so far this frame, the behaviors add to
+/// this themselves since their contributions are all mixed together by the time apply_force
+/// sees the forces
+#[derive(Default)]
+pub struct SteeringTotals {
+    pub separation: f32,
+    pub alignment: f32,
+    pub cohesion: f32,
+}
+
+#[derive(Default)]
+pub struct ForceLogState {
+    writer: Option<BufWriter<File>>,
+    frame: u64,
+    /// Set once the file couldn't be opened or written to, so the error is only reported once
+    failed: bool,
+}
+
+impl ForceLogState {
+    fn flush(&mut self) {
+        if let Some(writer) = &mut self.writer {
+            if let Err(error) = writer.flush() {
+                eprintln!("Couldn't flush the force log: {}", error);
+            }
+        }
+    }
+}
+
+/// This runs in CoreStage::Last so every behavior has had its say this frame, and so that an
+/// AppExit sent this frame is seen before the app actually exits
+pub fn write_force_log(
+    time: Res<Time>,
+    log: Res<ForceLog>,
+    mut totals: ResMut<SteeringTotals>,
+    mut state: Local<ForceLogState>,
+    mut exits: EventReader<AppExit>,
+) {
+    let totals = std::mem::take(totals.as_mut());
+    if exits.iter().next().is_some() {
+        state.flush();
+        return;
+    }
+    if !log.enabled {
+        // Whatever was logged so far is all there is until logging is turned back on
+        state.flush();
+        return;
+    }
+    if state.failed {
+        return;
+    }
+
+    let state = &mut *state;
+    if state.writer.is_none() {
+        match File::create(&log.path) {
+            Ok(file) => {
+                let mut writer = BufWriter::new(file);
+                if let Err(error) = writeln!(writer, "frame,seconds,separation,alignment,cohesion")
+                {
+                    eprintln!("Couldn't write to the force log {}: {}", log.path, error);
+                    state.failed = true;
+                    return;
+                }
+                state.writer = Some(writer);
+            }
+            Err(error) => {
+                eprintln!("Couldn't create the force log {}: {}", log.path, error);
+                state.failed = true;
+                return;
+            }
+        }
+    }
+
+    if let Some(writer) = &mut state.writer {
+        let row = writeln!(
+            writer,
+            "{},{},{},{},{}",
+            state.frame,
+            time.seconds_since_startup(),
+            totals.separation,
+            totals.alignment,
+            totals.cohesion
+        );
+        if let Err(error) = row {
+            eprintln!("Couldn't write to the force log {}: {}", log.path, error);
+            state.failed = true;
+        }
+    }
+    state.frame += 1;
+}
@@ -0,0 +1,220 @@
+use bevy::{
+    ecs::{
+        component::Component,
+        entity::Entity,
+        query::{With, Without},
+        system::{Commands, Query, Res, ResMut},
+    },
+    input::{keyboard::KeyCode, Input},
+    math::Vec2,
+    prelude::Transform,
+};
+
+use std::collections::HashSet;
+
+use crate::{
+    seek_force, sleep::Sleeping, stagger::SteeringStagger, BehaviorToggles, Force, HeadingOffset,
+    SpawnIndex, Velocity,
+};
+
+/// The boid a formation forms up behind
+#[derive(Component)]
+pub struct Leader;
+
+/// A boid with Formation flies in a V behind the Leader, taking up a slot that's offset behind
+/// and to one side of it
+#[derive(Component)]
+pub struct Formation {
+    /// The boid's place in the V, slot 0 and 1 are right behind the leader on its left and
+    /// right, 2 and 3 are behind them and so on
+    ///
+    /// Slots are handed out by assign_formation_slots, None means the boid hasn't been given one
+    /// yet (or there's no leader to form up behind)
+    pub slot: Option<usize>,
+    /// A number between 0 and 1 to determine how interested the boid is in keeping its slot,
+    /// see Seek's interest for how it interacts with other behaviors
+    pub interest: f32,
+}
+
+/// The shape of the V
+pub struct FormationParams {
+    /// How far behind the slots in front of it every row of the V is
+    pub spacing_back: f32,
+    /// How far to the side of the slots in front of it every row of the V is
+    pub spacing_side: f32,
+}
+
+impl Default for FormationParams {
+    fn default() -> Self {
+        FormationParams {
+            spacing_back: 20.0,
+            spacing_side: 15.0,
+        }
+    }
+}
+
+/// The order slots were handed out in
+#[derive(Default)]
+pub struct FormationSlots {
+    /// The leader the slots were handed out behind, when the leader changes everyone gets a new
+    /// slot behind the new one
+    leader: Option<Entity>,
+    /// Every follower by slot, compacted whenever a follower leaves so no gaps open in the V
+    order: Vec<Entity>,
+}
+
+/// Where a slot is relative to the leader in the leader's own frame, x being forward and y left
+fn slot_offset(params: &FormationParams, slot: usize) -> Vec2 {
+    let row = (slot / 2 + 1) as f32;
+    let side = if slot % 2 == 0 { 1.0 } else { -1.0 };
+    Vec2::new(-row * params.spacing_back, side * row * params.spacing_side)
+}
+
+pub fn assign_formation_slots(
+    mut commands: Commands,
+    mut slots: ResMut<FormationSlots>,
+    leaders: Query<(Entity, &Transform), With<Leader>>,
+    mut followers: Query<(Entity, &Transform, &mut Formation), Without<Leader>>,
+) {
+    let slots = slots.as_mut();
+    let leader = leaders.iter().next();
+    let present = followers
+        .iter_mut()
+        .map(|(follower, _, _)| follower)
+        .collect::<HashSet<_>>();
+
+    if leader.map(|(entity, _)| entity) != slots.leader {
+        // The leader is gone but its followers aren't, rather than the V falling apart the
+        // follower right behind it takes over
+        if leader.is_none() && slots.leader.is_some() {
+            if let Some(&successor) = slots
+                .order
+                .iter()
+                .find(|follower| present.contains(follower))
+            {
+                commands
+                    .entity(successor)
+                    .insert(Leader)
+                    .remove::<Formation>();
+            }
+        }
+        slots.leader = leader.map(|(entity, _)| entity);
+        slots.order.clear();
+    }
+
+    let (leader, leader_transform) = match leader {
+        Some(leader) => leader,
+        None => {
+            for (_, _, mut formation) in followers.iter_mut() {
+                formation.slot = None;
+            }
+            return;
+        }
+    };
+
+    slots
+        .order
+        .retain(|follower| *follower != leader && present.contains(follower));
+
+    // Newcomers join the back of the V in the order they arrived, if several arrive at once
+    // (like right after the leader changes) the ones closest to the leader get the front slots
+    let leader_position = leader_transform.translation.truncate();
+    let mut newcomers = followers
+        .iter_mut()
+        .filter(|(follower, _, _)| !slots.order.contains(follower))
+        .map(|(follower, transform, _)| {
+            let distance = transform.translation.truncate().distance(leader_position);
+            (follower, distance)
+        })
+        .collect::<Vec<_>>();
+    newcomers.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap());
+    slots
+        .order
+        .extend(newcomers.into_iter().map(|(follower, _)| follower));
+
+    for (slot, &follower) in slots.order.iter().enumerate() {
+        if let Ok((_, _, mut formation)) = followers.get_mut(follower) {
+            formation.slot = Some(slot);
+        }
+    }
+}
+
+pub fn fly_in_formation(
+    toggles: Res<BehaviorToggles>,
+    params: Res<FormationParams>,
+    stagger: Res<SteeringStagger>,
+    heading_offset: Res<HeadingOffset>,
+    leaders: Query<&Transform, With<Leader>>,
+    mut followers: Query<
+        (&mut Force, &Velocity, &Transform, &Formation, &SpawnIndex),
+        (Without<Leader>, Without<Sleeping>),
+    >,
+) {
+    if !toggles.formation {
+        return;
+    }
+    let leader = match leaders.iter().next() {
+        Some(leader) => leader,
+        None => return,
+    };
+    // Banking rolls the leader around the axis its nose points along, so this is unaffected
+    let forward = (leader.rotation * heading_offset.forward())
+        .truncate()
+        .normalize_or_zero();
+    let left = forward.perp();
+    let leader_position = leader.translation.truncate();
+
+    for (mut force, velocity, Transform { translation, .. }, formation, index) in
+        followers.iter_mut()
+    {
+        if !stagger.is_due(index) {
+            continue;
+        }
+        if let Some(slot) = formation.slot {
+            let offset = slot_offset(&params, slot);
+            let target = leader_position + forward * offset.x + left * offset.y;
+            let force = force.as_mut();
+            force.vector += seek_force(
+                target,
+                translation.truncate(),
+                velocity.vector,
+                force.max,
+                formation.interest,
+            );
+        }
+    }
+}
+
+/// Pressing F puts a flock without a formation into one behind one of its boids, and breaks up
+/// the formation again if there's one already
+pub fn toggle_formation(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    leaders: Query<Entity, With<Leader>>,
+    boids: Query<Entity, (With<Velocity>, Without<Leader>)>,
+) {
+    if !keys.just_pressed(KeyCode::F) {
+        return;
+    }
+
+    if leaders.iter().next().is_some() {
+        for leader in leaders.iter() {
+            commands.entity(leader).remove::<Leader>();
+        }
+        for boid in boids.iter() {
+            commands.entity(boid).remove::<Formation>();
+        }
+        return;
+    }
+
+    let mut boids = boids.iter();
+    if let Some(leader) = boids.next() {
+        commands.entity(leader).insert(Leader);
+        for boid in boids {
+            commands.entity(boid).insert(Formation {
+                slot: None,
+                interest: 1.0,
+            });
+        }
+    }
+}
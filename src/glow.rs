@@ -0,0 +1,238 @@
+use bevy::{
+    asset::{Assets, Handle},
+    ecs::{
+        entity::Entity,
+        query::{With, Without},
+        system::{Commands, Local, Query, Res, ResMut},
+    },
+    input::{keyboard::KeyCode, Input},
+    math::Vec3,
+    prelude::Transform,
+    render::{
+        color::Color,
+        entity::MeshBundle,
+        mesh::{Indices, Mesh},
+        pipeline::{
+            BlendComponent, BlendFactor, BlendOperation, BlendState, PipelineDescriptor,
+            PrimitiveTopology, RenderPipeline, RenderPipelines,
+        },
+        shader::{Shader, ShaderStage, ShaderStages},
+    },
+};
+use std::collections::HashMap;
+
+use crate::{color::vertex_color, compile_shader, Tint, Velocity, FALLBACK_VERTEX_SHADER};
+
+/// Glows sit just behind the boids so the boids themselves always draw on top, while staying in
+/// front of the arena's floor
+const GLOW_Z: f32 = -0.02;
+
+/// Has every boid give off a soft halo of light, for a nighttime look. Toggled with L
+///
+/// Halos are blended additively, so where boids bunch up their halos pile up into a bright bloom
+/// while a lone boid only gets a faint one
+pub struct Glow {
+    pub enabled: bool,
+    /// How far the halo reaches out from the boid's center
+    pub radius: f32,
+    /// How bright the halo is right at the boid's center, it fades to nothing at radius
+    pub intensity: f32,
+    pub color: Color,
+}
+
+impl Default for Glow {
+    fn default() -> Self {
+        Glow {
+            enabled: false,
+            radius: 30.0,
+            intensity: 0.35,
+            color: Color::rgb(1.0, 0.85, 0.5),
+        }
+    }
+}
+
+impl Glow {
+    /// The Tint glows are drawn with, the glow shader takes its alpha as the intensity
+    fn tint(&self) -> Tint {
+        let mut color = self.color;
+        color.set_a(self.intensity.max(0.0));
+        Tint { color }
+    }
+}
+
+pub struct GlowAssets {
+    pipeline: Handle<PipelineDescriptor>,
+    mesh: Handle<Mesh>,
+}
+
+/// A square spanning -1 to 1, with uvs running from one corner to the other for the shader to
+/// work out how far from the center every pixel is
+fn create_glow_mesh() -> Mesh {
+    let positions = vec![
+        [-1.0, -1.0, 0.0],
+        [1.0, -1.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [-1.0, 1.0, 0.0],
+    ];
+    let uvs = vec![[0.0, 1.0], [1.0, 1.0], [1.0, 0.0], [0.0, 0.0]];
+    let vertices = positions.len();
+
+    let mut glow = Mesh::new(PrimitiveTopology::TriangleList);
+    glow.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    // Only the fallback shader looks at this, it's the glow's color everywhere else
+    glow.set_attribute(
+        Mesh::ATTRIBUTE_COLOR,
+        vec![vertex_color(Color::BLACK); vertices],
+    );
+    glow.set_attribute(Mesh::ATTRIBUTE_UV_0, uvs);
+    glow.set_indices(Some(Indices::U32(vec![0, 1, 2, 2, 3, 0])));
+    glow
+}
+
+pub fn setup_glow(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut pipelines: ResMut<Assets<PipelineDescriptor>>,
+    mut shaders: ResMut<Assets<Shader>>,
+) {
+    let mut pipeline = PipelineDescriptor::default_config(ShaderStages {
+        vertex: shaders.add(compile_shader(
+            ShaderStage::Vertex,
+            GLOW_VERTEX_SHADER,
+            FALLBACK_VERTEX_SHADER,
+        )),
+        fragment: Some(shaders.add(compile_shader(
+            ShaderStage::Fragment,
+            GLOW_FRAGMENT_SHADER,
+            FALLBACK_GLOW_FRAGMENT_SHADER,
+        ))),
+    });
+    // Every halo adds its light on top of whatever is behind it instead of covering it up
+    for target in pipeline.color_target_states.iter_mut() {
+        target.blend = Some(BlendState {
+            color: BlendComponent {
+                src_factor: BlendFactor::SrcAlpha,
+                dst_factor: BlendFactor::One,
+                operation: BlendOperation::Add,
+            },
+            alpha: BlendComponent {
+                src_factor: BlendFactor::One,
+                dst_factor: BlendFactor::One,
+                operation: BlendOperation::Add,
+            },
+        });
+    }
+    // Halos overlap each other all the time, writing depth would have the nearest one hide the
+    // ones behind it rather than add up with them
+    if let Some(depth_stencil) = pipeline.depth_stencil.as_mut() {
+        depth_stencil.depth_write_enabled = false;
+    }
+
+    commands.insert_resource(GlowAssets {
+        pipeline: pipelines.add(pipeline),
+        mesh: meshes.add(create_glow_mesh()),
+    });
+}
+
+pub fn toggle_glow(keys: Res<Input<KeyCode>>, mut glow: ResMut<Glow>) {
+    if keys.just_pressed(KeyCode::L) {
+        glow.enabled = !glow.enabled;
+    }
+}
+
+/// Keeps a halo behind every boid while Glow is enabled, and despawns them all once it isn't
+pub fn draw_glows(
+    mut commands: Commands,
+    glow: Res<Glow>,
+    assets: Res<GlowAssets>,
+    boids: Query<(Entity, &Transform), With<Velocity>>,
+    // The halo entity drawn for every boid
+    mut drawn: Local<HashMap<Entity, Entity>>,
+    mut halos: Query<(&mut Transform, &mut Tint), Without<Velocity>>,
+) {
+    // Halos of boids that are gone, or all of them when glowing was toggled off
+    drawn.retain(|&boid, halo| {
+        let keep = glow.enabled && boids.get(boid).is_ok();
+        if !keep {
+            commands.entity(*halo).despawn();
+        }
+        keep
+    });
+    if !glow.enabled {
+        return;
+    }
+
+    let tint = glow.tint();
+    for (boid, transform) in boids.iter() {
+        let halo_transform = Transform {
+            translation: transform.translation.truncate().extend(GLOW_Z),
+            scale: Vec3::new(glow.radius, glow.radius, 1.0),
+            ..Default::default()
+        };
+
+        match drawn.get(&boid).map(|&halo| halos.get_mut(halo)) {
+            Some(Ok((mut transform, mut halo_tint))) => {
+                *transform = halo_transform;
+                halo_tint.color = tint.color;
+            }
+            // The halo was despawned by something else, there's nothing left to draw it with
+            Some(Err(_)) => {}
+            None => {
+                let halo = commands
+                    .spawn_bundle(MeshBundle {
+                        mesh: assets.mesh.clone(),
+                        render_pipelines: RenderPipelines::from_pipelines(vec![
+                            RenderPipeline::new(assets.pipeline.clone()),
+                        ]),
+                        transform: halo_transform,
+                        ..Default::default()
+                    })
+                    .insert(Tint { color: tint.color })
+                    .id();
+                drawn.insert(boid, halo);
+            }
+        }
+    }
+}
+
+const GLOW_VERTEX_SHADER: &str = r"
+#version 450
+layout(location = 0) in vec3 Vertex_Position;
+layout(location = 2) in vec2 Vertex_Uv;
+layout(location = 0) out vec2 v_Uv;
+layout(set = 0, binding = 0) uniform CameraViewProj {
+    mat4 ViewProj;
+};
+layout(set = 1, binding = 0) uniform Transform {
+    mat4 Model;
+};
+void main() {
+    v_Uv = Vertex_Uv;
+    gl_Position = ViewProj * Model * vec4(Vertex_Position, 1.0);
+}
+";
+
+const GLOW_FRAGMENT_SHADER: &str = r"
+#version 450
+layout(location = 0) in vec2 v_Uv;
+layout(location = 0) out vec4 o_Target;
+layout(set = 2, binding = 0) uniform Tint_color {
+    vec4 TintColor;
+};
+void main() {
+    // 0 at the boid's center and 1 at the edge of the circle fitting in the quad
+    float distance = length(v_Uv * 2.0 - 1.0);
+    // Squaring the falloff gives a bright core that fades out softly, rather than a cone
+    float falloff = 1.0 - smoothstep(0.0, 1.0, distance);
+    o_Target = vec4(TintColor.rgb, TintColor.a * falloff * falloff);
+}
+";
+
+/// Used in place of GLOW_FRAGMENT_SHADER when it doesn't compile, adding nothing at all
+const FALLBACK_GLOW_FRAGMENT_SHADER: &str = r"
+#version 450
+layout(location = 0) out vec4 o_Target;
+void main() {
+    o_Target = vec4(0.0);
+}
+";
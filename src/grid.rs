@@ -0,0 +1,469 @@
+use bevy::{
+    ecs::{
+        entity::Entity,
+        query::With,
+        system::{Query, Res, ResMut},
+    },
+    math::Vec2,
+    prelude::Transform,
+};
+use std::collections::{HashMap, HashSet};
+
+use crate::{quadtree::QuadTree, ArenaShape, EdgeBehavior, EdgeBehaviors, Velocity, WorldBounds};
+
+/// How SpatialGrid organizes boids to find the ones around a position quickly
+#[derive(Clone, Copy)]
+pub enum SpatialIndex {
+    /// Square cells of the same size everywhere, cheap to build and best when boids are spread
+    /// out fairly evenly
+    UniformGrid,
+    /// A QuadTree splitting wherever more than capacity boids gather, best when boids clump
+    /// together densely and leave the rest of the world empty
+    QuadTree { capacity: usize },
+}
+
+impl Default for SpatialIndex {
+    fn default() -> Self {
+        SpatialIndex::UniformGrid
+    }
+}
+
+/// How the distance between two positions is measured
+#[derive(Clone, Copy)]
+pub enum DistanceMetric {
+    /// Plain straight line distance
+    Euclidean,
+    /// The world wraps around at its edges along either axis (a cylinder) or both (a torus), so
+    /// two positions near opposite edges of a wrapping axis are actually close to each other
+    /// through the edge
+    Wrapping {
+        /// The size of the world, centered on the origin
+        size: Vec2,
+        /// Whether the left and right edges wrap
+        x: bool,
+        /// Whether the top and bottom edges wrap
+        y: bool,
+    },
+}
+
+impl DistanceMetric {
+    /// The shortest offset that leads from `from` to `to`
+    pub fn offset(&self, from: Vec2, to: Vec2) -> Vec2 {
+        let offset = to - from;
+        match *self {
+            DistanceMetric::Euclidean => offset,
+            DistanceMetric::Wrapping { size, x, y } => {
+                let wrap = |offset: f32, size: f32, wraps: bool| {
+                    if wraps {
+                        offset - size * (offset / size).round()
+                    } else {
+                        offset
+                    }
+                };
+                Vec2::new(wrap(offset.x, size.x, x), wrap(offset.y, size.y, y))
+            }
+        }
+    }
+
+    pub fn distance(&self, from: Vec2, to: Vec2) -> f32 {
+        self.offset(from, to).length()
+    }
+}
+
+/// A uniform grid bucketing every boid by its position, so that finding the boids around some
+/// position only has to look at the few cells that overlap it rather than at the entire flock
+///
+/// With SpatialIndex::QuadTree the boids go into a QuadTree instead, queries work just the same
+/// either way
+pub struct SpatialGrid {
+    /// The width and height of a single cell
+    ///
+    /// Ideally this is about the size of the largest radius that gets queried, so that a query
+    /// only ever has to walk the 3x3 block of cells around a position
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<(Entity, Vec2)>>,
+    tree: Option<QuadTree>,
+    metric: DistanceMetric,
+}
+
+impl SpatialGrid {
+    pub fn new(cell_size: f32) -> Self {
+        SpatialGrid {
+            cell_size,
+            cells: HashMap::new(),
+            tree: None,
+            metric: DistanceMetric::Euclidean,
+        }
+    }
+
+    /// The metric neighbor queries are measured with, anything looking at the positions the
+    /// grid returns should measure them with this as well
+    pub fn metric(&self) -> DistanceMetric {
+        self.metric
+    }
+
+    fn cell_of(&self, position: Vec2) -> (i32, i32) {
+        (
+            (position.x / self.cell_size).floor() as i32,
+            (position.y / self.cell_size).floor() as i32,
+        )
+    }
+
+    /// Replaces everything in the grid with entries, organized by index
+    pub fn rebuild(&mut self, index: SpatialIndex, entries: Vec<(Entity, Vec2)>) {
+        self.cells.clear();
+        self.tree = None;
+        match index {
+            SpatialIndex::UniformGrid => {
+                for (entity, position) in entries {
+                    let cell = self.cell_of(position);
+                    self.cells.entry(cell).or_default().push((entity, position));
+                }
+            }
+            SpatialIndex::QuadTree { capacity } => {
+                self.tree = Some(QuadTree::build(&entries, capacity));
+            }
+        }
+    }
+
+    /// Every entity (and its position) that is within radius of position, if an entity sits
+    /// exactly at position it's included as well so callers should skip themselves. Each entity
+    /// turns up once, even with a radius reaching all the way around a wrapping world
+    pub fn within(&self, position: Vec2, radius: f32) -> impl Iterator<Item = (Entity, Vec2)> + '_ {
+        // Once the radius reaches halfway across a wrapping axis the copies of position searched
+        // from below overlap, and whatever is in the overlap would be found by each of them
+        let overlapping = match self.metric {
+            DistanceMetric::Euclidean => false,
+            DistanceMetric::Wrapping { size, x, y } => {
+                (x && radius >= size.x / 2.0) || (y && radius >= size.y / 2.0)
+            }
+        };
+        let mut seen = overlapping.then(HashSet::new);
+
+        // When the world wraps, the cells near the opposite edge are just as close as the ones
+        // next to position, so those get searched from a copy of position moved across the edge
+        let images = match self.metric {
+            DistanceMetric::Euclidean => vec![position],
+            DistanceMetric::Wrapping { size, x, y } => {
+                let shifts = |position: f32, half_size: f32, size: f32, wraps: bool| {
+                    let mut shifts = vec![0.0];
+                    if !wraps {
+                        return shifts;
+                    }
+                    if position + radius > half_size {
+                        shifts.push(-size);
+                    }
+                    if position - radius < -half_size {
+                        shifts.push(size);
+                    }
+                    shifts
+                };
+                let x_shifts = shifts(position.x, size.x / 2.0, size.x, x);
+                let y_shifts = shifts(position.y, size.y / 2.0, size.y, y);
+                x_shifts
+                    .iter()
+                    .flat_map(|&x| y_shifts.iter().map(move |&y| position + Vec2::new(x, y)))
+                    .collect()
+            }
+        };
+
+        let found = images.into_iter().flat_map(
+            move |image| -> Box<dyn Iterator<Item = (Entity, Vec2)> + '_> {
+                if let Some(tree) = &self.tree {
+                    return Box::new(tree.within(image, radius).into_iter());
+                }
+
+                let (min_x, min_y) = self.cell_of(image - Vec2::splat(radius));
+                let (max_x, max_y) = self.cell_of(image + Vec2::splat(radius));
+                Box::new(
+                    (min_x..=max_x)
+                        .flat_map(move |x| (min_y..=max_y).map(move |y| (x, y)))
+                        .filter_map(move |cell| self.cells.get(&cell))
+                        .flatten()
+                        .copied()
+                        .filter(move |(_, other)| other.distance_squared(image) <= radius * radius),
+                )
+            },
+        );
+        found.filter(move |(entity, _)| seen.as_mut().map_or(true, |seen| seen.insert(*entity)))
+    }
+}
+
+impl Default for SpatialGrid {
+    fn default() -> Self {
+        SpatialGrid::new(50.0)
+    }
+}
+
+/// Every entity in the grid within radius of pos, for systems of your own that want to know
+/// what's around a spot without walking the whole world
+///
+/// This only knows what was in the grid as of update_spatial_grid in CoreStage::PreUpdate, and
+/// an entity sitting exactly at pos (such as the one asking) is included as well. Distances are
+/// measured with the grid's metric so neighbors across a wrapping edge are found too
+pub fn query_neighbors(grid: &SpatialGrid, pos: Vec2, radius: f32) -> Vec<Entity> {
+    grid.within(pos, radius).map(|(entity, _)| entity).collect()
+}
+
+/// Rebuilds the grid from scratch with every boid's current position
+pub fn update_spatial_grid(
+    mut grid: ResMut<SpatialGrid>,
+    bounds: Res<WorldBounds>,
+    arena: Res<ArenaShape>,
+    edges: Res<EdgeBehaviors>,
+    index: Res<SpatialIndex>,
+    query: Query<(Entity, &Transform), With<Velocity>>,
+) {
+    let grid = grid.as_mut();
+    // Coming back in on the other side of a circle doesn't make its sides neighbors
+    let rect = matches!(*arena, ArenaShape::Rect);
+    let x = rect && edges.x == EdgeBehavior::Wrap;
+    let y = rect && edges.y == EdgeBehavior::Wrap;
+    grid.metric = if x || y {
+        DistanceMetric::Wrapping {
+            size: bounds.size,
+            x,
+            y,
+        }
+    } else {
+        DistanceMetric::Euclidean
+    };
+    grid.rebuild(
+        *index,
+        query
+            .iter()
+            .map(|(entity, transform)| (entity, transform.translation.truncate()))
+            .collect(),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::world::World;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::time::Instant;
+
+    /// count entries in a size world, bunched up in a handful of tight clusters with one sitting
+    /// right on the corner so it spills over every wrapping edge, and the rest of the world empty
+    fn clustered_entries(world: &mut World, size: Vec2, count: usize) -> Vec<(Entity, Vec2)> {
+        let mut rng = StdRng::seed_from_u64(7);
+        let half_size = size / 2.0;
+        let centers = [
+            Vec2::new(-half_size.x * 0.5, half_size.y * 0.3),
+            Vec2::new(half_size.x * 0.2, -half_size.y * 0.6),
+            half_size,
+            Vec2::new(-half_size.x, 0.0),
+        ];
+        (0..count)
+            .map(|i| {
+                let center = centers[i % centers.len()];
+                let offset = Vec2::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0)) * 20.0;
+                // Wrapped back in so every entry is inside the world, like boids are
+                let position = center + offset;
+                let position = position - size * (position / size).round();
+                (world.spawn().id(), position)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn wrapping_metric_measures_through_the_edge() {
+        let size = Vec2::new(200.0, 100.0);
+        let torus = DistanceMetric::Wrapping {
+            size,
+            x: true,
+            y: true,
+        };
+        // 5 in from the left edge and 5 in from the right edge are 10 apart through the edge
+        let left = Vec2::new(-95.0, 0.0);
+        let right = Vec2::new(95.0, 0.0);
+        assert!((torus.distance(left, right) - 10.0).abs() < 1e-4);
+        assert!((torus.offset(left, right) - Vec2::new(-10.0, 0.0)).length() < 1e-4);
+        // And the same goes for the top and bottom
+        let top = Vec2::new(0.0, 48.0);
+        let bottom = Vec2::new(0.0, -48.0);
+        assert!((torus.distance(top, bottom) - 4.0).abs() < 1e-4);
+
+        // Only the axes that wrap are measured through their edges
+        let cylinder = DistanceMetric::Wrapping {
+            size,
+            x: true,
+            y: false,
+        };
+        assert!((cylinder.distance(left, right) - 10.0).abs() < 1e-4);
+        assert!((cylinder.distance(top, bottom) - 96.0).abs() < 1e-4);
+        assert!((DistanceMetric::Euclidean.distance(left, right) - 190.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn query_neighbors_finds_everything_within_radius() {
+        let mut world = World::new();
+        let positions = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(3.0, 4.0),
+            // Right on the radius counts, and it's a couple of cells away
+            Vec2::new(-15.0, 0.0),
+            Vec2::new(15.0, 15.0),
+            Vec2::new(40.0, -2.0),
+        ];
+        let entries = positions
+            .iter()
+            .map(|&position| (world.spawn().id(), position))
+            .collect::<Vec<_>>();
+
+        for index in [
+            SpatialIndex::UniformGrid,
+            SpatialIndex::QuadTree { capacity: 1 },
+        ] {
+            let mut grid = SpatialGrid::new(10.0);
+            grid.rebuild(index, entries.clone());
+            let mut found = query_neighbors(&grid, Vec2::ZERO, 15.0);
+            found.sort();
+            let expected = entries[..3]
+                .iter()
+                .map(|&(entity, _)| entity)
+                .collect::<Vec<_>>();
+            assert_eq!(found, expected);
+            assert!(query_neighbors(&grid, Vec2::new(100.0, 100.0), 15.0).is_empty());
+        }
+    }
+
+    #[test]
+    fn radius_past_half_a_wrapping_world_finds_everything_once() {
+        let mut world = World::new();
+        let positions = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(-45.0, 40.0),
+            Vec2::new(45.0, -40.0),
+            Vec2::new(20.0, 10.0),
+        ];
+        let entries = positions
+            .iter()
+            .map(|&position| (world.spawn().id(), position))
+            .collect::<Vec<_>>();
+        let mut expected = entries
+            .iter()
+            .map(|&(entity, _)| entity)
+            .collect::<Vec<_>>();
+        expected.sort();
+
+        for index in [
+            SpatialIndex::UniformGrid,
+            SpatialIndex::QuadTree { capacity: 1 },
+        ] {
+            let mut grid = SpatialGrid::new(10.0);
+            grid.metric = DistanceMetric::Wrapping {
+                size: Vec2::new(100.0, 100.0),
+                x: true,
+                y: true,
+            };
+            grid.rebuild(index, entries.clone());
+            // Nothing in a 100x100 torus is further than about 71 from anything else
+            for radius in [50.0, 80.0, 150.0] {
+                let mut found = query_neighbors(&grid, Vec2::new(40.0, 40.0), radius);
+                found.sort();
+                assert_eq!(found, expected, "radius {}", radius);
+            }
+        }
+    }
+
+    #[test]
+    fn within_matches_a_linear_scan() {
+        let mut world = World::new();
+        let size = Vec2::new(400.0, 300.0);
+        let entries = clustered_entries(&mut world, size, 400);
+        let metrics = [
+            DistanceMetric::Euclidean,
+            DistanceMetric::Wrapping {
+                size,
+                x: true,
+                y: false,
+            },
+            DistanceMetric::Wrapping {
+                size,
+                x: true,
+                y: true,
+            },
+        ];
+        // Every entry itself, and the corners and edges which only wrapping reaches past
+        let positions = entries
+            .iter()
+            .map(|&(_, position)| position)
+            .chain([size / 2.0, -size / 2.0, Vec2::new(size.x / 2.0, 0.0)])
+            .collect::<Vec<_>>();
+
+        for index in [
+            SpatialIndex::UniformGrid,
+            SpatialIndex::QuadTree { capacity: 8 },
+        ] {
+            for metric in metrics {
+                let mut grid = SpatialGrid::new(25.0);
+                grid.metric = metric;
+                grid.rebuild(index, entries.clone());
+                for &position in positions.iter() {
+                    for radius in [5.0, 25.0, 60.0] {
+                        let mut found = query_neighbors(&grid, position, radius);
+                        found.sort();
+                        let mut expected = entries
+                            .iter()
+                            .filter(|&&(_, other)| metric.distance(position, other) <= radius)
+                            .map(|&(entity, _)| entity)
+                            .collect::<Vec<_>>();
+                        expected.sort();
+                        assert_eq!(found, expected, "around {} within {}", position, radius);
+                    }
+                }
+            }
+        }
+    }
+
+    /// How long each SpatialIndex takes to rebuild and then find every entry's neighbors on a
+    /// clustered flock, which is where the QuadTree is meant to pull ahead of the uniform grid
+    ///
+    /// Timings only mean something in release, run it with
+    /// `cargo test --release clustered_queries -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_clustered_queries() {
+        let mut world = World::new();
+        let size = Vec2::new(1280.0, 720.0);
+        let entries = clustered_entries(&mut world, size, 5000);
+        let metric = DistanceMetric::Wrapping {
+            size,
+            x: true,
+            y: true,
+        };
+        let rounds = 20;
+
+        let mut totals = Vec::new();
+        for index in [
+            SpatialIndex::UniformGrid,
+            SpatialIndex::QuadTree { capacity: 8 },
+        ] {
+            let mut grid = SpatialGrid::new(25.0);
+            grid.metric = metric;
+            let mut total = 0;
+            let start = Instant::now();
+            for _ in 0..rounds {
+                grid.rebuild(index, entries.clone());
+                total = entries
+                    .iter()
+                    .map(|&(_, position)| grid.within(position, 25.0).count())
+                    .sum::<usize>();
+            }
+            let name = match index {
+                SpatialIndex::UniformGrid => "uniform grid",
+                SpatialIndex::QuadTree { .. } => "quadtree",
+            };
+            println!(
+                "{}: {:?} per frame for {} neighbors",
+                name,
+                start.elapsed() / rounds,
+                total
+            );
+            totals.push(total);
+        }
+        assert_eq!(totals[0], totals[1]);
+    }
+}
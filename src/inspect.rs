@@ -0,0 +1,392 @@
+use bevy::{
+    asset::Handle,
+    ecs::{
+        component::Component,
+        entity::Entity,
+        query::{With, Without},
+        system::{Commands, Local, Query, Res, ResMut},
+    },
+    input::{keyboard::KeyCode, mouse::MouseButton, Input},
+    math::{Quat, Vec2, Vec3},
+    prelude::Transform,
+    render::{camera::OrthographicProjection, color::Color, draw::Visible, mesh::Mesh},
+    window::Windows,
+};
+use std::num::FpCategory;
+
+use crate::{
+    alarm::Alarm,
+    create_boid_mesh_bundle, cursor_world_position,
+    debug::{DebugOverlay, OverlayDrawing},
+    flocking::FlockingParams,
+    grid::SpatialGrid,
+    shapes, CometTail, Tint, Velocity,
+};
+
+/// Shows the speed and heading of whichever boid the cursor is hovering over in the window's
+/// title, and puts the original title back once the cursor isn't over any boid
+pub struct HoverInspector {
+    /// How close the cursor has to be to a boid to be hovering it
+    pub radius: f32,
+    /// The boid currently being hovered
+    pub hovered: Option<Entity>,
+}
+
+impl Default for HoverInspector {
+    fn default() -> Self {
+        HoverInspector {
+            radius: 10.0,
+            hovered: None,
+        }
+    }
+}
+
+pub fn inspect_hovered(
+    mut inspector: ResMut<HoverInspector>,
+    mut windows: ResMut<Windows>,
+    grid: Res<SpatialGrid>,
+    // The window's own title, kept around to restore it once nothing is hovered anymore
+    mut original_title: Local<Option<String>>,
+    boids: Query<&Velocity>,
+) {
+    let inspector = inspector.as_mut();
+    let window = match windows.get_primary_mut() {
+        Some(window) => window,
+        None => return,
+    };
+
+    let radius = inspector.radius;
+    let metric = grid.metric();
+    let hovered = cursor_world_position(window).and_then(|cursor| {
+        let nearby = grid
+            .within(cursor, radius)
+            .map(|(boid, position)| (boid, metric.distance(cursor, position)))
+            .collect::<Vec<_>>();
+        // Sticking with the hovered boid for as long as the cursor is still over it, rather than
+        // always jumping to the nearest one, stops it from flickering between two close boids
+        inspector
+            .hovered
+            .filter(|hovered| nearby.iter().any(|(boid, _)| boid == hovered))
+            .or_else(|| {
+                nearby
+                    .iter()
+                    .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+                    .map(|&(boid, _)| boid)
+            })
+    });
+    let hovered = hovered.and_then(|boid| boids.get(boid).ok().map(|velocity| (boid, velocity)));
+    inspector.hovered = hovered.map(|(boid, _)| boid);
+
+    match hovered {
+        Some((boid, velocity)) => {
+            if original_title.is_none() {
+                *original_title = Some(window.title().to_string());
+            }
+            // A boid without velocity has no heading to speak of, same as in update_boids
+            let heading = if velocity.vector.length().classify() != FpCategory::Zero {
+                let heading = Vec2::new(1.0, 0.0).angle_between(velocity.vector);
+                format!("{:.0}°", heading.to_degrees())
+            } else {
+                "-".to_string()
+            };
+            window.set_title(format!(
+                "Boid {:?}: speed {:.2}, heading {}",
+                boid,
+                velocity.vector.length(),
+                heading
+            ));
+        }
+        None => {
+            if let Some(title) = original_title.take() {
+                window.set_title(title);
+            }
+        }
+    }
+}
+
+/// Marks the boid picked out for a closer look, middle clicking a boid selects it and middle
+/// clicking anywhere else clears the selection
+#[derive(Component)]
+pub struct Selected;
+
+pub fn select_hovered(
+    mut commands: Commands,
+    inspector: Res<HoverInspector>,
+    buttons: Res<Input<MouseButton>>,
+    selected: Query<Entity, With<Selected>>,
+) {
+    if !buttons.just_pressed(MouseButton::Middle) {
+        return;
+    }
+    for boid in selected.iter() {
+        commands.entity(boid).remove::<Selected>();
+    }
+    if let Some(boid) = inspector.hovered {
+        commands.entity(boid).insert(Selected);
+    }
+}
+
+/// Marks a boid pinned in place as an anchor, it doesn't move no matter what pushes it but the
+/// boids around it still see it and flock around it like any other. Pressing I pins the Selected
+/// boid or unpins it if it's already pinned
+///
+/// A pinned boid keeps the velocity it had when it was pinned, so it keeps facing the same way
+/// and neighbors still align with it
+#[derive(Component)]
+pub struct Pinned;
+
+pub fn toggle_pin(
+    mut commands: Commands,
+    keys: Res<Input<KeyCode>>,
+    selected: Query<(Entity, Option<&Pinned>), With<Selected>>,
+) {
+    if !keys.just_pressed(KeyCode::I) {
+        return;
+    }
+    for (boid, pinned) in selected.iter() {
+        if pinned.is_some() {
+            commands.entity(boid).remove::<Pinned>();
+        } else {
+            commands.entity(boid).insert(Pinned);
+        }
+    }
+}
+
+/// Keeps the camera centered on the Selected boid, gliding after it rather than snapping to it so
+/// following a boid through a sharp turn doesn't make the whole world lurch. Toggled with V
+///
+/// Nothing is followed while no boid is selected, following turns itself off when the boid being
+/// followed is despawned. Zooming works just the same while following, panning gets pulled back
+/// to the boid
+pub struct CameraFollow {
+    pub enabled: bool,
+    /// How much of the way to the boid the camera moves every frame, 1 snaps right onto it
+    pub smoothing: f32,
+}
+
+impl Default for CameraFollow {
+    fn default() -> Self {
+        CameraFollow {
+            enabled: false,
+            smoothing: 0.1,
+        }
+    }
+}
+
+pub fn toggle_camera_follow(keys: Res<Input<KeyCode>>, mut follow: ResMut<CameraFollow>) {
+    if keys.just_pressed(KeyCode::V) {
+        follow.enabled = !follow.enabled;
+    }
+}
+
+pub fn follow_selected(
+    mut follow: ResMut<CameraFollow>,
+    // The boid that was followed last, to tell it being despawned apart from it being deselected
+    mut followed: Local<Option<Entity>>,
+    selected: Query<(Entity, &Transform), (With<Selected>, Without<OrthographicProjection>)>,
+    exists: Query<Entity>,
+    mut cameras: Query<&mut Transform, With<OrthographicProjection>>,
+) {
+    if !follow.enabled {
+        *followed = None;
+        return;
+    }
+    let (boid, target) = match selected.iter().next() {
+        Some((boid, transform)) => (boid, transform.translation.truncate()),
+        None => {
+            if let Some(boid) = followed.take() {
+                if exists.get(boid).is_err() {
+                    follow.enabled = false;
+                }
+            }
+            return;
+        }
+    };
+    *followed = Some(boid);
+
+    let smoothing = follow.smoothing.clamp(0.0, 1.0);
+    for mut camera in cameras.iter_mut() {
+        // Only x and y, the camera has to stay where it is along z to see anything
+        let position = camera.translation.truncate();
+        let position = position + (target - position) * smoothing;
+        camera.translation.x = position.x;
+        camera.translation.y = position.y;
+    }
+}
+
+/// The colors the Selected boid's perception radii are drawn in, see
+/// DebugOverlay::PerceptionRadii
+pub struct PerceptionRadiiColors {
+    pub separation: Color,
+    pub alignment: Color,
+    pub cohesion: Color,
+}
+
+impl Default for PerceptionRadiiColors {
+    fn default() -> Self {
+        PerceptionRadiiColors {
+            separation: Color::RED,
+            alignment: Color::GREEN,
+            cohesion: Color::BLUE,
+        }
+    }
+}
+
+/// Draws a ring for each of the separation, alignment and cohesion radii around the Selected
+/// boid, only around that one boid since rings around every boid would bury the flock
+pub fn draw_perception_radii(
+    drawing: OverlayDrawing,
+    params: Res<FlockingParams>,
+    colors: Res<PerceptionRadiiColors>,
+    mut rings: Local<Option<[Entity; 3]>>,
+    selected: Query<&Transform, With<Selected>>,
+    mut drawn: Query<(&mut Transform, &mut Visible, &mut Tint), Without<Selected>>,
+) {
+    let OverlayDrawing {
+        overlays,
+        mut commands,
+        assets,
+        mut meshes,
+    } = drawing;
+    let show = overlays.shows(DebugOverlay::PerceptionRadii);
+    let rings = match *rings {
+        Some(rings) => rings,
+        None if show => {
+            let mesh = meshes.add(shapes::create_ring_mesh(64, 0.02));
+            let mut spawn_ring = || {
+                let mut bundle =
+                    create_boid_mesh_bundle(assets.pipeline.clone(), mesh.clone(), Vec2::ZERO);
+                bundle.visible.is_visible = false;
+                commands
+                    .spawn_bundle(bundle)
+                    .insert(CometTail::default())
+                    .insert(Tint::default())
+                    .insert(Alarm::default())
+                    .id()
+            };
+            // The rings show up from the next frame on, once they have actually been spawned
+            *rings = Some([spawn_ring(), spawn_ring(), spawn_ring()]);
+            return;
+        }
+        None => return,
+    };
+
+    let position = selected
+        .iter()
+        .next()
+        .map(|transform| transform.translation.truncate());
+    for (ring, radius, color) in [
+        (rings[0], params.separation_radius, colors.separation),
+        (rings[1], params.alignment_radius, colors.alignment),
+        (rings[2], params.cohesion_radius, colors.cohesion),
+    ] {
+        if let Ok((mut transform, mut visible, mut tint)) = drawn.get_mut(ring) {
+            match position {
+                Some(position) if show => {
+                    visible.is_visible = true;
+                    transform.translation = position.extend(DebugOverlay::PerceptionRadii.z());
+                    transform.scale = Vec3::new(radius, radius, 1.0);
+                    tint.color = color;
+                }
+                _ => visible.is_visible = false,
+            }
+        }
+    }
+}
+
+/// How the Selected boid's field of view is drawn, see DebugOverlay::VisionCone
+pub struct VisionCone {
+    /// Translucent so the boids in view still show through
+    pub color: Color,
+    /// How many triangles the cone's arc is made of
+    pub segments: u32,
+}
+
+impl Default for VisionCone {
+    fn default() -> Self {
+        VisionCone {
+            color: Color::rgba(1.0, 1.0, 0.4, 0.15),
+            segments: 32,
+        }
+    }
+}
+
+/// The entity the vision cone is drawn with, its mesh and the field of view it was built for
+pub struct VisionConeMesh {
+    entity: Entity,
+    mesh: Handle<Mesh>,
+    fov_degrees: f32,
+}
+
+/// Draws the Selected boid's field of view as a wedge out to cohesion_radius, turning along with
+/// its heading
+pub fn draw_vision_cone(
+    drawing: OverlayDrawing,
+    params: Res<FlockingParams>,
+    cone: Res<VisionCone>,
+    mut drawn: Local<Option<VisionConeMesh>>,
+    selected: Query<(&Transform, &Velocity), With<Selected>>,
+    mut wedges: Query<(&mut Transform, &mut Visible, &mut Tint), Without<Selected>>,
+) {
+    let OverlayDrawing {
+        overlays,
+        mut commands,
+        assets,
+        mut meshes,
+    } = drawing;
+    let show = overlays.shows(DebugOverlay::VisionCone);
+    let drawn = match drawn.as_mut() {
+        Some(drawn) => drawn,
+        None if show => {
+            let angle = params.fov_degrees.to_radians();
+            let mesh = meshes.add(shapes::create_sector_mesh(cone.segments, angle));
+            let mut bundle =
+                create_boid_mesh_bundle(assets.pipeline.clone(), mesh.clone(), Vec2::ZERO);
+            bundle.visible.is_visible = false;
+            // The cone shows up from the next frame on, once it has actually been spawned
+            let entity = commands
+                .spawn_bundle(bundle)
+                .insert(CometTail::default())
+                .insert(Tint { color: cone.color })
+                .insert(Alarm::default())
+                .id();
+            *drawn = Some(VisionConeMesh {
+                entity,
+                mesh,
+                fov_degrees: params.fov_degrees,
+            });
+            return;
+        }
+        None => return,
+    };
+
+    if drawn.fov_degrees != params.fov_degrees {
+        if let Some(mesh) = meshes.get_mut(&drawn.mesh) {
+            *mesh = shapes::create_sector_mesh(cone.segments, params.fov_degrees.to_radians());
+            drawn.fov_degrees = params.fov_degrees;
+        }
+    }
+
+    let (mut transform, mut visible, mut tint) = match wedges.get_mut(drawn.entity) {
+        Ok(wedge) => wedge,
+        Err(_) => return,
+    };
+    match selected.iter().next() {
+        Some((boid, velocity)) if show => {
+            visible.is_visible = true;
+            transform.translation = boid
+                .translation
+                .truncate()
+                .extend(DebugOverlay::VisionCone.z());
+            // Like the boid itself the cone keeps facing the way it was going once it stops
+            if velocity.vector.length().classify() != FpCategory::Zero {
+                transform.rotation =
+                    Quat::from_rotation_z(velocity.vector.y.atan2(velocity.vector.x));
+            }
+            let radius = params.cohesion_radius;
+            transform.scale = Vec3::new(radius, radius, 1.0);
+            tint.color = cone.color;
+        }
+        _ => visible.is_visible = false,
+    }
+}
@@ -1,24 +1,118 @@
 use bevy::{
-    app::App,
-    asset::Assets,
+    app::{App, CoreStage, StartupStage},
+    asset::{AssetServer, Assets},
+    core::Time,
     ecs::{
         component::Component,
         entity::Entity,
-        system::{Commands, Query, Res, ResMut},
+        query::{With, Without},
+        schedule::{ParallelSystemDescriptorCoercion, SystemLabel, SystemStage},
+        system::{Commands, Local, Query, Res, ResMut, SystemParam},
     },
-    math::{Quat, Vec2},
+    input::{keyboard::KeyCode, mouse::MouseButton, Input},
+    math::{Quat, Vec2, Vec3, Vec4},
     prelude::{Handle, Transform},
+    reflect::TypeUuid,
     render::{
+        camera::OrthographicProjection,
+        color::Color,
+        draw::Visible,
         entity::{MeshBundle, OrthographicCameraBundle},
-        mesh::{Indices, Mesh},
-        pipeline::{PipelineDescriptor, PrimitiveTopology, RenderPipeline, RenderPipelines},
+        mesh::Mesh,
+        pipeline::{Face, FrontFace, PipelineDescriptor, RenderPipeline, RenderPipelines},
+        render_graph::{base, RenderGraph, RenderResourcesNode},
+        renderer::RenderResources,
         shader::{Shader, ShaderStage, ShaderStages},
+        RenderStage,
     },
-    window::Windows,
+    sprite::ColorMaterial,
+    window::{Window, Windows},
     DefaultPlugins,
 };
-use rand::{thread_rng, Rng};
-use std::num::FpCategory;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    f32::consts::{PI, TAU},
+    num::FpCategory,
+};
+
+mod alarm;
+mod attractors;
+mod budget;
+#[cfg(feature = "frame-capture")]
+mod capture;
+mod chatter;
+mod color;
+mod compass;
+mod custom;
+mod debug;
+#[cfg(feature = "fixed-point")]
+mod fixed;
+mod flocking;
+mod flocks;
+mod force_arrows;
+mod force_log;
+mod formation;
+mod glow;
+mod grid;
+mod inspect;
+mod metrics;
+mod mood;
+mod obstacles;
+mod path;
+mod predators;
+mod quadtree;
+mod render_throttle;
+mod reproduction;
+mod ribbons;
+mod scene;
+mod scent;
+mod shapes;
+mod sleep;
+mod sprites;
+mod stagger;
+#[cfg(test)]
+mod test_world;
+mod tethers;
+mod trackpad;
+
+use alarm::{Alarm, AlarmParams};
+use attractors::AttractorParams;
+use budget::FrameBudget;
+use chatter::NeighborLines;
+use compass::Compass;
+use custom::CustomSteering;
+use debug::{DebugOverlay, DebugOverlays, OverlayDrawing};
+use flocking::{
+    Alignment, Awareness, AwarenessFade, Cohesion, CollisionLayer, CollisionMatrix, ComfortZone,
+    CruiseSpeed, ExploreSchedule, FlockingParams, IdleCruising, MinimumSpacing, NeighborWeighting,
+    Neighbors, PerceptionNoise, PresetSelection, Regroup, Separation, SeparationMath,
+    SpeedController, SpeedScaledSeparation, StalemateBreaking, TargetAvgSpeed,
+};
+use flocks::{FlockColors, FlockCount, FlockId};
+use force_arrows::{CapturedForces, ForceArrows};
+use force_log::{ForceLog, SteeringTotals};
+use formation::{FormationParams, FormationSlots};
+use glow::Glow;
+use grid::{SpatialGrid, SpatialIndex};
+use inspect::{CameraFollow, HoverInspector, PerceptionRadiiColors, Pinned, VisionCone};
+use metrics::{ClusterDetection, FlockMetrics, MetricsWindow, RunDuration};
+use mood::{Mood, MoodGauge};
+use obstacles::{
+    AvoidObstacles, NearbyObstacles, Obstacle, ObstacleGrid, ObstacleLayout, ObstacleParams,
+};
+use path::{FollowPath, Path};
+use predators::PredatorParams;
+use render_throttle::{RenderThrottle, WarmUp};
+use reproduction::{BirthTimer, Genes, Reproduction};
+use ribbons::RibbonTrails;
+use scene::Scene;
+use scent::ScentField;
+use shapes::BoidSilhouette;
+use sleep::{Settling, SleepParams, Sleeping};
+use sprites::{BoidSprite, SpriteMode};
+use stagger::{StaggeredForce, SteeringStagger};
+use tethers::TetherParams;
+use trackpad::TrackpadCamera;
 
 #[derive(Component)]
 struct Velocity {
@@ -60,15 +154,1140 @@ struct SeekCursor {
     interest: f32,
 }
 
+/// Lets a boid bank (roll around its own heading) into its turns
+#[derive(Component, Default)]
+struct Bank {
+    /// The heading the boid had on the previous frame, used to find how fast it's turning
+    previous_heading: Option<f32>,
+    /// How far the boid is currently rolled, negative rolls bank into left (counterclockwise)
+    /// turns and positive ones into right turns
+    roll: f32,
+}
+
+/// How strongly boids with Bank bank into their turns
+struct BankingParams {
+    /// Radians of roll for every radian per frame the boid is turning
+    roll_per_turn: f32,
+    /// The furthest a boid will ever roll in either direction
+    max_roll: f32,
+    /// A number between 0 and 1 for how much of the way towards its target roll a boid rolls
+    /// every frame, lower numbers mean smoother but slower rolling
+    responsiveness: f32,
+}
+
+impl Default for BankingParams {
+    fn default() -> Self {
+        BankingParams {
+            roll_per_turn: 20.0,
+            max_roll: PI / 3.0,
+            responsiveness: 0.1,
+        }
+    }
+}
+
+/// Stops every steering behavior from pushing boids at all while physics keeps running, so boids
+/// coast along whatever velocity they had and any tangle they're in plays out on its own, handy
+/// for seeing what a separation explosion does once nothing is feeding it. Toggled with space
+///
+/// The behaviors keep their BehaviorToggles, unfreezing picks up right where they were
+#[derive(Default)]
+struct FreezeSteering(bool);
+
+/// Pins every boid in place while it keeps steering and turning to face its velocity, which
+/// isolates the heading math in update_boids (and any HeadingOffset) so it can be checked by eye,
+/// e.g. against boids seeking the cursor
+///
+/// Boids' velocities carry on changing as usual, they just aren't moved by them
+#[derive(Default)]
+struct RotationOnly(bool);
+
+/// Switches for turning each steering behavior on or off while the simulation runs, which is
+/// handy for finding out which behavior is behind some pattern the flock is making
+///
+/// Each can be flipped with the number key next to it, with everything off boids just coast along
+/// whatever velocity they have. The behaviors without a key of their own (soft edges, the speed
+/// controller, stalemate breaking, idle cruising, fleeing predators, scent and custom steering)
+/// only steer while at least one of these is on
+struct BehaviorToggles {
+    /// 1
+    seek: bool,
+    /// 2
+    seek_cursor: bool,
+    /// 3
+    separation: bool,
+    /// 4
+    alignment: bool,
+    /// 5
+    cohesion: bool,
+    /// 6
+    regroup: bool,
+    /// 7
+    formation: bool,
+    /// 8
+    attractors: bool,
+    /// 9
+    obstacles: bool,
+    /// 0, off to begin with since it takes over the whole flock
+    follow_path: bool,
+}
+
+impl Default for BehaviorToggles {
+    fn default() -> Self {
+        BehaviorToggles {
+            seek: true,
+            seek_cursor: true,
+            separation: true,
+            alignment: true,
+            cohesion: true,
+            regroup: true,
+            formation: true,
+            attractors: true,
+            obstacles: true,
+            follow_path: false,
+        }
+    }
+}
+
+impl BehaviorToggles {
+    /// Whether every behavior is switched off, see BehaviorToggles
+    fn all_off(&self) -> bool {
+        !(self.seek
+            || self.seek_cursor
+            || self.separation
+            || self.alignment
+            || self.cohesion
+            || self.regroup
+            || self.formation
+            || self.attractors
+            || self.obstacles
+            || self.follow_path)
+    }
+}
+
+/// Below min_speed a boid's heading is too noisy to face it directly, so update_boids instead
+/// turns the boid towards it just a little every frame
+///
+/// This is on top of boids with no velocity at all keeping whatever heading they had
+struct RotationSmoothing {
+    min_speed: f32,
+    /// A number between 0 and 1 for how much of the way towards a slow boid's heading it turns
+    /// every frame, 0 holds the boid's rotation until it's going faster than min_speed
+    slow_turn: f32,
+}
+
+impl Default for RotationSmoothing {
+    fn default() -> Self {
+        RotationSmoothing {
+            min_speed: 0.05,
+            slow_turn: 0.05,
+        }
+    }
+}
+
+/// The direction the boid art's nose points in before it's rotated, as an angle counterclockwise
+/// from +x, so that art that isn't modeled pointing along +x (like the triangle is) still faces
+/// the boid's heading
+///
+/// Art pointing up would use an offset of PI / 2
+#[derive(Default)]
+struct HeadingOffset(f32);
+
+impl HeadingOffset {
+    /// The rotation that turns the art's nose to face heading
+    fn rotation(&self, heading: f32) -> Quat {
+        Quat::from_rotation_z(heading - self.0)
+    }
+
+    /// The direction the art's nose points in before it's rotated
+    fn forward(&self) -> Vec3 {
+        Vec3::new(self.0.cos(), self.0.sin(), 0.0)
+    }
+}
+
+/// Level of detail for large flocks, boids that are off screen or too small to make out at the
+/// camera's zoom aren't drawn at all
+///
+/// Hidden boids are only hidden, they keep being simulated and flocking with everyone else
+struct LodParams {
+    /// The size of a boid in world units
+    boid_size: f32,
+    /// How many pixels big a boid has to appear on screen to be drawn
+    min_screen_size: f32,
+    /// How far (in pixels) past the edge of the screen a boid is still drawn, so boids that are
+    /// only halfway off screen don't pop out of existence
+    margin: f32,
+}
+
+impl Default for LodParams {
+    fn default() -> Self {
+        LodParams {
+            boid_size: 10.0,
+            min_screen_size: 2.0,
+            margin: 10.0,
+        }
+    }
+}
+
+/// The order a boid was spawned in, unlike query order this is the same from run to run
+#[derive(Component, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+struct SpawnIndex(u64);
+
+/// The SpawnIndex the next boid gets, shared by everything that spawns boids so that no two of
+/// them ever get the same one
+#[derive(Default)]
+struct NextSpawnIndex(u64);
+
+impl NextSpawnIndex {
+    fn take(&mut self) -> SpawnIndex {
+        let index = SpawnIndex(self.0);
+        self.0 += 1;
+        index
+    }
+}
+
+/// Bevy doesn't guarantee the order queries visit entities in, and since floating point addition
+/// isn't associative anything summed across boids can come out slightly different every run
+///
+/// While this is on, those sums visit boids in SpawnIndex order instead, making runs with the
+/// same SimRng seed bit-identical at some cost to performance
+#[derive(Default)]
+struct DeterministicMode(bool);
+
+/// The random number generator everything random in the simulation draws from, insert one made
+/// with StdRng::seed_from_u64 for reproducible runs
+struct SimRng(StdRng);
+
+impl Default for SimRng {
+    fn default() -> Self {
+        SimRng(StdRng::from_entropy())
+    }
+}
+
+/// The handles every boid is drawn with, created once in start
+struct BoidAssets {
+    pipeline: Handle<PipelineDescriptor>,
+    mesh: Handle<Mesh>,
+    /// What boids are drawn with instead of the mesh in SpriteMode::Sprite
+    sprite: Option<BoidSprite>,
+}
+
+/// How many boids the flock is made of, boids keep being spawned until there are this many
+///
+/// This is kept up while other systems take boids away, so a steady population can be watched
+/// over a long run
+struct TargetPopulation(usize);
+
+impl Default for TargetPopulation {
+    fn default() -> Self {
+        TargetPopulation(100)
+    }
+}
+
+/// Whether boids past the TargetPopulation get despawned, newest first, also at SpawnRate
+///
+/// Without this lowering the TargetPopulation only stops new boids from spawning
+#[derive(Default)]
+struct DespawnExcess(bool);
+
+/// How many boids are spawned every second while there are fewer than TargetPopulation of them,
+/// a rate of 0 spawns all of the missing boids at once
+///
+/// Trickling boids in from a small seed is a nice way to watch a flock form
+#[derive(Default)]
+struct SpawnRate(f32);
+
+/// Where in WorldBounds boids are spawned
+#[derive(Clone, Copy)]
+enum SpawnPattern {
+    /// Anywhere at all
+    Random,
+    /// Evenly spaced rows and columns filling the world, with a spot for every boid of the
+    /// TargetPopulation
+    Grid,
+    /// Anywhere within radius of the middle of the world
+    Cluster { radius: f32 },
+}
+
+impl Default for SpawnPattern {
+    fn default() -> Self {
+        SpawnPattern::Random
+    }
+}
+
+/// How far boids are nudged off the spot their SpawnPattern picked for them, in a random
+/// direction, which softens rigid patterns like Grid into something more natural
+///
+/// The nudges come from SimRng, so they're the same every run with the same seed
+#[derive(Default)]
+struct SpawnJitter(f32);
+
+/// Which way boids are flying when they're spawned, at their full speed unless they start at
+/// rest
+#[derive(Clone, Copy)]
+enum InitialHeading {
+    /// Not moving at all, boids pick up a heading from whatever steers them first
+    AtRest,
+    /// All facing the same direction, so the flock starts out already coherent
+    Facing(Vec2),
+    /// Away from the middle of the world, which pairs nicely with SpawnPattern::Cluster, a boid
+    /// right in the middle has no away and faces +x instead
+    Outward,
+}
+
+impl Default for InitialHeading {
+    fn default() -> Self {
+        InitialHeading::AtRest
+    }
+}
+
+impl InitialHeading {
+    /// The velocity a boid spawned at position starts with
+    fn velocity(&self, position: Vec2, speed: f32) -> Vec2 {
+        let direction = match *self {
+            InitialHeading::AtRest => Vec2::ZERO,
+            InitialHeading::Facing(direction) => direction.normalize_or_zero(),
+            InitialHeading::Outward if position == Vec2::ZERO => Vec2::X,
+            InitialHeading::Outward => position.normalize_or_zero(),
+        };
+        direction * speed
+    }
+}
+
+/// Alt left clicking spawns a boid at the cursor, holding the button down keeps spraying them
+/// out at rate every second for as long as it's held
+///
+/// Sprayed boids count towards the TargetPopulation so they aren't taken back out by
+/// DespawnExcess, no more are sprayed once there are max_boids
+struct CursorSpray {
+    rate: f32,
+    max_boids: usize,
+    /// The fastest sprayed boids start out flying, each in a random direction, which fans them out
+    /// like a fountain
+    spread: f32,
+}
+
+impl Default for CursorSpray {
+    fn default() -> Self {
+        CursorSpray {
+            rate: 20.0,
+            max_boids: 2000,
+            spread: 0.5,
+        }
+    }
+}
+
+/// Keeps freshly spawned boids from landing on top of each other (and on top of boids that are
+/// already flying), which would otherwise make them burst apart from separation on their first
+/// frame
+///
+/// Spots are picked at random and thrown away until one is at least twice the radius away from
+/// every other boid, if none is found after max_attempts tries the boid is spawned at the last
+/// spot tried anyway
+///
+/// Spots where a boid would overlap an Obstacle are thrown away the same way while
+/// clear_of_obstacles is set, whether or not boids are kept apart from each other
+struct SpawnSpacing {
+    enabled: bool,
+    clear_of_obstacles: bool,
+    /// How much room a single boid takes up
+    radius: f32,
+    max_attempts: u32,
+}
+
+impl Default for SpawnSpacing {
+    fn default() -> Self {
+        SpawnSpacing {
+            enabled: false,
+            clear_of_obstacles: true,
+            // Half the length of a boid
+            radius: 5.0,
+            max_attempts: 30,
+        }
+    }
+}
+
+/// The rectangle boids live in, centered on the origin
+struct WorldBounds {
+    size: Vec2,
+}
+
+impl Default for WorldBounds {
+    fn default() -> Self {
+        // The size of the default window
+        WorldBounds {
+            size: Vec2::new(1280.0, 720.0),
+        }
+    }
+}
+
+/// The narrowest WorldBounds boids can live in, a world narrower than this along either axis
+/// (say one sized after a window that was minimized down to nothing) is swapped for the default
+/// WorldBounds. Spawning across a span of zero panics and wrapping around one divides by zero
+struct MinWorldSize(f32);
+
+impl Default for MinWorldSize {
+    fn default() -> Self {
+        MinWorldSize(1.0)
+    }
+}
+
+/// Runs in StartupStage::PreStartup and then in CoreStage::First every frame, so nothing during
+/// startup or the frame ever sees a degenerate WorldBounds
+fn guard_world_bounds(min_size: Res<MinWorldSize>, mut bounds: ResMut<WorldBounds>) {
+    // Written so a NaN size counts as too small as well
+    let usable = bounds.size.x >= min_size.0 && bounds.size.y >= min_size.0;
+    if !usable {
+        eprintln!(
+            "WorldBounds of {} is too small to simulate in, using the default instead",
+            bounds.size
+        );
+        *bounds = WorldBounds::default();
+    }
+}
+
+/// How the camera frames the world when the app starts
+///
+/// With a WorldBounds bigger than the window a larger scale zooms out far enough to see all of
+/// it, a scale of 2 shows twice as much of the world in each direction, TrackpadCamera can move it
+/// around from there. Everything following the cursor assumes the default framing of a scale of 1
+/// centered on the origin though
+struct CameraFraming {
+    scale: f32,
+    /// The point in the world the camera is centered on
+    position: Vec2,
+}
+
+impl Default for CameraFraming {
+    fn default() -> Self {
+        CameraFraming {
+            scale: 1.0,
+            position: Vec2::ZERO,
+        }
+    }
+}
+
+/// What happens to a boid reaching an edge of WorldBounds
+#[derive(Clone, Copy, PartialEq)]
+enum EdgeBehavior {
+    /// The boid comes back in through the opposite edge, and neighbors are found across the
+    /// edge as well
+    Wrap,
+    /// The boid is reflected back in, as if it bounced off a wall
+    Bounce,
+    /// The boid is steered back in once it's within SoftEdge.margin of the edge, a fast boid can
+    /// still overshoot it a little before it's turned around
+    Steer,
+    /// The boid just flies off into the distance
+    None,
+}
+
+/// The EdgeBehavior along each axis, x for the left and right edges and y for the top and bottom
+///
+/// Each axis is handled on its own so a boid can wrap around one axis while bouncing off the
+/// other, with both wrapping the world is a torus and with just one it's a cylinder
+struct EdgeBehaviors {
+    x: EdgeBehavior,
+    y: EdgeBehavior,
+}
+
+impl Default for EdgeBehaviors {
+    fn default() -> Self {
+        EdgeBehaviors {
+            x: EdgeBehavior::None,
+            y: EdgeBehavior::None,
+        }
+    }
+}
+
+/// The shape of the world boids live in, its edges are handled by EdgeBehaviors either way
+///
+/// A Rect is just WorldBounds. A Circle is centered on the origin and has only the one edge, so
+/// it goes by EdgeBehaviors.x alone. Wrapping out of a circle comes back in on the opposite side,
+/// but that doesn't make it a torus, so neighbors aren't found across its edge
+#[derive(Clone, Copy)]
+enum ArenaShape {
+    Rect,
+    Circle { radius: f32 },
+}
+
+impl Default for ArenaShape {
+    fn default() -> Self {
+        ArenaShape::Rect
+    }
+}
+
+/// Just in front of the camera's far plane, so a Circle arena's floor is drawn behind everything
+const ARENA_FLOOR_Z: f32 = -0.05;
+
+/// How edges with EdgeBehavior::Steer turn boids back in, the deeper into the margin (or past
+/// the edge) a boid is the harder it's turned all the way up to its Force.max
+struct SoftEdge {
+    margin: f32,
+    interest: f32,
+}
+
+impl Default for SoftEdge {
+    fn default() -> Self {
+        SoftEdge {
+            margin: 50.0,
+            interest: 1.0,
+        }
+    }
+}
+
+/// The number of integration steps each simulation tick is split into
+///
+/// Steering forces are still computed once per tick, but they're integrated over this many
+/// smaller steps so that fast boids don't overshoot in one big jump. Boids are pushed back out
+/// of obstacles after every step, so a boid can only fly through an obstacle that's smaller than
+/// how far it goes in a single step
+struct Substeps(u32);
+
+impl Default for Substeps {
+    fn default() -> Self {
+        Substeps(1)
+    }
+}
+
+/// What apply_force caps when turning a boid's force into movement
+///
+/// Every behavior already clamps its own force to Force.max, but a boid with several behaviors
+/// can still end up with a total force larger than that once they're all added together
+enum CapMode {
+    /// The total force (and so the acceleration since boids have a mass of 1) is clamped to
+    /// Force.max while Velocity.max is ignored, a boid under a constant force speeds up forever
+    Acceleration,
+    /// The resulting velocity is clamped to Velocity.max while the total force isn't clamped, a
+    /// boid under a constant force reaches Velocity.max as quickly as all its behaviors push it
+    /// and then stays there
+    Velocity,
+    /// The total force is clamped to Force.max and the resulting velocity to Velocity.max, a
+    /// boid under a constant force reaches Velocity.max no faster than Force.max allows
+    Both,
+}
+
+impl Default for CapMode {
+    fn default() -> Self {
+        CapMode::Velocity
+    }
+}
+
+/// How Velocity.max is enforced whenever CapMode caps velocities
+#[derive(Clone, Copy)]
+enum SpeedCapMode {
+    /// Speeds past Velocity.max are cut straight down to it, a boid under a constant force
+    /// hits its top speed abruptly
+    Hard,
+    /// Past a knee below Velocity.max the boid is dragged back harder and harder the closer it
+    /// gets to Velocity.max, a boid under a constant force eases into its top speed and never
+    /// quite reaches it. Built with the fixed-point feature this is the same as Hard, see
+    /// src/fixed.rs
+    Soft {
+        /// How far below Velocity.max the knee sits as a fraction of it, 0 is the same as Hard
+        /// while 1 starts dragging from a standstill
+        softness: f32,
+    },
+}
+
+impl Default for SpeedCapMode {
+    fn default() -> Self {
+        SpeedCapMode::Hard
+    }
+}
+
+impl SpeedCapMode {
+    /// The velocity a boid is left with after capping it to a top speed of max
+    fn cap(&self, velocity: Vec2, max: f32) -> Vec2 {
+        match *self {
+            SpeedCapMode::Hard => Vec2::clamp_length_max(velocity, max),
+            SpeedCapMode::Soft { softness } => {
+                let knee = max * (1.0 - softness.clamp(0.0, 1.0));
+                let range = max - knee;
+                let speed = velocity.length();
+                if speed <= knee || range <= 0.0 {
+                    return Vec2::clamp_length_max(velocity, max);
+                }
+                // tanh leaves speeds right past the knee almost untouched and squeezes faster
+                // ones closer and closer to max without ever going over it
+                let speed = knee + range * ((speed - knee) / range).tanh();
+                velocity.normalize() * speed
+            }
+        }
+    }
+}
+
+/// The slowest a moving boid ever flies, real flocking animals hardly ever stop dead and a boid
+/// that does loses its heading
+///
+/// A boid that has come to a complete stop with nothing pushing it is left alone since there's no
+/// way to tell which way it should go, 0 lets boids slow down as much as they like
+#[derive(Default)]
+struct MinSpeed(f32);
+
+/// How heavy a boid is, the heavier it is the less its steering force accelerates it
+///
+/// Boids without a Mass have a mass of 1
+#[derive(Component)]
+struct Mass(f32);
+
+/// The range of masses boids are spawned with, each boid gets a mass picked at random from it
+///
+/// The masses come from SimRng, so they're the same every run with the same seed. Boids sprayed
+/// from the cursor and offspring are always spawned with a mass of 1
+struct SpawnMass {
+    min: f32,
+    max: f32,
+}
+
+impl Default for SpawnMass {
+    fn default() -> Self {
+        SpawnMass { min: 1.0, max: 1.0 }
+    }
+}
+
+impl SpawnMass {
+    fn pick(&self, rng: &mut StdRng) -> f32 {
+        // Drawing nothing when every boid weighs the same keeps runs from before there were
+        // masses the same as they were
+        if self.max > self.min {
+            rng.gen_range(self.min..=self.max)
+        } else {
+            self.min
+        }
+    }
+}
+
+/// The most a boid's steering can accelerate it by, no matter how large a force it's given or
+/// how light it is
+///
+/// Force.max limits forces, which only limits acceleration as long as every boid has a mass of
+/// 1, this is a limit on the acceleration itself. None means there is no limit
+#[derive(Default)]
+struct MaxAcceleration(Option<f32>);
+
+/// Whether the back faces of meshes are culled, skipping triangles that face away from the camera
+/// before they're shaded
+///
+/// Every mesh here is wound counter-clockwise when looked at by the 2D camera (from +z), which is
+/// the front face, so they stay visible with culling on. A boid banking past a quarter turn
+/// would show its back face and disappear, BankingParams::max_roll has to stay below that
+#[derive(Default)]
+struct BackFaceCulling(bool);
+
+/// How close boids have to be to the cursor for SeekCursor to pull them in, boids further away
+/// get no pull at all and just keep flocking. None pulls in every boid no matter how far away
+///
+/// A small radius makes the cursor good for nudging a few boids along without dragging the whole
+/// flock around, the radius is drawn as a ring around the cursor with DebugOverlays::cursor_reach
+#[derive(Default)]
+struct CursorReach(Option<f32>);
+
+/// A constant acceleration pulling every boid, on top of whatever they're steering towards
+///
+/// Pointed downwards it gives the flock a falling particles look
+#[derive(Default)]
+struct Gravity(Vec2);
+
+/// The colors of the boid mesh's three vertices, which get blended across the triangle so a boid
+/// can be shaded as a gradient (e.g. a bright nose and a dark tail to make its heading obvious)
+///
+/// Silhouettes other than the triangle are colored as if they were laid over the triangle, from
+/// the nose at the origin back to the tail edge 10 units behind it
+///
+/// Since these are vertex colors any other coloring that happens in the shader is multiplied on
+/// top of them, so a tint of red over a white to black gradient results in a red to black one
+struct BoidGradient {
+    /// The tail vertex below the boid's heading
+    tail_bottom: Color,
+    /// The vertex at the tip of the boid, pointing in its heading
+    nose: Color,
+    /// The tail vertex above the boid's heading
+    tail_top: Color,
+}
+
+impl BoidGradient {
+    /// The vertex colors for a boid mesh with these vertex positions
+    fn vertex_colors(&self, positions: &[[f32; 3]]) -> Vec<[f32; 3]> {
+        let [tail_bottom, nose, tail_top] = [self.tail_bottom, self.nose, self.tail_top]
+            .map(|color| Vec3::from(color::vertex_color(color)));
+        positions
+            .iter()
+            .map(|&[x, y, _]| {
+                // How far back towards the tail and how far up along the tail edge the vertex is
+                let back = (-x / 10.0).clamp(0.0, 1.0);
+                let up = ((y + 5.0) / 10.0).clamp(0.0, 1.0);
+                nose.lerp(tail_bottom.lerp(tail_top, up), back).to_array()
+            })
+            .collect()
+    }
+}
+
+impl Default for BoidGradient {
+    fn default() -> Self {
+        // A plain white gradient leaves a boid exactly the color of its Tint
+        BoidGradient {
+            tail_bottom: Color::WHITE,
+            nose: Color::WHITE,
+            tail_top: Color::WHITE,
+        }
+    }
+}
+
+/// The color a boid is drawn in, this is handed to FRAGMENT_SHADER as a uniform so every boid can
+/// have its own color while still sharing the one mesh, and it's multiplied with the mesh's
+/// BoidGradient
+#[derive(Component, RenderResources, TypeUuid)]
+#[uuid = "5b0c3c1e-2d0a-4a3f-8f4b-7e1d6c9a2b53"]
+struct Tint {
+    color: Color,
+}
+
+impl Default for Tint {
+    fn default() -> Self {
+        Tint {
+            color: Color::BLACK,
+        }
+    }
+}
+
+/// How long a boid has been alive, in seconds
+#[derive(Component, Default)]
+struct Age(f32);
+
+/// Which coloring system picks the boids' Tints, Plain leaves them alone
+#[derive(Clone, Copy, PartialEq)]
+enum ColorMode {
+    Plain,
+    /// See AgeColoring
+    Age,
+    /// See FlockColors
+    Flock,
+    /// Around the color wheel by the direction a boid is heading, red for right, then through
+    /// yellow and green going counter-clockwise, so the flow of the flock shows at a glance
+    Heading,
+}
+
+impl Default for ColorMode {
+    fn default() -> Self {
+        ColorMode::Plain
+    }
+}
+
+/// How ColorMode::Age colors boids by how old they are, fading from young to old over max_age
+struct AgeColoring {
+    /// The color of a freshly spawned boid
+    young: Color,
+    /// The color of a boid that's max_age or older
+    old: Color,
+    /// The age in seconds at which a boid is fully old
+    max_age: f32,
+}
+
+impl Default for AgeColoring {
+    fn default() -> Self {
+        AgeColoring {
+            young: Color::rgb(0.6, 0.9, 1.0),
+            old: Color::rgb(0.1, 0.1, 0.3),
+            max_age: 60.0,
+        }
+    }
+}
+
+/// How far back a boid's comet tail currently stretches, this is handed to VERTEX_SHADER as a
+/// uniform so the tail is drawn by displacing the mesh's tail tip rather than with any extra
+/// entities
+#[derive(Component, RenderResources, Default, TypeUuid)]
+#[uuid = "0d3f5e0e-6a57-4bb0-9b3c-2f4a3d6e8a41"]
+struct CometTail {
+    length: f32,
+}
+
+/// How long boids' comet tails get
+struct CometTailParams {
+    /// The length of the tail for every unit of speed, a boid without any speed has no tail
+    /// and is drawn as the plain triangle
+    length_per_speed: f32,
+}
+
+impl Default for CometTailParams {
+    fn default() -> Self {
+        CometTailParams {
+            length_per_speed: 20.0,
+        }
+    }
+}
+
+/// The name of the vertex attribute marking which of the boid mesh's vertices are pulled back to
+/// form the comet tail, 1 for the tail's tip and 0 for everything else
+const ATTRIBUTE_TAIL: &str = "Vertex_Tail";
+
+#[derive(SystemLabel, Debug, Clone, PartialEq, Eq, Hash)]
+enum BoidSystem {
+    AgeBoids,
+    RegulatePopulation,
+    AssignFormationSlots,
+    GatherNeighbors,
+    FadeAwareness,
+    ModulateSpeed,
+    SpreadAlarm,
+    Seek,
+    SeekCursor,
+    Separate,
+    Align,
+    Cohere,
+    Regroup,
+    FlyInFormation,
+    Attract,
+    FleePredators,
+    AvoidObstacles,
+    FollowPath,
+    FollowScent,
+    SteerInsideEdges,
+    ControlSpeed,
+    BreakStalemates,
+    CustomSteering,
+    CruiseWhenIdle,
+    Sleep,
+    HoldStaggeredForces,
+    CaptureForces,
+    ApplyForce,
+    UpdateBoids,
+    MeasureFlock,
+}
+
 fn main() {
-    App::new()
-        .add_plugins(DefaultPlugins)
+    let mut app = App::new();
+    app.add_plugins(DefaultPlugins)
+        .insert_resource(Scene::from_args())
+        .insert_resource(ObstacleLayout::from_args())
+        .insert_resource(RunDuration::from_args())
+        .init_resource::<Substeps>()
+        .init_resource::<CapMode>()
+        .init_resource::<SpeedCapMode>()
+        .init_resource::<MinSpeed>()
+        .init_resource::<Gravity>()
+        .init_resource::<MaxAcceleration>()
+        .init_resource::<SpawnMass>()
+        .init_resource::<CursorReach>()
+        .init_resource::<CursorPursuit>()
+        .init_resource::<CursorCuriosity>()
+        .init_resource::<MaxPrediction>()
+        .init_resource::<CursorMotion>()
+        .init_resource::<CursorMemory>()
+        .init_resource::<BackFaceCulling>()
+        .init_resource::<BehaviorToggles>()
+        .init_resource::<FreezeSteering>()
+        .init_resource::<RotationOnly>()
+        .init_resource::<HeadingOffset>()
+        .init_resource::<RotationSmoothing>()
+        .init_resource::<WorldBounds>()
+        .init_resource::<MinWorldSize>()
+        .init_resource::<CameraFraming>()
+        .init_resource::<TrackpadCamera>()
+        .init_resource::<EdgeBehaviors>()
+        .init_resource::<ArenaShape>()
+        .init_resource::<SoftEdge>()
+        .init_resource::<TargetPopulation>()
+        .init_resource::<DespawnExcess>()
+        .init_resource::<FrameBudget>()
+        .init_resource::<SimRng>()
+        .init_resource::<DeterministicMode>()
+        .init_resource::<SpawnRate>()
+        .init_resource::<SpawnSpacing>()
+        .init_resource::<SpawnPattern>()
+        .init_resource::<SpawnJitter>()
+        .init_resource::<NextSpawnIndex>()
+        .init_resource::<CursorSpray>()
+        .init_resource::<Reproduction>()
+        .init_resource::<InitialHeading>()
+        .init_resource::<BoidGradient>()
+        .init_resource::<BoidSilhouette>()
+        .init_resource::<SpriteMode>()
+        .init_resource::<FlockingParams>()
+        .init_resource::<PresetSelection>()
+        .init_resource::<ExploreSchedule>()
+        .init_resource::<Mood>()
+        .init_resource::<SeparationMath>()
+        .init_resource::<SpeedScaledSeparation>()
+        .init_resource::<NeighborWeighting>()
+        .init_resource::<MinimumSpacing>()
+        .insert_resource(CollisionMatrix::from_args())
+        .init_resource::<AwarenessFade>()
+        .init_resource::<StalemateBreaking>()
+        .init_resource::<TargetAvgSpeed>()
+        .init_resource::<SpeedController>()
+        .init_resource::<IdleCruising>()
+        .init_resource::<ComfortZone>()
+        .init_resource::<AlarmParams>()
+        .init_resource::<FlockCount>()
+        .init_resource::<FlockColors>()
+        .init_resource::<SpatialGrid>()
+        .init_resource::<SpatialIndex>()
+        .init_resource::<FlockMetrics>()
+        .init_resource::<ClusterDetection>()
+        .init_resource::<MetricsWindow>()
+        .init_resource::<BankingParams>()
+        .init_resource::<CometTailParams>()
+        .init_resource::<RibbonTrails>()
+        .init_resource::<ScentField>()
+        .init_resource::<HoverInspector>()
+        .init_resource::<CameraFollow>()
+        .init_resource::<PerceptionRadiiColors>()
+        .init_resource::<VisionCone>()
+        .init_resource::<Compass>()
+        .init_resource::<MoodGauge>()
+        .init_resource::<FormationParams>()
+        .init_resource::<LodParams>()
+        .init_resource::<ColorMode>()
+        .init_resource::<AgeColoring>()
+        .init_resource::<FormationSlots>()
+        .init_resource::<AttractorParams>()
+        .init_resource::<ObstacleParams>()
+        .init_resource::<ObstacleGrid>()
+        .init_resource::<SleepParams>()
+        .init_resource::<TetherParams>()
+        .init_resource::<PredatorParams>()
+        .init_resource::<Path>()
+        .init_resource::<DebugOverlays>()
+        .init_resource::<ForceArrows>()
+        .init_resource::<Glow>()
+        .init_resource::<NeighborLines>()
+        .init_resource::<CapturedForces>()
+        .init_resource::<ForceLog>()
+        .init_resource::<SteeringTotals>()
+        .init_resource::<SteeringStagger>()
+        .init_resource::<CustomSteering>()
+        .init_resource::<RenderThrottle>()
+        .init_resource::<WarmUp>()
+        .stage(
+            RenderStage::RenderGraphSystems,
+            |stage: &mut SystemStage| stage.set_run_criteria(render_throttle::is_render_frame),
+        )
+        .stage(RenderStage::Draw, |stage: &mut SystemStage| {
+            stage.set_run_criteria(render_throttle::is_render_frame)
+        })
+        .stage(RenderStage::Render, |stage: &mut SystemStage| {
+            stage.set_run_criteria(render_throttle::is_render_frame)
+        })
+        // place_obstacle_layout lays its obstacles out across WorldBounds during startup, before
+        // CoreStage::First ever runs
+        .add_startup_system_to_stage(StartupStage::PreStartup, guard_world_bounds)
         .add_startup_system(start)
-        .add_system(seek_target)
-        .add_system(seek_mouse)
-        .add_system(apply_force)
-        .add_system(update_boids)
-        .run();
+        .add_startup_system(spawn_camera)
+        .add_startup_system(scene::setup_scene)
+        .add_startup_system(force_arrows::setup_force_arrows)
+        .add_startup_system(glow::setup_glow)
+        // BoidAssets only exist once start's commands are applied
+        .add_startup_system_to_stage(StartupStage::PostStartup, obstacles::place_obstacle_layout)
+        .add_system(budget::fit_population_to_budget)
+        .add_system(regulate_population.label(BoidSystem::RegulatePopulation))
+        .add_system(sprites::fall_back_from_failed_sprite)
+        // Sprayed boids only exist once commands are applied, regulate_population running in
+        // between would see the raised TargetPopulation without them and spawn more
+        .add_system(spray_boids.after(BoidSystem::RegulatePopulation))
+        // Just like sprayed boids offspring only exist once commands are applied
+        .add_system(reproduction::reproduce.after(BoidSystem::RegulatePopulation))
+        .add_system(toggle_behaviors)
+        .add_system_to_stage(CoreStage::PreUpdate, grid::update_spatial_grid)
+        .add_system_to_stage(CoreStage::PreUpdate, obstacles::update_obstacle_grid)
+        .add_system_to_stage(CoreStage::PreUpdate, stagger::advance_stagger)
+        .add_system_to_stage(CoreStage::First, render_throttle::advance_render_throttle)
+        .add_system_to_stage(CoreStage::First, guard_world_bounds)
+        .add_system_to_stage(CoreStage::PreUpdate, track_cursor)
+        // Every steering system adds into the same Force so they can't run in parallel anyway,
+        // giving them a fixed order means the floating point sums come out the same every run
+        // Every steering system steers towards Velocity.max, so it has to be settled beforehand
+        .add_system(flocking::modulate_speed_by_crowding.label(BoidSystem::ModulateSpeed))
+        .add_system(
+            alarm::spread_alarm
+                .label(BoidSystem::SpreadAlarm)
+                .after(BoidSystem::ModulateSpeed),
+        )
+        .add_system(
+            seek_target
+                .label(BoidSystem::Seek)
+                .after(BoidSystem::SpreadAlarm),
+        )
+        .add_system(
+            seek_mouse
+                .label(BoidSystem::SeekCursor)
+                .after(BoidSystem::Seek),
+        )
+        .add_system(flocking::select_flocking_preset)
+        .add_system(mood::adjust_mood)
+        .add_system(
+            mood::apply_mood
+                .before(BoidSystem::GatherNeighbors)
+                .before(BoidSystem::ModulateSpeed),
+        )
+        .add_system_to_stage(CoreStage::PreUpdate, flocking::apply_flocking_preset)
+        // Presets are applied in PreUpdate, so the schedule always has the last word
+        .add_system(
+            flocking::follow_explore_schedule
+                .before(BoidSystem::GatherNeighbors)
+                .before(BoidSystem::ModulateSpeed),
+        )
+        .add_system(flocking::gather_neighbors.label(BoidSystem::GatherNeighbors))
+        .add_system(
+            flocking::fade_awareness
+                .label(BoidSystem::FadeAwareness)
+                .after(BoidSystem::GatherNeighbors),
+        )
+        .add_system(
+            flocking::separate
+                .label(BoidSystem::Separate)
+                .after(BoidSystem::FadeAwareness)
+                .after(BoidSystem::SeekCursor),
+        )
+        .add_system(
+            flocking::align
+                .label(BoidSystem::Align)
+                .after(BoidSystem::Separate),
+        )
+        .add_system(
+            flocking::cohere
+                .label(BoidSystem::Cohere)
+                .after(BoidSystem::Align),
+        )
+        .add_system(
+            flocking::regroup
+                .label(BoidSystem::Regroup)
+                .after(BoidSystem::Cohere),
+        )
+        .add_system(formation::assign_formation_slots.label(BoidSystem::AssignFormationSlots))
+        .add_system(
+            formation::fly_in_formation
+                .label(BoidSystem::FlyInFormation)
+                .after(BoidSystem::AssignFormationSlots)
+                .after(BoidSystem::Regroup),
+        )
+        .add_system(
+            attractors::attract
+                .label(BoidSystem::Attract)
+                .after(BoidSystem::FlyInFormation),
+        )
+        .add_system(
+            predators::flee_predators
+                .label(BoidSystem::FleePredators)
+                .after(BoidSystem::Attract),
+        )
+        .add_system(
+            obstacles::avoid_obstacles
+                .label(BoidSystem::AvoidObstacles)
+                .after(BoidSystem::FleePredators),
+        )
+        .add_system(
+            path::follow_path
+                .label(BoidSystem::FollowPath)
+                .after(BoidSystem::AvoidObstacles),
+        )
+        .add_system(
+            scent::follow_scent
+                .label(BoidSystem::FollowScent)
+                .after(BoidSystem::FollowPath),
+        )
+        .add_system(
+            steer_inside_edges
+                .label(BoidSystem::SteerInsideEdges)
+                .after(BoidSystem::FollowScent),
+        )
+        .add_system(
+            flocking::control_average_speed
+                .label(BoidSystem::ControlSpeed)
+                .after(BoidSystem::SteerInsideEdges),
+        )
+        .add_system(
+            flocking::break_stalemates
+                .label(BoidSystem::BreakStalemates)
+                .after(BoidSystem::ControlSpeed),
+        )
+        .add_system(
+            custom::custom_steering
+                .label(BoidSystem::CustomSteering)
+                .after(BoidSystem::BreakStalemates),
+        )
+        .add_system(
+            flocking::cruise_when_idle
+                .label(BoidSystem::CruiseWhenIdle)
+                .after(BoidSystem::CustomSteering),
+        )
+        .add_system(
+            sleep::sleep_and_wake
+                .label(BoidSystem::Sleep)
+                .after(BoidSystem::CruiseWhenIdle),
+        )
+        .add_system(
+            stagger::hold_staggered_forces
+                .label(BoidSystem::HoldStaggeredForces)
+                .after(BoidSystem::Sleep),
+        )
+        .add_system(
+            force_arrows::capture_forces
+                .label(BoidSystem::CaptureForces)
+                .after(BoidSystem::HoldStaggeredForces),
+        )
+        .add_system(
+            apply_force
+                .label(BoidSystem::ApplyForce)
+                .after(BoidSystem::CaptureForces),
+        )
+        .add_system(
+            update_boids
+                .label(BoidSystem::UpdateBoids)
+                .after(BoidSystem::ApplyForce),
+        )
+        .add_system(bank_boids.after(BoidSystem::UpdateBoids))
+        .add_system(
+            flocking::separate_positions
+                .after(BoidSystem::ApplyForce)
+                .after(BoidSystem::UpdateBoids),
+        )
+        .add_system(tethers::tie_tethers)
+        .add_system(
+            tethers::enforce_tethers
+                .after(BoidSystem::ApplyForce)
+                .after(BoidSystem::UpdateBoids),
+        )
+        .add_system(update_comet_tails)
+        .add_system(ribbons::draw_ribbon_trails.after(BoidSystem::UpdateBoids))
+        .add_system(scent::deposit_scent.after(BoidSystem::ApplyForce))
+        .add_system(update_lod)
+        .add_system(age_boids.label(BoidSystem::AgeBoids))
+        .add_system(color_by_age.after(BoidSystem::AgeBoids))
+        .add_system(flocks::color_by_flock)
+        .add_system(color_by_heading)
+        .add_system(handle_edges)
+        .add_system(metrics::detect_clusters)
+        .add_system(metrics::measure_flock.label(BoidSystem::MeasureFlock))
+        .add_system(metrics::end_run.after(BoidSystem::MeasureFlock))
+        .add_system(compass::draw_compass.after(BoidSystem::MeasureFlock))
+        .add_system(mood::draw_mood_gauge)
+        .add_system(inspect::inspect_hovered)
+        .add_system(inspect::select_hovered)
+        .add_system(inspect::toggle_pin)
+        .add_system(inspect::toggle_camera_follow)
+        .add_system(inspect::follow_selected.after(BoidSystem::UpdateBoids))
+        .add_system(inspect::draw_perception_radii)
+        .add_system(inspect::draw_vision_cone)
+        .add_system(formation::toggle_formation)
+        .add_system(attractors::place_attractors)
+        .add_system(obstacles::place_obstacles)
+        .add_system(predators::regulate_predators)
+        .add_system(predators::hunt)
+        .add_system(draw_cursor_reach)
+        .add_system(chatter::draw_neighbor_lines.after(BoidSystem::GatherNeighbors))
+        .add_system(teleport_flock)
+        .add_system(trackpad::control_camera_with_trackpad)
+        .add_system(debug::toggle_debug_overlays)
+        .add_system(scene::run_stress_scene)
+        .add_system(force_arrows::draw_force_arrows.after(BoidSystem::CaptureForces))
+        .add_system(glow::toggle_glow)
+        .add_system(glow::draw_glows.after(BoidSystem::UpdateBoids))
+        .add_system_to_stage(CoreStage::Last, force_log::write_force_log);
+    #[cfg(feature = "frame-capture")]
+    app.add_plugin(capture::FrameCapturePlugin);
+    render_throttle::warm_up(&mut app);
+    app.run();
 }
 
 fn create_boid_mesh_bundle(
@@ -86,16 +1305,48 @@ fn create_boid_mesh_bundle(
     }
 }
 
+/// Everything start needs to build the pipeline boids are drawn with
+#[derive(SystemParam)]
+struct PipelineSetup<'a> {
+    // A pipeline will be added with custom shaders
+    pipelines: ResMut<'a, Assets<PipelineDescriptor>>,
+    // Access to add new shaders
+    shaders: ResMut<'a, Assets<Shader>>,
+    // The render graph is how the comet tail's uniform gets to the shader
+    render_graph: ResMut<'a, RenderGraph>,
+    culling: Res<'a, BackFaceCulling>,
+}
+
+/// Everything start needs to load the boid sprite, see SpriteMode
+#[derive(SystemParam)]
+struct SpriteLoading<'a> {
+    sprite_mode: Res<'a, SpriteMode>,
+    asset_server: Res<'a, AssetServer>,
+    materials: ResMut<'a, Assets<ColorMaterial>>,
+}
+
 fn start(
     mut commands: Commands,
     // We will add a new Mesh for the star being created
     mut meshes: ResMut<Assets<Mesh>>,
-    // A pipeline will be added with custom shaders
-    mut pipelines: ResMut<Assets<PipelineDescriptor>>,
-    // Access to add new shaders
-    mut shaders: ResMut<Assets<Shader>>,
-    windows: Res<Windows>,
+    pipeline_setup: PipelineSetup,
+    sprite_loading: SpriteLoading,
+    gradient: Res<BoidGradient>,
+    silhouette: Res<BoidSilhouette>,
+    arena: Res<ArenaShape>,
 ) {
+    let PipelineSetup {
+        mut pipelines,
+        mut shaders,
+        mut render_graph,
+        culling,
+    } = pipeline_setup;
+    let SpriteLoading {
+        sprite_mode,
+        asset_server,
+        mut materials,
+    } = sprite_loading;
+
     // We first create a pipeline, which is the sequence of steps that are
     // needed to get to pixels on the screen starting from a description of the
     // geometries in the scene. Pipelines have fixed steps, which sometimes can
@@ -103,111 +1354,756 @@ fn start(
     // steps, the vertex and fragment shaders, that we can customize writing
     // shader programs.
 
-    let pipeline_handle = pipelines.add(PipelineDescriptor::default_config(ShaderStages {
+    let mut pipeline = PipelineDescriptor::default_config(ShaderStages {
         // Vertex shaders are run once for every vertex in the mesh.
         // Each vertex can have attributes associated to it (e.g. position,
         // color, texture mapping). The output of a shader is per-vertex.
-        vertex: shaders.add(Shader::from_glsl(ShaderStage::Vertex, VERTEX_SHADER)),
+        vertex: shaders.add(compile_shader(
+            ShaderStage::Vertex,
+            VERTEX_SHADER,
+            FALLBACK_VERTEX_SHADER,
+        )),
         // Fragment shaders are run for each pixel belonging to a triangle on
         // the screen. Their output is per-pixel.
-        fragment: Some(shaders.add(Shader::from_glsl(ShaderStage::Fragment, FRAGMENT_SHADER))),
-    }));
+        fragment: Some(shaders.add(compile_shader(
+            ShaderStage::Fragment,
+            FRAGMENT_SHADER,
+            FALLBACK_FRAGMENT_SHADER,
+        ))),
+    });
+    // Counter-clockwise triangles face the camera, see BackFaceCulling
+    pipeline.primitive.front_face = FrontFace::Ccw;
+    pipeline.primitive.cull_mode = culling.0.then(|| Face::Back);
+    let pipeline_handle = pipelines.add(pipeline);
 
-    let mut rng = thread_rng();
+    // Every entity's CometTail is bound as a uniform before the main pass draws it
+    render_graph.add_system_node("comet_tail", RenderResourcesNode::<CometTail>::new(true));
+    render_graph
+        .add_node_edge("comet_tail", base::node::MAIN_PASS)
+        .unwrap();
+    // And the same goes for its Tint
+    render_graph.add_system_node("tint", RenderResourcesNode::<Tint>::new(true));
+    render_graph
+        .add_node_edge("tint", base::node::MAIN_PASS)
+        .unwrap();
+    // And its Alarm
+    render_graph.add_system_node("alarm", RenderResourcesNode::<Alarm>::new(true));
+    render_graph
+        .add_node_edge("alarm", base::node::MAIN_PASS)
+        .unwrap();
 
     // A mesh can be reused! We need a mesh per shape/color though!
     // So for example a red triangle would need a different mesh, but most other triangles can
     // actually reuse this mesh with some transform stretching if we wanted
-    let mut triangle = Mesh::new(PrimitiveTopology::TriangleList);
-    triangle.set_attribute(
-        Mesh::ATTRIBUTE_POSITION,
-        vec![[-10.0, -5.0, 0.0], [0.0, 0.0, 0.0], [-10.0, 5.0, 0.0]],
-    );
-    triangle.set_attribute(
-        Mesh::ATTRIBUTE_COLOR,
-        vec![[0.0, 0.0, 0.0], [0.0, 0.0, 0.0], [0.0, 0.0, 0.0]],
-    );
-    triangle.set_indices(Some(Indices::U32(vec![0, 1, 2])));
-    let mesh_handle = meshes.add(triangle);
-
-    if let Some(window) = windows.as_ref().get_primary() {
-        (0..100).for_each(|_| {
-            let width = window.width();
-            let x = rng.gen_range(-width / 2.0..width / 2.0);
-            let height = window.height();
-            let y = rng.gen_range(-height / 2.0..height / 2.0);
-
-            let triangle = create_boid_mesh_bundle(
-                pipeline_handle.clone(),
-                mesh_handle.clone(),
-                Vec2::new(x, y),
-            );
+    let mesh_handle = meshes.add(silhouette.mesh(&gradient));
 
-            commands
-                .spawn_bundle(triangle)
-                .insert(Velocity {
-                    vector: Vec2::new(0.0, 0.0),
-                    max: 1.0,
-                })
-                .insert(Force {
-                    vector: Vec2::new(0.0, 0.0),
-                    max: 0.25,
-                })
-                .insert(SeekCursor { interest: 1.0 });
-        });
+    // A circle doesn't fill the window like a rectangle does, its floor shows where the edge is
+    if let ArenaShape::Circle { radius } = *arena {
+        let mut floor = create_boid_mesh_bundle(
+            pipeline_handle.clone(),
+            meshes.add(shapes::create_circle_mesh(128)),
+            Vec2::ZERO,
+        );
+        floor.transform.translation.z = ARENA_FLOOR_Z;
+        floor.transform.scale = Vec3::new(radius, radius, 1.0);
+        commands
+            .spawn_bundle(floor)
+            .insert(CometTail::default())
+            .insert(Tint {
+                color: Color::rgb(0.12, 0.12, 0.16),
+            })
+            .insert(Alarm::default());
     }
 
-    commands
-        // And use an orthographic projection
-        .spawn_bundle(OrthographicCameraBundle::new_2d());
+    commands.insert_resource(BoidAssets {
+        pipeline: pipeline_handle,
+        mesh: mesh_handle,
+        sprite: BoidSprite::load(&sprite_mode, &asset_server, &mut materials),
+    });
 }
 
-fn update_boids(mut query: Query<(&mut Transform, &Velocity)>) {
-    for (mut transform, velocity) in query.iter_mut() {
-        let transform = transform.as_mut();
-        // new position = current position + velocity
-        transform.translation += velocity.vector.extend(0.0);
-        // If there's no velocity then setting the rotation causes the object not to render due
-        // to angle_between's calculation containing a division by Sqrt(Mag(A)^2 * Mag(B)^2)
-        // which in case of B being 0 would be 0 so division by 0 would result in a NaN
-        // Attempting to render with NaN as angle just results in no rendering happening
-        //
-        // One could argue that setting the angle to 0 or something like that should be done if
-        // the object has no velocity but then any object with no velocity would immediately face
-        // the right side, which makes no sense.
-        // In real life objects preserve their facing direction even after losing all their
-        // velocity, this does just that by refusing to set the rotation when there is no velocity
-        // thus preserving the previous rotation!
-        if velocity.vector.length().classify() != FpCategory::Zero {
-            // angle
-            transform.rotation =
-                Quat::from_rotation_z(Vec2::new(1.0, 0.0).angle_between(velocity.vector));
-        }
-    }
+/// The camera doesn't depend on the window or the renderer in any way, so it always gets spawned
+/// even when those aren't around yet
+fn spawn_camera(mut commands: Commands, framing: Res<CameraFraming>) {
+    // And use an orthographic projection
+    let mut camera = OrthographicCameraBundle::new_2d();
+    camera.orthographic_projection.scale = framing.scale;
+    camera.transform.translation.x = framing.position.x;
+    camera.transform.translation.y = framing.position.y;
+    commands.spawn_bundle(camera);
 }
 
-fn seek_force(
-    target_position: Vec2,
-    current_position: Vec2,
-    current_velocity: Vec2,
-    max_force: f32,
-    interest: f32,
-) -> Vec2 {
-    // target - position
-    let desired_velocity = target_position - current_position;
+/// The Velocity.max every boid is spawned with
+const BOID_MAX_SPEED: f32 = 1.0;
+/// The Force.max every boid is spawned with
+const BOID_MAX_FORCE: f32 = 0.25;
 
-    // steering force = desired velocity - current velocity
-    Vec2::clamp_length_max(desired_velocity - current_velocity, max_force) * interest
+fn spawn_boid(
+    commands: &mut Commands,
+    assets: &BoidAssets,
+    position: Vec2,
+    velocity: Vec2,
+    index: SpawnIndex,
+    flock: FlockId,
+) -> Entity {
+    let mut boid = match &assets.sprite {
+        Some(sprite) => commands.spawn_bundle(sprite.bundle(position)),
+        None => commands.spawn_bundle(create_boid_mesh_bundle(
+            assets.pipeline.clone(),
+            assets.mesh.clone(),
+            position,
+        )),
+    };
+
+    boid.insert(Velocity {
+        vector: velocity.clamp_length_max(BOID_MAX_SPEED),
+        max: BOID_MAX_SPEED,
+    })
+    .insert(CruiseSpeed(1.0))
+    .insert(Force {
+        vector: Vec2::new(0.0, 0.0),
+        max: BOID_MAX_FORCE,
+    })
+    .insert(SeekCursor { interest: 1.0 })
+    .insert(Bank::default())
+    .insert(Neighbors::default())
+    .insert(PerceptionNoise::default())
+    .insert(Awareness::default())
+    .insert(Separation { interest: 1.0 })
+    .insert(CollisionLayer::of_flock(flock))
+    .insert(Alignment { interest: 1.0 })
+    .insert(Cohesion { interest: 1.0 })
+    .insert(Regroup { interest: 0.25 })
+    .insert(AvoidObstacles::new(1.0))
+    .insert(FollowPath { interest: 1.0 })
+    .insert(CometTail::default())
+    .insert(Tint::default())
+    .insert(Alarm::default())
+    .insert(Age::default())
+    .insert(StaggeredForce::default())
+    .insert(Settling::default())
+    .insert(BirthTimer::default())
+    .insert(Genes::default())
+    .insert(flock)
+    .insert(index)
+    .id()
 }
 
-fn seek_target(
-    mut query: Query<(&mut Force, &Velocity, &Transform, &Seek)>,
-    transforms: Query<&Transform>,
+/// Everything systems that spawn boids need to spawn them, see spawn_boid
+#[derive(SystemParam)]
+struct BoidSpawner<'a> {
+    commands: Commands<'a>,
+    assets: Res<'a, BoidAssets>,
+    rng: ResMut<'a, SimRng>,
+    next_index: ResMut<'a, NextSpawnIndex>,
+    flocks: Res<'a, FlockCount>,
+}
+
+/// How many boids regulate_population keeps the flock at and how quickly it gets there
+#[derive(SystemParam)]
+struct PopulationControl<'a> {
+    target: Res<'a, TargetPopulation>,
+    despawn_excess: Res<'a, DespawnExcess>,
+    rate: Res<'a, SpawnRate>,
+}
+
+/// Where and how regulate_population spawns boids
+#[derive(SystemParam)]
+struct SpawnLayout<'a> {
+    spacing: Res<'a, SpawnSpacing>,
+    pattern: Res<'a, SpawnPattern>,
+    jitter: Res<'a, SpawnJitter>,
+    mass: Res<'a, SpawnMass>,
+    heading: Res<'a, InitialHeading>,
+    bounds: Res<'a, WorldBounds>,
+}
+
+/// Tops the flock up to TargetPopulation (or thins it down with DespawnExcess), either all at
+/// once or trickling in at SpawnRate
+fn regulate_population(
+    spawner: BoidSpawner,
+    time: Res<Time>,
+    population_control: PopulationControl,
+    layout: SpawnLayout,
+    // Partially accumulated boids that will be spawned (or despawned) once they add up to a
+    // whole one
+    mut pending: Local<f32>,
+    boids: Query<(Entity, &Transform, &SpawnIndex), With<Velocity>>,
+    obstacles: Query<&Obstacle>,
 ) {
-    for (mut force, velocity, Transform { translation, .. }, seek) in query.iter_mut() {
-        let force = force.as_mut();
-        if let Ok(target) = transforms.get_component::<Transform>(seek.target) {
-            force.vector = seek_force(
+    let BoidSpawner {
+        mut commands,
+        assets,
+        mut rng,
+        mut next_index,
+        flocks,
+    } = spawner;
+    let PopulationControl {
+        target,
+        despawn_excess,
+        rate,
+    } = population_control;
+    let SpawnLayout {
+        spacing,
+        pattern,
+        jitter,
+        mass,
+        heading,
+        bounds,
+    } = layout;
+    let population = boids.iter().count();
+    let excess = if despawn_excess.0 {
+        population.saturating_sub(target.0)
+    } else {
+        0
+    };
+    let missing = target.0.saturating_sub(population);
+    if missing == 0 && excess == 0 {
+        // Nothing accumulates while the flock is at its target, otherwise changing the
+        // TargetPopulation later would spawn everything that piled up in the meantime at once
+        *pending = 0.0;
+        return;
+    }
+
+    let mut limit = |wanted: usize| {
+        if rate.0 > 0.0 {
+            *pending += rate.0 * time.delta_seconds();
+            let allowed = (pending.floor() as usize).min(wanted);
+            *pending -= allowed as f32;
+            allowed
+        } else {
+            wanted
+        }
+    };
+
+    if excess > 0 {
+        let mut newest = boids
+            .iter()
+            .map(|(entity, _, &index)| (index, entity))
+            .collect::<Vec<_>>();
+        newest.sort_unstable_by_key(|&(index, _)| std::cmp::Reverse(index));
+        for (_, entity) in newest.into_iter().take(limit(excess)) {
+            commands.entity(entity).despawn();
+        }
+        return;
+    }
+    let spawning = limit(missing);
+
+    // Boids are spawned across WorldBounds rather than the window, the window might not exist
+    // yet (or at all when running without one) and boids should still be spawned when it doesn't
+    let rng = &mut rng.as_mut().0;
+    let half_size = bounds.size / 2.0;
+    // Enough columns and rows for the whole TargetPopulation, in about the world's aspect ratio
+    let columns = ((target.0 as f32 * bounds.size.x / bounds.size.y)
+        .sqrt()
+        .ceil() as u64)
+        .max(1);
+    let rows = ((target.0 as u64 + columns - 1) / columns).max(1);
+    let cell = bounds.size / Vec2::new(columns as f32, rows as f32);
+    let spawn_position = |rng: &mut StdRng, index: u64| {
+        let position = match *pattern {
+            SpawnPattern::Random => Vec2::new(
+                rng.gen_range(-half_size.x..half_size.x),
+                rng.gen_range(-half_size.y..half_size.y),
+            ),
+            SpawnPattern::Grid => {
+                // Boids respawned later on take over the spots of the ones before them
+                let slot = index % (columns * rows);
+                let (column, row) = (slot % columns, slot / columns);
+                -half_size + cell * Vec2::new(column as f32 + 0.5, row as f32 + 0.5)
+            }
+            SpawnPattern::Cluster { radius } => random_in_circle(rng, radius),
+        };
+        (position + random_in_circle(rng, jitter.0)).clamp(-half_size, half_size)
+    };
+
+    // Boids spawned this frame are only spawned once commands are applied, so they're
+    // tracked here alongside the ones already flying
+    let mut taken = if spacing.enabled {
+        boids
+            .iter()
+            .map(|(_, transform, _)| transform.translation.truncate())
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let min_distance_squared = (2.0 * spacing.radius).powi(2);
+    let blocked = |position: Vec2, taken: &[Vec2]| {
+        (spacing.enabled
+            && taken
+                .iter()
+                .any(|other| position.distance_squared(*other) < min_distance_squared))
+            || (spacing.clear_of_obstacles
+                && obstacles
+                    .iter()
+                    .any(|obstacle| obstacle.overlaps(position, spacing.radius)))
+    };
+    let mut crowded = 0;
+
+    (0..spawning).for_each(|_| {
+        let mut position = spawn_position(rng, next_index.0);
+        if spacing.enabled || spacing.clear_of_obstacles {
+            let mut attempts = 1;
+            while blocked(position, &taken) {
+                if attempts >= spacing.max_attempts {
+                    crowded += 1;
+                    break;
+                }
+                position = spawn_position(rng, next_index.0);
+                attempts += 1;
+            }
+            if spacing.enabled {
+                taken.push(position);
+            }
+        }
+
+        // Handing out flocks round-robin keeps them all the same size
+        let flock = FlockId((next_index.0 % flocks.0.max(1) as u64) as usize);
+        let boid = spawn_boid(
+            &mut commands,
+            &assets,
+            position,
+            heading.velocity(position, BOID_MAX_SPEED),
+            next_index.take(),
+            flock,
+        );
+        commands.entity(boid).insert(Mass(mass.pick(rng)));
+    });
+
+    if crowded > 0 {
+        eprintln!(
+            "Couldn't find room for {} boids after {} attempts each, they were spawned overlapping other boids or obstacles",
+            crowded, spacing.max_attempts
+        );
+    }
+}
+
+fn spray_boids(
+    spawner: BoidSpawner,
+    time: Res<Time>,
+    spray: Res<CursorSpray>,
+    input: MouseInput,
+    mut target: ResMut<TargetPopulation>,
+    // Partially accumulated boids that will be sprayed once they add up to a whole one
+    mut pending: Local<f32>,
+    boids: Query<Entity, With<Velocity>>,
+) {
+    let alt = input.keys.pressed(KeyCode::LAlt) || input.keys.pressed(KeyCode::RAlt);
+    // Letting go stops the spray right away, nothing left pending comes out later
+    let cursor = match input.cursor() {
+        Some(cursor) if alt && input.buttons.pressed(MouseButton::Left) => cursor,
+        _ => {
+            *pending = 0.0;
+            return;
+        }
+    };
+
+    // The click itself sprays the first boid, holding on sprays the rest
+    if input.buttons.just_pressed(MouseButton::Left) {
+        *pending = 1.0;
+    } else {
+        *pending += spray.rate * time.delta_seconds();
+    }
+    let population = boids.iter().count().max(target.0);
+    let spraying = (pending.floor() as usize).min(spray.max_boids.saturating_sub(population));
+    *pending -= pending.floor();
+
+    let BoidSpawner {
+        mut commands,
+        assets,
+        mut rng,
+        mut next_index,
+        flocks,
+    } = spawner;
+    let rng = &mut rng.as_mut().0;
+    for _ in 0..spraying {
+        let index = next_index.take();
+        let flock = FlockId((index.0 % flocks.0.max(1) as u64) as usize);
+        let velocity = random_in_circle(rng, spray.spread);
+        spawn_boid(&mut commands, &assets, cursor, velocity, index, flock);
+    }
+    target.0 = population + spraying;
+}
+
+/// A random point anywhere within radius of the origin, points are spread evenly over the
+/// circle rather than bunching up in the middle
+fn random_in_circle(rng: &mut StdRng, radius: f32) -> Vec2 {
+    if radius <= 0.0 {
+        return Vec2::ZERO;
+    }
+    let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+    let distance = radius * rng.gen_range(0.0f32..1.0).sqrt();
+    Vec2::new(angle.cos(), angle.sin()) * distance
+}
+
+fn toggle_behaviors(
+    keys: Res<Input<KeyCode>>,
+    mut toggles: ResMut<BehaviorToggles>,
+    mut freeze: ResMut<FreezeSteering>,
+) {
+    let toggles = toggles.as_mut();
+    for (key, toggle) in [
+        (KeyCode::Space, &mut freeze.0),
+        (KeyCode::Key1, &mut toggles.seek),
+        (KeyCode::Key2, &mut toggles.seek_cursor),
+        (KeyCode::Key3, &mut toggles.separation),
+        (KeyCode::Key4, &mut toggles.alignment),
+        (KeyCode::Key5, &mut toggles.cohesion),
+        (KeyCode::Key6, &mut toggles.regroup),
+        (KeyCode::Key7, &mut toggles.formation),
+        (KeyCode::Key8, &mut toggles.attractors),
+        (KeyCode::Key9, &mut toggles.obstacles),
+        (KeyCode::Key0, &mut toggles.follow_path),
+    ] {
+        if keys.just_pressed(key) {
+            *toggle = !*toggle;
+        }
+    }
+}
+
+fn update_boids(
+    heading_offset: Res<HeadingOffset>,
+    smoothing: Res<RotationSmoothing>,
+    mut query: Query<(&mut Transform, &Velocity), Without<Pinned>>,
+) {
+    for (mut transform, velocity) in query.iter_mut() {
+        let transform = transform.as_mut();
+        // If there's no velocity then setting the rotation causes the object not to render due
+        // to angle_between's calculation containing a division by Sqrt(Mag(A)^2 * Mag(B)^2)
+        // which in case of B being 0 would be 0 so division by 0 would result in a NaN
+        // Attempting to render with NaN as angle just results in no rendering happening
+        //
+        // One could argue that setting the angle to 0 or something like that should be done if
+        // the object has no velocity but then any object with no velocity would immediately face
+        // the right side, which makes no sense.
+        // In real life objects preserve their facing direction even after losing all their
+        // velocity, this does just that by refusing to set the rotation when there is no velocity
+        // thus preserving the previous rotation!
+        let speed = velocity.vector.length();
+        if speed.classify() != FpCategory::Zero {
+            // angle
+            let rotation =
+                heading_offset.rotation(Vec2::new(1.0, 0.0).angle_between(velocity.vector));
+            // The same goes for velocities that are merely tiny, they're so noisy that snapping
+            // to them would leave the boid spinning in place
+            transform.rotation = if speed >= smoothing.min_speed {
+                rotation
+            } else {
+                transform.rotation.slerp(rotation, smoothing.slow_turn)
+            };
+        }
+    }
+}
+
+/// Wraps an angle into the range -PI to PI
+fn wrap_angle(angle: f32) -> f32 {
+    (angle + PI).rem_euclid(TAU) - PI
+}
+
+fn bank_boids(
+    params: Res<BankingParams>,
+    heading_offset: Res<HeadingOffset>,
+    mut query: Query<(&mut Transform, &mut Bank)>,
+) {
+    for (mut transform, mut bank) in query.iter_mut() {
+        let transform = transform.as_mut();
+        let bank = bank.as_mut();
+        // update_boids already decided which way the boid faces, and rolling around the nose
+        // doesn't change where the nose points
+        let nose = (transform.rotation * heading_offset.forward()).truncate();
+        let heading = nose.y.atan2(nose.x);
+
+        let turn = bank.previous_heading.map_or(0.0, |previous_heading| {
+            wrap_angle(heading - previous_heading)
+        });
+        bank.previous_heading = Some(heading);
+
+        // Rolling towards the target rather than snapping to it means a boid that stops turning
+        // levels out smoothly instead of all at once
+        let target_roll = (-turn * params.roll_per_turn).clamp(-params.max_roll, params.max_roll);
+        bank.roll += (target_roll - bank.roll) * params.responsiveness;
+
+        // Rolling around the axis the boid's nose points along after facing it towards its
+        // heading, seen from the orthographic camera above this narrows the boid as if it were
+        // tilting
+        transform.rotation = heading_offset.rotation(heading)
+            * Quat::from_axis_angle(heading_offset.forward(), bank.roll);
+    }
+}
+
+/// Keeps a single coordinate (and the velocity along it) inside an edge to edge span of size
+/// centered on the origin
+fn handle_edge(behavior: EdgeBehavior, size: f32, position: &mut f32, velocity: &mut f32) {
+    let half_size = size / 2.0;
+    match behavior {
+        EdgeBehavior::Wrap => *position = (*position + half_size).rem_euclid(size) - half_size,
+        EdgeBehavior::Bounce => {
+            // Mirroring whatever went past the edge back inside, a boid that's somehow more than
+            // a whole span outside is just put on the edge
+            if *position > half_size {
+                *position = (2.0 * half_size - *position).max(-half_size);
+                *velocity = -velocity.abs();
+            } else if *position < -half_size {
+                *position = (-2.0 * half_size - *position).min(half_size);
+                *velocity = velocity.abs();
+            }
+        }
+        EdgeBehavior::Steer | EdgeBehavior::None => {}
+    }
+}
+
+/// Keeps a position (and the velocity at it) inside a circle of radius centered on the origin
+fn handle_circle_edge(
+    behavior: EdgeBehavior,
+    radius: f32,
+    position: &mut Vec2,
+    velocity: &mut Vec2,
+) {
+    let distance = position.length();
+    if distance <= radius {
+        return;
+    }
+    // Anything past the edge is away from the center so this is never a division by zero, and
+    // whatever is more than a whole radius past it lands on the center
+    let outward = *position / distance;
+    let comeback = (2.0 * radius - distance).max(0.0);
+    match behavior {
+        EdgeBehavior::Wrap => *position = -outward * comeback,
+        EdgeBehavior::Bounce => {
+            *position = outward * comeback;
+            // Reflecting off the tangent at the edge only flips the part heading outwards
+            let outwards_speed = velocity.dot(outward);
+            if outwards_speed > 0.0 {
+                *velocity -= 2.0 * outwards_speed * outward;
+            }
+        }
+        EdgeBehavior::Steer | EdgeBehavior::None => {}
+    }
+}
+
+/// Keeps a position (and the velocity at it) inside the arena
+fn handle_arena_edges(
+    arena: ArenaShape,
+    edges: &EdgeBehaviors,
+    bounds: &WorldBounds,
+    position: &mut Vec2,
+    velocity: &mut Vec2,
+) {
+    match arena {
+        ArenaShape::Rect => {
+            handle_edge(edges.x, bounds.size.x, &mut position.x, &mut velocity.x);
+            handle_edge(edges.y, bounds.size.y, &mut position.y, &mut velocity.y);
+        }
+        ArenaShape::Circle { radius } => {
+            handle_circle_edge(edges.x, radius.max(0.0), position, velocity)
+        }
+    }
+}
+
+/// The arena and its edges, for systems keeping things other than boids inside of it
+#[derive(SystemParam)]
+struct ArenaEdges<'a> {
+    arena: Res<'a, ArenaShape>,
+    edges: Res<'a, EdgeBehaviors>,
+    bounds: Res<'a, WorldBounds>,
+}
+
+impl<'a> ArenaEdges<'a> {
+    /// Keeps a position (and the velocity at it) inside the arena, see handle_arena_edges
+    fn handle(&self, position: &mut Vec2, velocity: &mut Vec2) {
+        handle_arena_edges(*self.arena, &self.edges, &self.bounds, position, velocity);
+    }
+}
+
+fn handle_edges(
+    arena: Res<ArenaShape>,
+    edges: Res<EdgeBehaviors>,
+    bounds: Res<WorldBounds>,
+    // A Pinned boid stays where it is even outside of the edges
+    mut query: Query<(&mut Transform, &mut Velocity), Without<Pinned>>,
+) {
+    for (mut transform, mut velocity) in query.iter_mut() {
+        let translation = &mut transform.as_mut().translation;
+        let mut position = translation.truncate();
+        handle_arena_edges(
+            *arena,
+            &edges,
+            &bounds,
+            &mut position,
+            &mut velocity.as_mut().vector,
+        );
+        translation.x = position.x;
+        translation.y = position.y;
+    }
+}
+
+/// Turns boids back in from edges with EdgeBehavior::Steer, see SoftEdge
+fn steer_inside_edges(
+    toggles: Res<BehaviorToggles>,
+    arena: Res<ArenaShape>,
+    edges: Res<EdgeBehaviors>,
+    bounds: Res<WorldBounds>,
+    soft: Res<SoftEdge>,
+    stagger: Res<SteeringStagger>,
+    mut query: Query<(&mut Force, &Velocity, &Transform, &SpawnIndex), Without<Sleeping>>,
+) {
+    let steering = match *arena {
+        ArenaShape::Rect => edges.x == EdgeBehavior::Steer || edges.y == EdgeBehavior::Steer,
+        ArenaShape::Circle { .. } => edges.x == EdgeBehavior::Steer,
+    };
+    if !steering || toggles.all_off() {
+        return;
+    }
+    let margin = soft.margin.max(f32::EPSILON);
+    // How far into the margin something at distance from the center of a span reaching out to
+    // edge is, from 0 at the margin's inner side to 1 at the edge and beyond
+    let depth = |distance: f32, edge: f32| ((distance - (edge - margin)) / margin).clamp(0.0, 1.0);
+
+    for (mut force, velocity, transform, index) in query.iter_mut() {
+        if !stagger.is_due(index) {
+            continue;
+        }
+        let position = transform.translation.truncate();
+        // A boid right on the center (only ever in the margin of an arena no wider than it) is
+        // already as far in as it can get, so it has no direction to be turned in
+        let inward = match *arena {
+            ArenaShape::Rect => {
+                let half_size = bounds.size / 2.0;
+                let axis = |behavior: EdgeBehavior, position: f32, half_size: f32| {
+                    if behavior == EdgeBehavior::Steer {
+                        -position / position.abs().max(f32::EPSILON)
+                            * depth(position.abs(), half_size)
+                    } else {
+                        0.0
+                    }
+                };
+                Vec2::new(
+                    axis(edges.x, position.x, half_size.x),
+                    axis(edges.y, position.y, half_size.y),
+                )
+            }
+            ArenaShape::Circle { radius } => {
+                -position.normalize_or_zero() * depth(position.length(), radius.max(0.0))
+            }
+        };
+        if inward == Vec2::ZERO {
+            continue;
+        }
+
+        let force = force.as_mut();
+        let desired_velocity = inward.normalize() * velocity.max;
+        force.vector += Vec2::clamp_length_max(desired_velocity - velocity.vector, force.max)
+            * inward.length().min(1.0)
+            * soft.interest;
+    }
+}
+
+fn update_lod(
+    params: Res<LodParams>,
+    windows: Res<Windows>,
+    cameras: Query<(&Transform, &OrthographicProjection)>,
+    mut boids: Query<(&Transform, &mut Visible), With<Velocity>>,
+) {
+    // Nothing is drawn into a minimized window anyway, so boids are left as they are rather
+    // than all being hidden until it's restored
+    let window = match windows.as_ref().get_primary() {
+        Some(window) if window.width() > 0.0 && window.height() > 0.0 => window,
+        _ => return,
+    };
+    let (camera, projection) = match cameras.iter().next() {
+        Some(camera) => camera,
+        None => return,
+    };
+
+    // Everything here is in pixels, the projection's scale is how many world units a pixel
+    // covers
+    let shown = params.boid_size / projection.scale >= params.min_screen_size;
+    let half_screen = Vec2::new(window.width(), window.height()) / 2.0 + params.margin;
+    let camera_position = camera.translation.truncate();
+
+    for (Transform { translation, .. }, mut visible) in boids.iter_mut() {
+        let on_screen = ((translation.truncate() - camera_position) / projection.scale)
+            .abs()
+            .cmple(half_screen)
+            .all();
+        visible.is_visible = shown && on_screen;
+    }
+}
+
+fn age_boids(time: Res<Time>, mut query: Query<&mut Age>) {
+    for mut age in query.iter_mut() {
+        age.0 += time.delta_seconds();
+    }
+}
+
+fn color_by_age(
+    mode: Res<ColorMode>,
+    coloring: Res<AgeColoring>,
+    mut query: Query<(&Age, &mut Tint)>,
+) {
+    if *mode != ColorMode::Age {
+        return;
+    }
+    let young = Vec4::from(coloring.young.as_rgba_linear());
+    let old = Vec4::from(coloring.old.as_rgba_linear());
+    for (age, mut tint) in query.iter_mut() {
+        let t = (age.0 / coloring.max_age).clamp(0.0, 1.0);
+        let color = young.lerp(old, t);
+        tint.color = Color::rgba_linear(color.x, color.y, color.z, color.w);
+    }
+}
+
+fn color_by_heading(mode: Res<ColorMode>, mut query: Query<(&Velocity, &mut Tint)>) {
+    if *mode != ColorMode::Heading {
+        return;
+    }
+    for (velocity, mut tint) in query.iter_mut() {
+        // Just like a boid keeps facing the way it was going once it stops, it keeps the color
+        // of that heading too, there's no angle to speak of without any velocity
+        if velocity.vector.length().classify() == FpCategory::Zero {
+            continue;
+        }
+        let angle = velocity.vector.y.atan2(velocity.vector.x).to_degrees();
+        tint.color = Color::hsl(angle.rem_euclid(360.0), 0.8, 0.5);
+    }
+}
+
+fn update_comet_tails(params: Res<CometTailParams>, mut query: Query<(&Velocity, &mut CometTail)>) {
+    for (velocity, mut comet_tail) in query.iter_mut() {
+        comet_tail.length = velocity.vector.length() * params.length_per_speed;
+    }
+}
+
+fn seek_force(
+    target_position: Vec2,
+    current_position: Vec2,
+    current_velocity: Vec2,
+    max_force: f32,
+    interest: f32,
+) -> Vec2 {
+    // target - position
+    let desired_velocity = target_position - current_position;
+
+    // steering force = desired velocity - current velocity
+    Vec2::clamp_length_max(desired_velocity - current_velocity, max_force) * interest
+}
+
+fn seek_target(
+    toggles: Res<BehaviorToggles>,
+    stagger: Res<SteeringStagger>,
+    mut query: Query<(&mut Force, &Velocity, &Transform, &Seek, &SpawnIndex), Without<Sleeping>>,
+    transforms: Query<&Transform>,
+) {
+    if !toggles.seek {
+        return;
+    }
+    for (mut force, velocity, Transform { translation, .. }, seek, index) in query.iter_mut() {
+        if !stagger.is_due(index) {
+            continue;
+        }
+        let force = force.as_mut();
+        if let Ok(target) = transforms.get_component::<Transform>(seek.target) {
+            force.vector += seek_force(
                 target.translation.truncate(),
                 translation.truncate(),
                 velocity.vector,
@@ -220,44 +2116,561 @@ fn seek_target(
     }
 }
 
+/// Where the cursor is in the world, the camera sits at the origin while the cursor's position
+/// is measured from the window's bottom left corner
+///
+/// A window minimized down to nothing has no cursor in it to speak of
+fn cursor_world_position(window: &Window) -> Option<Vec2> {
+    if window.width() <= 0.0 || window.height() <= 0.0 {
+        return None;
+    }
+    window
+        .cursor_position()
+        .map(|cursor| cursor - Vec2::new(window.width(), window.height()) / 2.0)
+}
+
+/// The mouse and keyboard, for systems that do something where the user clicks
+#[derive(SystemParam)]
+struct MouseInput<'a> {
+    windows: Res<'a, Windows>,
+    buttons: Res<'a, Input<MouseButton>>,
+    keys: Res<'a, Input<KeyCode>>,
+}
+
+impl<'a> MouseInput<'a> {
+    /// Where the cursor is in the primary window, see cursor_world_position
+    fn cursor(&self) -> Option<Vec2> {
+        self.windows
+            .get_primary()
+            .and_then(|window| cursor_world_position(window))
+    }
+}
+
+/// Everything about how SeekCursor boids go after the cursor, see each of them for what they do
+#[derive(SystemParam)]
+struct CursorSeeking<'a> {
+    reach: Res<'a, CursorReach>,
+    pursuit: Res<'a, CursorPursuit>,
+    curiosity: Res<'a, CursorCuriosity>,
+    max_prediction: Res<'a, MaxPrediction>,
+}
+
 fn seek_mouse(
-    windows: Res<Windows>,
-    mut query: Query<(&mut Force, &Velocity, &Transform, &SeekCursor)>,
+    toggles: Res<BehaviorToggles>,
+    seeking: CursorSeeking,
+    motion: Res<CursorMotion>,
+    stagger: Res<SteeringStagger>,
+    memory: Res<CursorMemory>,
+    mut query: Query<(&mut Force, &Velocity, &Transform, &SeekCursor, &SpawnIndex)>,
 ) {
-    if let Some(window) = windows.as_ref().get_primary() {
-        if let Some(cursor) = window.cursor_position() {
-            let real_cursor_position = cursor - Vec2::new(window.width(), window.height()) / 2.0;
-            for (mut force, velocity, Transform { translation, .. }, seek_cursor) in
-                query.iter_mut()
-            {
+    if !toggles.seek_cursor {
+        return;
+    }
+    let CursorSeeking {
+        reach,
+        pursuit,
+        curiosity,
+        max_prediction,
+    } = seeking;
+    let real_cursor_position = match memory.target() {
+        Some(cursor) => cursor,
+        None => return,
+    };
+    for (mut force, velocity, Transform { translation, .. }, seek_cursor, index) in query.iter_mut()
+    {
+        if !stagger.is_due(index) {
+            continue;
+        }
+        let position = translation.truncate();
+        if let Some(radius) = reach.0 {
+            if position.distance_squared(real_cursor_position) > radius * radius {
+                continue;
+            }
+        }
+        // The cursor is predicted for about as many frames as the boid would take to get to it
+        // at full speed
+        let prediction = (position.distance(real_cursor_position) / velocity.max.max(f32::EPSILON))
+            .min(max_prediction.0);
+        let predicted = real_cursor_position + motion.velocity * prediction;
+        let target = match *pursuit {
+            CursorPursuit::Seek => real_cursor_position,
+            CursorPursuit::Pursue => predicted,
+            // Seeking the predicted position mirrored around the boid flees from it
+            CursorPursuit::Evade => 2.0 * position - predicted,
+            CursorPursuit::Curious => {
                 let force = force.as_mut();
-                force.vector = seek_force(
-                    real_cursor_position,
-                    translation.truncate(),
-                    velocity.vector,
-                    force.max,
-                    seek_cursor.interest,
-                );
+                force.vector +=
+                    curiosity.steer(position - real_cursor_position, velocity, force.max)
+                        * seek_cursor.interest;
+                continue;
+            }
+        };
+        let force = force.as_mut();
+        force.vector += seek_force(
+            target,
+            position,
+            velocity.vector,
+            force.max,
+            seek_cursor.interest,
+        );
+    }
+}
+
+/// How SeekCursor boids go after the cursor
+enum CursorPursuit {
+    /// Head straight for where the cursor is right now, always a step behind a moving cursor
+    Seek,
+    /// Head for where the cursor will be by the time the boid gets there, judging by how fast
+    /// the cursor has been moving
+    Pursue,
+    /// Flee from where the cursor will be by the time it could reach the boid, best paired with
+    /// a CursorReach so only boids near the cursor scatter
+    Evade,
+    /// Drift closer to the cursor from a distance but back off when it gets too close, hovering
+    /// around it at a comfortable distance, see CursorCuriosity
+    Curious,
+}
+
+impl Default for CursorPursuit {
+    fn default() -> Self {
+        CursorPursuit::Seek
+    }
+}
+
+/// How Curious boids feel about the cursor depending on how far away it is, like a curious but
+/// cautious animal
+///
+/// Within comfort_radius the cursor pushes boids away, harder the closer they are. Between that
+/// and curiosity_radius it gently draws them in, and past curiosity_radius boids don't care about
+/// it at all. Both sides fade to nothing at comfort_radius so there's no sudden flip from one to
+/// the other for boids to keep overshooting
+struct CursorCuriosity {
+    comfort_radius: f32,
+    curiosity_radius: f32,
+    /// How fast boids drift in at the middle of the curious range, as a fraction of Velocity.max
+    attraction: f32,
+    /// How fast boids back off right at the cursor, as a fraction of Velocity.max
+    repulsion: f32,
+}
+
+impl Default for CursorCuriosity {
+    fn default() -> Self {
+        CursorCuriosity {
+            comfort_radius: 80.0,
+            curiosity_radius: 300.0,
+            attraction: 0.3,
+            repulsion: 1.0,
+        }
+    }
+}
+
+impl CursorCuriosity {
+    /// How fast a boid this far away from the cursor wants to head towards it, negative when it
+    /// wants to get away
+    fn speed_towards(&self, distance: f32) -> f32 {
+        if distance < self.comfort_radius {
+            -self.repulsion * (1.0 - distance / self.comfort_radius.max(f32::EPSILON))
+        } else if distance < self.curiosity_radius {
+            // Rises from nothing at the comfort radius and falls back to nothing at the edge of
+            // the curious range so boids wandering in and out of it don't get jerked around
+            let t =
+                (distance - self.comfort_radius) / (self.curiosity_radius - self.comfort_radius);
+            self.attraction * (PI * t).sin()
+        } else {
+            0.0
+        }
+    }
+
+    /// The steering force for a boid offset this much from the cursor
+    ///
+    /// The boid steers towards a velocity rather than just being pushed, so as that velocity
+    /// shrinks to nothing around the comfort radius the boid brakes instead of coasting past it
+    fn steer(&self, offset: Vec2, velocity: &Velocity, max_force: f32) -> Vec2 {
+        let distance = offset.length();
+        if distance >= self.curiosity_radius {
+            return Vec2::ZERO;
+        }
+        let towards = -offset.normalize_or_zero();
+        let desired_velocity = towards * self.speed_towards(distance) * velocity.max;
+        Vec2::clamp_length_max(desired_velocity - velocity.vector, max_force)
+    }
+}
+
+/// How far ahead Pursue and Evade predict the cursor, in frames
+///
+/// A far away boid is predicted for longer since it takes longer to get there, this caps that
+/// so boids across the world don't aim way off past the cursor
+struct MaxPrediction(f32);
+
+impl Default for MaxPrediction {
+    fn default() -> Self {
+        MaxPrediction(30.0)
+    }
+}
+
+/// Where the cursor was last frame and how far it moved since the frame before, in the same per
+/// frame units as Velocity
+///
+/// There's no previous position on the first frame or after the cursor comes back into the
+/// window, the cursor is treated as standing still until it has been seen twice in a row
+#[derive(Default)]
+struct CursorMotion {
+    previous: Option<Vec2>,
+    velocity: Vec2,
+}
+
+/// Where the cursor was last seen in the window, SeekCursor boids keep heading there for up to
+/// linger seconds after the cursor leaves the window rather than losing track of it all at once.
+/// A linger of None keeps heading there until the cursor comes back
+///
+/// Until the cursor has been in the window at all there's nowhere to head for
+struct CursorMemory {
+    linger: Option<f32>,
+    last_seen: Option<Vec2>,
+    /// Seconds since the cursor was last in the window
+    since_seen: f32,
+}
+
+impl Default for CursorMemory {
+    fn default() -> Self {
+        CursorMemory {
+            linger: Some(2.0),
+            last_seen: None,
+            since_seen: 0.0,
+        }
+    }
+}
+
+impl CursorMemory {
+    /// Where SeekCursor boids should head for, the cursor itself while it's in the window
+    fn target(&self) -> Option<Vec2> {
+        let remembered = self.linger.map_or(true, |linger| self.since_seen <= linger);
+        self.last_seen.filter(|_| remembered)
+    }
+}
+
+fn track_cursor(
+    time: Res<Time>,
+    windows: Res<Windows>,
+    mut motion: ResMut<CursorMotion>,
+    mut memory: ResMut<CursorMemory>,
+) {
+    let cursor = windows
+        .get_primary()
+        .and_then(|window| cursor_world_position(window));
+    let motion = motion.as_mut();
+    motion.velocity = match (motion.previous, cursor) {
+        (Some(previous), Some(cursor)) => cursor - previous,
+        _ => Vec2::ZERO,
+    };
+    motion.previous = cursor;
+
+    let memory = memory.as_mut();
+    match cursor {
+        Some(cursor) => {
+            memory.last_seen = Some(cursor);
+            memory.since_seen = 0.0;
+        }
+        None => memory.since_seen += time.delta_seconds(),
+    }
+}
+
+/// Control left clicking moves the whole flock over so its centroid lands on the cursor, every
+/// boid keeps its place in the flock and its velocity so the flock flies on from there. Pinned
+/// boids stay behind
+fn teleport_flock(
+    deterministic: Res<DeterministicMode>,
+    windows: Res<Windows>,
+    buttons: Res<Input<MouseButton>>,
+    keys: Res<Input<KeyCode>>,
+    mut boids: Query<(&mut Transform, &SpawnIndex), (With<Velocity>, Without<Pinned>)>,
+) {
+    let control = keys.pressed(KeyCode::LControl) || keys.pressed(KeyCode::RControl);
+    if !control || !buttons.just_pressed(MouseButton::Left) {
+        return;
+    }
+    // There's no cursor position while the cursor is outside of the window
+    let cursor = match windows
+        .get_primary()
+        .and_then(|window| cursor_world_position(window))
+    {
+        Some(cursor) => cursor,
+        None => return,
+    };
+
+    let mut positions = boids
+        .iter_mut()
+        .map(|(transform, &index)| (index, transform.translation.truncate()))
+        .collect::<Vec<_>>();
+    if positions.is_empty() {
+        return;
+    }
+    if deterministic.0 {
+        positions.sort_unstable_by_key(|&(index, _)| index);
+    }
+    let centroid = positions
+        .iter()
+        .fold(Vec2::ZERO, |sum, (_, position)| sum + *position)
+        / positions.len() as f32;
+
+    let delta = (cursor - centroid).extend(0.0);
+    for (mut transform, _) in boids.iter_mut() {
+        transform.translation += delta;
+    }
+}
+
+/// Draws CursorReach's radius as a ring following the cursor
+fn draw_cursor_reach(
+    drawing: OverlayDrawing,
+    reach: Res<CursorReach>,
+    windows: Res<Windows>,
+    mut ring: Local<Option<Entity>>,
+    mut rings: Query<(&mut Transform, &mut Visible)>,
+) {
+    let OverlayDrawing {
+        overlays,
+        mut commands,
+        assets,
+        mut meshes,
+    } = drawing;
+    let show = overlays.shows(DebugOverlay::CursorReach);
+    let cursor = windows
+        .get_primary()
+        .and_then(|window| cursor_world_position(window));
+
+    let ring = match *ring {
+        Some(ring) => ring,
+        None if show => {
+            let mut bundle = create_boid_mesh_bundle(
+                assets.pipeline.clone(),
+                meshes.add(shapes::create_ring_mesh(64, 0.02)),
+                Vec2::ZERO,
+            );
+            bundle.visible.is_visible = false;
+            // The ring shows up from the next frame on, once it has actually been spawned
+            *ring = Some(
+                commands
+                    .spawn_bundle(bundle)
+                    .insert(CometTail::default())
+                    .insert(Tint {
+                        color: Color::WHITE,
+                    })
+                    .insert(Alarm::default())
+                    .id(),
+            );
+            return;
+        }
+        None => return,
+    };
+
+    if let Ok((mut transform, mut visible)) = rings.get_mut(ring) {
+        match (show, reach.0, cursor) {
+            (true, Some(radius), Some(cursor)) => {
+                visible.is_visible = true;
+                transform.translation = cursor.extend(DebugOverlay::CursorReach.z());
+                transform.scale = Vec3::new(radius, radius, 1.0);
             }
+            _ => visible.is_visible = false,
         }
     }
 }
 
-fn apply_force(mut query: Query<(&mut Velocity, &mut Force)>) {
-    for (mut velocity, mut force) in query.iter_mut() {
+/// Everything apply_force needs to know about how boids move
+#[derive(SystemParam)]
+struct Motion<'a> {
+    substeps: Res<'a, Substeps>,
+    cap_mode: Res<'a, CapMode>,
+    speed_cap_mode: Res<'a, SpeedCapMode>,
+    min_speed: Res<'a, MinSpeed>,
+    gravity: Res<'a, Gravity>,
+    max_acceleration: Res<'a, MaxAcceleration>,
+    rotation_only: Res<'a, RotationOnly>,
+}
+
+/// Moves every boid by the force on it, in Substeps steps
+///
+/// Boids are pushed back out of any obstacle they end up inside of after every step, so the
+/// more steps a tick is split into the smaller the obstacles fast boids can't fly through
+fn apply_force(
+    motion: Motion,
+    obstacles: NearbyObstacles,
+    mut query: Query<(
+        &mut Transform,
+        &mut Velocity,
+        &mut Force,
+        Option<&Mass>,
+        Option<&Pinned>,
+    )>,
+) {
+    let Motion {
+        substeps,
+        cap_mode,
+        speed_cap_mode,
+        min_speed,
+        gravity,
+        max_acceleration,
+        rotation_only,
+    } = motion;
+    // A substep count of 0 would freeze everything, treat it as no subdivision instead
+    let substeps = substeps.0.max(1);
+    for (mut transform, mut velocity, mut force, mass, pinned) in query.iter_mut() {
+        let transform = transform.as_mut();
         let force = force.as_mut();
+        // Pinned boids are pushed around just like the rest, it just doesn't get them anywhere
+        if pinned.is_some() {
+            force.vector = Vec2::ZERO;
+            continue;
+        }
         let velocity = velocity.as_mut();
-        // velocity = current velocity + acceleration; acceleration = force if mass = 1
-        velocity.vector = Vec2::clamp_length_max(velocity.vector + force.vector, velocity.max);
+        // Gravity isn't part of a boid's own steering so it isn't limited by Force.max, while
+        // Velocity.max still applies and acts as a terminal velocity for falling boids
+        let acceleration =
+            steering_acceleration(force, &cap_mode, mass, &max_acceleration) + gravity.0;
+
+        #[cfg(feature = "fixed-point")]
+        {
+            let max_speed = match (&*cap_mode, &*speed_cap_mode) {
+                (CapMode::Acceleration, _) => None,
+                // There's no tanh in fixed-point, so the soft cap is just as hard as the hard one
+                (_, SpeedCapMode::Hard | SpeedCapMode::Soft { .. }) => Some(velocity.max),
+            };
+            let (position, new_velocity) = fixed::integrate(
+                transform.translation.truncate(),
+                velocity.vector,
+                acceleration,
+                substeps,
+                fixed::SpeedLimits {
+                    max: max_speed,
+                    min: min_speed.0,
+                },
+                !rotation_only.0,
+                |position, velocity| obstacles.push_out(position, velocity),
+            );
+            transform.translation.x = position.x;
+            transform.translation.y = position.y;
+            velocity.vector = new_velocity;
+        }
+        #[cfg(not(feature = "fixed-point"))]
+        {
+            let step = 1.0 / substeps as f32;
+            for _ in 0..substeps {
+                // velocity = current velocity + acceleration
+                velocity.vector += acceleration * step;
+                if let CapMode::Velocity | CapMode::Both = *cap_mode {
+                    velocity.vector = speed_cap_mode.cap(velocity.vector, velocity.max);
+                }
+                let speed = velocity.vector.length();
+                if speed > 0.0 && speed < min_speed.0 {
+                    velocity.vector *= min_speed.0 / speed;
+                }
+                // new position = current position + velocity
+                if !rotation_only.0 {
+                    let mut position = transform.translation.truncate() + velocity.vector * step;
+                    obstacles.push_out(&mut position, &mut velocity.vector);
+                    transform.translation.x = position.x;
+                    transform.translation.y = position.y;
+                }
+            }
+        }
         // Once a force is applied it is removed
         force.vector = Vec2::ZERO;
     }
 }
 
+/// How much a boid's own steering accelerates it, its force capped by CapMode and then divided
+/// by its mass, capped again by MaxAcceleration
+fn steering_acceleration(
+    force: &Force,
+    cap_mode: &CapMode,
+    mass: Option<&Mass>,
+    max_acceleration: &MaxAcceleration,
+) -> Vec2 {
+    let steering = match *cap_mode {
+        CapMode::Acceleration | CapMode::Both => Vec2::clamp_length_max(force.vector, force.max),
+        CapMode::Velocity => force.vector,
+    };
+    // acceleration = force / mass
+    let steering = steering / mass.map_or(1.0, |mass| mass.0);
+    match max_acceleration.0 {
+        Some(max) => Vec2::clamp_length_max(steering, max),
+        None => steering,
+    }
+}
+
+/// Compiles source up front so a mistake in it gets reported properly, rather than as a panic deep
+/// in the renderer once the pipeline is first used
+///
+/// A shader that doesn't compile is swapped for fallback, which should be simple enough to always
+/// compile, so the window still opens and the rest of the app can be debugged
+fn compile_shader(stage: ShaderStage, source: &str, fallback: &str) -> Shader {
+    let shader = Shader::from_glsl(stage, source);
+    let error = match shader.get_spirv(None) {
+        Ok(_) => return shader,
+        Err(error) => error.to_string(),
+    };
+
+    eprintln!(
+        "The {:?} shader failed to compile, falling back to a plain one:\n{}",
+        stage, error
+    );
+    // Compilers report errors as something like `shader:12: error: ...`, every line that's
+    // mentioned is shown along with its number
+    let lines = source.lines().collect::<Vec<_>>();
+    error
+        .split(':')
+        .filter_map(|part| part.trim().parse::<usize>().ok())
+        .filter_map(|number| Some((number, lines.get(number.checked_sub(1)?)?)))
+        .for_each(|(number, line)| eprintln!("{:>4} | {}", number, line));
+
+    Shader::from_glsl(stage, fallback)
+}
+
 const VERTEX_SHADER: &str = r"
 #version 450
 layout(location = 0) in vec3 Vertex_Position;
 layout(location = 1) in vec3 Vertex_Color;
+layout(location = 2) in float Vertex_Tail;
+layout(location = 1) out vec3 v_Color;
+layout(set = 0, binding = 0) uniform CameraViewProj {
+    mat4 ViewProj;
+};
+layout(set = 1, binding = 0) uniform Transform {
+    mat4 Model;
+};
+layout(set = 2, binding = 0) uniform CometTail_length {
+    float TailLength;
+};
+void main() {
+    v_Color = Vertex_Color;
+    // Only the tail's tip is marked by Vertex_Tail, pulling it back along the boid's own x axis
+    // (away from its nose) stretches it into a tail
+    vec3 position = Vertex_Position - vec3(Vertex_Tail * TailLength, 0.0, 0.0);
+    gl_Position = ViewProj * Model * vec4(position, 1.0);
+}
+";
+
+const FRAGMENT_SHADER: &str = r"
+#version 450
+layout(location = 1) in vec3 v_Color;
+layout(location = 0) out vec4 o_Target;
+layout(set = 3, binding = 0) uniform Tint_color {
+    vec4 TintColor;
+};
+layout(set = 4, binding = 0) uniform Alarm_level {
+    float AlarmLevel;
+};
+void main() {
+    // Startled boids flash towards white
+    o_Target = mix(vec4(v_Color, 1.0) * TintColor, vec4(1.0), AlarmLevel * 0.8);
+}
+";
+
+/// Draws meshes where they are in their own colors and nothing else, used in place of
+/// VERTEX_SHADER when it doesn't compile
+const FALLBACK_VERTEX_SHADER: &str = r"
+#version 450
+layout(location = 0) in vec3 Vertex_Position;
+layout(location = 1) in vec3 Vertex_Color;
 layout(location = 1) out vec3 v_Color;
 layout(set = 0, binding = 0) uniform CameraViewProj {
     mat4 ViewProj;
@@ -271,7 +2684,8 @@ void main() {
 }
 ";
 
-const FRAGMENT_SHADER: &str = r"
+/// Used in place of FRAGMENT_SHADER when it doesn't compile
+const FALLBACK_FRAGMENT_SHADER: &str = r"
 #version 450
 layout(location = 1) in vec3 v_Color;
 layout(location = 0) out vec4 o_Target;
@@ -279,3 +2693,757 @@ void main() {
     o_Target = vec4(v_Color, 1.0);
 }
 ";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_world::test_world;
+    use bevy::{
+        ecs::{schedule::Stage, system::CommandQueue, world::World},
+        window::{WindowDescriptor, WindowId},
+    };
+
+    /// A boid's speed after each of ticks ticks of being pushed along x by force, starting from
+    /// a standstill with a Force.max of 1 and a Velocity.max of 3
+    fn speeds_under_constant_force(world: &mut World, force: f32, ticks: usize) -> Vec<f32> {
+        let boid = world
+            .spawn()
+            .insert_bundle((
+                Transform::default(),
+                Velocity {
+                    vector: Vec2::ZERO,
+                    max: 3.0,
+                },
+                Force {
+                    vector: Vec2::ZERO,
+                    max: 1.0,
+                },
+            ))
+            .id();
+        let mut stage = SystemStage::single(apply_force);
+        (0..ticks)
+            .map(|_| {
+                world.get_mut::<Force>(boid).unwrap().vector = Vec2::new(force, 0.0);
+                stage.run(world);
+                world.get::<Velocity>(boid).unwrap().vector.length()
+            })
+            .collect()
+    }
+
+    fn assert_speeds(speeds: &[f32], expected: &[f32]) {
+        assert_eq!(speeds.len(), expected.len());
+        for (speed, expected) in speeds.iter().zip(expected) {
+            assert!(
+                (speed - expected).abs() < 1e-4,
+                "{:?} != {:?}",
+                speeds,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn acceleration_cap_limits_the_force_only() {
+        let mut world = test_world(0);
+        world.insert_resource(CapMode::Acceleration);
+        let speeds = speeds_under_constant_force(&mut world, 10.0, 5);
+        // Speeding up by Force.max every tick, right past Velocity.max
+        assert_speeds(&speeds, &[1.0, 2.0, 3.0, 4.0, 5.0]);
+    }
+
+    #[test]
+    fn velocity_cap_limits_the_speed_only() {
+        let mut world = test_world(0);
+        world.insert_resource(CapMode::Velocity);
+        let speeds = speeds_under_constant_force(&mut world, 10.0, 3);
+        // The whole force goes through, so Velocity.max is reached right away
+        assert_speeds(&speeds, &[3.0, 3.0, 3.0]);
+    }
+
+    #[test]
+    fn capping_both_limits_the_force_and_the_speed() {
+        let mut world = test_world(0);
+        world.insert_resource(CapMode::Both);
+        let speeds = speeds_under_constant_force(&mut world, 10.0, 5);
+        assert_speeds(&speeds, &[1.0, 2.0, 3.0, 3.0, 3.0]);
+    }
+
+    /// Where a boid flying straight at a small obstacle ends up after a tick split into substeps
+    fn fly_at_obstacle(substeps: u32) -> Vec2 {
+        let mut world = test_world(0);
+        world.insert_resource(Substeps(substeps));
+        world.spawn().insert(Obstacle {
+            pos: Vec2::new(21.0, 0.0),
+            radius: 3.0,
+        });
+        let boid = world
+            .spawn()
+            .insert_bundle((
+                Transform::default(),
+                Velocity {
+                    vector: Vec2::new(40.0, 0.0),
+                    max: 40.0,
+                },
+                Force {
+                    vector: Vec2::ZERO,
+                    max: 1.0,
+                },
+            ))
+            .id();
+        SystemStage::single(obstacles::update_obstacle_grid).run(&mut world);
+        SystemStage::single(apply_force).run(&mut world);
+        world.get::<Transform>(boid).unwrap().translation.truncate()
+    }
+
+    #[test]
+    fn substeps_stop_fast_boids_tunneling_through_obstacles() {
+        // In one step the boid jumps clean over the obstacle
+        assert!(fly_at_obstacle(1).x > 24.0);
+        // Split into 8 steps of 5 one lands inside it, and the boid is stopped at its near edge
+        let position = fly_at_obstacle(8);
+        assert!(
+            (position - Vec2::new(18.0, 0.0)).length() < 1e-3,
+            "{}",
+            position
+        );
+    }
+
+    #[test]
+    fn heavy_boids_are_held_to_max_acceleration() {
+        let force = Force {
+            vector: Vec2::new(1000.0, 0.0),
+            max: 1000.0,
+        };
+        let mass = Mass(10.0);
+        let acceleration = steering_acceleration(
+            &force,
+            &CapMode::Both,
+            Some(&mass),
+            &MaxAcceleration(Some(2.0)),
+        );
+        assert!((acceleration - Vec2::new(2.0, 0.0)).length() < 1e-5);
+
+        // Without the cap the same boid speeds up by force / mass
+        let acceleration =
+            steering_acceleration(&force, &CapMode::Both, Some(&mass), &MaxAcceleration(None));
+        assert!((acceleration - Vec2::new(100.0, 0.0)).length() < 1e-5);
+    }
+
+    #[test]
+    fn spawn_masses_stay_in_range() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mass = SpawnMass { min: 0.5, max: 4.0 };
+        for _ in 0..1000 {
+            let picked = mass.pick(&mut rng);
+            assert!((0.5..=4.0).contains(&picked), "picked {}", picked);
+        }
+        assert_eq!(SpawnMass::default().pick(&mut rng), 1.0);
+    }
+
+    #[test]
+    fn heading_offset_turns_up_pointing_art_to_face_its_velocity() {
+        let mut world = World::new();
+        world.insert_resource(HeadingOffset(std::f32::consts::FRAC_PI_2));
+        world.insert_resource(RotationSmoothing::default());
+        let velocities = [
+            Vec2::new(1.0, 0.0),
+            Vec2::new(0.0, 1.0),
+            Vec2::new(-2.0, 1.0),
+            Vec2::new(0.5, -3.0),
+        ];
+        let boids = velocities
+            .iter()
+            .map(|&vector| {
+                world
+                    .spawn()
+                    .insert_bundle((Transform::default(), Velocity { vector, max: 5.0 }))
+                    .id()
+            })
+            .collect::<Vec<_>>();
+
+        SystemStage::single(update_boids).run(&mut world);
+
+        for (boid, velocity) in boids.into_iter().zip(velocities) {
+            let nose = (world.get::<Transform>(boid).unwrap().rotation * Vec3::Y).truncate();
+            assert!(
+                (nose - velocity.normalize()).length() < 1e-4,
+                "nose {} for velocity {}",
+                nose,
+                velocity
+            );
+        }
+    }
+
+    #[test]
+    fn age_only_ever_goes_up() {
+        let mut world = World::new();
+        world.insert_resource(Time::default());
+        let boid = world.spawn().insert(Age::default()).id();
+        let mut stage = SystemStage::single(age_boids);
+        let mut previous = 0.0;
+        for _ in 0..5 {
+            std::thread::sleep(std::time::Duration::from_millis(2));
+            world.get_resource_mut::<Time>().unwrap().update();
+            stage.run(&mut world);
+            let age = world.get::<Age>(boid).unwrap().0;
+            assert!(age >= previous, "{} after {}", age, previous);
+            previous = age;
+        }
+        // The first update only starts the clock, every one after it moves it on
+        assert!(previous > 0.0);
+    }
+
+    /// The flock's metrics after spawning it from seed and letting it flock for ticks ticks
+    fn flock_deterministically(seed: u64, ticks: usize) -> FlockMetrics {
+        let mut world = test_world(seed);
+        world.insert_resource(DeterministicMode(true));
+        flock_for(world, ticks)
+    }
+
+    /// The flock's metrics after spawning it into world and letting it flock for ticks ticks
+    fn flock_for(mut world: World, ticks: usize) -> FlockMetrics {
+        SystemStage::single(regulate_population).run(&mut world);
+        // One stage per system runs them one after the other in the same order every tick
+        let mut stages = vec![
+            SystemStage::single(grid::update_spatial_grid),
+            SystemStage::single(flocking::gather_neighbors),
+            SystemStage::single(flocking::fade_awareness),
+            SystemStage::single(flocking::separate),
+            SystemStage::single(flocking::align),
+            SystemStage::single(flocking::cohere),
+            SystemStage::single(apply_force),
+            SystemStage::single(metrics::measure_flock),
+        ];
+        for _ in 0..ticks {
+            for stage in stages.iter_mut() {
+                stage.run(&mut world);
+            }
+        }
+        world.remove_resource::<FlockMetrics>().unwrap()
+    }
+
+    #[test]
+    fn seeded_deterministic_runs_measure_the_same() {
+        let first = flock_deterministically(42, 60);
+        let second = flock_deterministically(42, 60);
+        let bits = |metrics: &FlockMetrics| {
+            [
+                metrics.average_speed.current,
+                metrics.average_speed.average,
+                metrics.polarization.current,
+                metrics.polarization.average,
+                metrics.spread.current,
+                metrics.spread.average,
+                metrics.heading.x,
+                metrics.heading.y,
+            ]
+            .map(f32::to_bits)
+        };
+        assert_eq!(bits(&first), bits(&second));
+        // And the flock did actually get going, two runs that never moved would match trivially
+        assert!(first.average_speed.current > 0.0);
+    }
+
+    #[test]
+    fn presets_flock_differently() {
+        let presets = [
+            flocking::FlockingPreset::SchoolingFish,
+            flocking::FlockingPreset::SwarmingGnats,
+            flocking::FlockingPreset::MigratingBirds,
+        ];
+        let metrics = presets
+            .iter()
+            .map(|preset| {
+                let mut world = test_world(42);
+                world.insert_resource(DeterministicMode(true));
+                world.insert_resource(preset.params());
+                let metrics = flock_for(world, 300);
+                (metrics.polarization.current, metrics.spread.current)
+            })
+            .collect::<Vec<_>>();
+
+        for (i, &(polarization, spread)) in metrics.iter().enumerate() {
+            for &(other_polarization, other_spread) in &metrics[i + 1..] {
+                assert!(
+                    (polarization - other_polarization).abs() > 1e-3
+                        || (spread - other_spread).abs() > 1e-2,
+                    "two presets flocked alike: {:?}",
+                    metrics
+                );
+            }
+        }
+    }
+
+    /// The biggest turn a boid makes in a single frame while its velocity is tiny and random
+    fn largest_turn_at_low_speed(smoothing: RotationSmoothing) -> f32 {
+        let mut world = World::new();
+        world.insert_resource(HeadingOffset::default());
+        world.insert_resource(smoothing);
+        let boid = world
+            .spawn()
+            .insert_bundle((
+                Transform::default(),
+                Velocity {
+                    vector: Vec2::ZERO,
+                    max: 1.0,
+                },
+            ))
+            .id();
+        let mut stage = SystemStage::single(update_boids);
+        let mut rng = StdRng::seed_from_u64(5);
+        let heading = |world: &World| {
+            let nose = world.get::<Transform>(boid).unwrap().rotation * Vec3::X;
+            nose.y.atan2(nose.x)
+        };
+        (0..50)
+            .map(|_| {
+                let before = heading(&world);
+                world.get_mut::<Velocity>(boid).unwrap().vector = random_in_circle(&mut rng, 0.01);
+                stage.run(&mut world);
+                wrap_angle(heading(&world) - before).abs()
+            })
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn slow_noisy_boids_turn_gently() {
+        let smoothed = largest_turn_at_low_speed(RotationSmoothing {
+            min_speed: 0.05,
+            slow_turn: 0.05,
+        });
+        // However far off the noise points, the boid turns at most 5% of the way there
+        assert!(smoothed <= 0.05 * PI + 1e-4, "turned {}", smoothed);
+
+        // Snapping straight to the noise instead spins the boid around wildly
+        let snapped = largest_turn_at_low_speed(RotationSmoothing {
+            min_speed: 0.0,
+            slow_turn: 0.05,
+        });
+        assert!(snapped > PI / 2.0, "turned {}", snapped);
+    }
+
+    /// Where every boid in world is
+    fn boid_positions(world: &mut World) -> Vec<Vec2> {
+        world
+            .query_filtered::<&Transform, With<Velocity>>()
+            .iter(world)
+            .map(|transform| transform.translation.truncate())
+            .collect()
+    }
+
+    #[test]
+    fn spaced_out_boids_spawn_apart() {
+        let mut world = test_world(9);
+        world.insert_resource(SpawnSpacing {
+            enabled: true,
+            clear_of_obstacles: true,
+            radius: 5.0,
+            max_attempts: 100,
+        });
+        SystemStage::single(regulate_population).run(&mut world);
+
+        let positions = boid_positions(&mut world);
+        assert_eq!(positions.len(), TargetPopulation::default().0);
+        for (i, a) in positions.iter().enumerate() {
+            for b in &positions[i + 1..] {
+                assert!(a.distance(*b) >= 10.0, "{} and {} overlap", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn soft_speed_cap_eases_into_top_speed() {
+        let mut world = test_world(0);
+        world.insert_resource(CapMode::Both);
+        world.insert_resource(SpeedCapMode::Hard);
+        let hard = speeds_under_constant_force(&mut world, 10.0, 12);
+        world.insert_resource(SpeedCapMode::Soft { softness: 0.5 });
+        let soft = speeds_under_constant_force(&mut world, 10.0, 12);
+
+        // The hard cap stops dead at Velocity.max as soon as it gets there
+        assert_speeds(&hard[..4], &[1.0, 2.0, 3.0, 3.0]);
+        // The soft one creeps up on it more and more slowly and settles just short of it
+        assert!(soft.windows(2).all(|pair| pair[1] >= pair[0]), "{:?}", soft);
+        assert!(soft.iter().all(|&speed| speed < 3.0), "{:?}", soft);
+        assert!(soft[11] > 2.8, "{:?}", soft);
+        // Below the knee at half of Velocity.max nothing is held back
+        assert!((soft[0] - hard[0]).abs() < 1e-4);
+        assert!(soft.iter().zip(&hard).all(|(soft, hard)| soft <= hard));
+    }
+
+    #[test]
+    fn boids_and_camera_spawn_without_a_window() {
+        let mut world = test_world(3);
+        world.insert_resource(CameraFraming::default());
+        assert!(world.get_resource::<Windows>().is_none());
+        SystemStage::single(spawn_camera).run(&mut world);
+        SystemStage::single(regulate_population).run(&mut world);
+
+        let cameras = world
+            .query::<&OrthographicProjection>()
+            .iter(&world)
+            .count();
+        assert_eq!(cameras, 1);
+        assert_eq!(
+            boid_positions(&mut world).len(),
+            TargetPopulation::default().0
+        );
+    }
+
+    #[test]
+    fn braking_boids_keep_to_min_speed() {
+        let mut world = test_world(0);
+        world.insert_resource(MinSpeed(0.3));
+        let mut spawn = |velocity| {
+            world
+                .spawn()
+                .insert_bundle((
+                    Transform::default(),
+                    Velocity {
+                        vector: velocity,
+                        max: 3.0,
+                    },
+                    Force {
+                        vector: Vec2::ZERO,
+                        max: 1.0,
+                    },
+                ))
+                .id()
+        };
+        let coasting = spawn(Vec2::new(0.5, 0.0));
+        let stopped = spawn(Vec2::ZERO);
+
+        let mut stage = SystemStage::single(apply_force);
+        for _ in 0..10 {
+            // Braking hard enough that it'd stop dead within a couple of ticks without MinSpeed
+            world.get_mut::<Force>(coasting).unwrap().vector = Vec2::new(-0.2, 0.0);
+            stage.run(&mut world);
+            let velocity = world.get::<Velocity>(coasting).unwrap().vector;
+            assert!(velocity.length() >= 0.3 - 1e-4, "{}", velocity);
+        }
+        // And a boid that's already stopped isn't sent off in some made up direction
+        assert_eq!(world.get::<Velocity>(stopped).unwrap().vector, Vec2::ZERO);
+    }
+
+    #[test]
+    fn boids_wrap_on_one_axis_and_bounce_on_the_other_at_once() {
+        let mut world = World::new();
+        world.insert_resource(ArenaShape::Rect);
+        world.insert_resource(EdgeBehaviors {
+            x: EdgeBehavior::Wrap,
+            y: EdgeBehavior::Bounce,
+        });
+        world.insert_resource(WorldBounds {
+            size: Vec2::new(200.0, 100.0),
+        });
+        // Gone 5 past the right edge and 3 past the top one in the same frame
+        let boid = world
+            .spawn()
+            .insert_bundle((
+                Transform::from_xyz(105.0, 53.0, 0.0),
+                Velocity {
+                    vector: Vec2::new(4.0, 2.0),
+                    max: 5.0,
+                },
+            ))
+            .id();
+        SystemStage::single(handle_edges).run(&mut world);
+
+        // It comes back in 5 from the left edge and is mirrored back 3 below the top edge, still
+        // heading right but now heading down
+        let position = world.get::<Transform>(boid).unwrap().translation.truncate();
+        assert!(
+            (position - Vec2::new(-95.0, 47.0)).length() < 1e-4,
+            "{}",
+            position
+        );
+        assert_eq!(
+            world.get::<Velocity>(boid).unwrap().vector,
+            Vec2::new(4.0, -2.0)
+        );
+    }
+
+    #[test]
+    fn head_on_boids_break_their_stalemate() {
+        let mut world = test_world(0);
+        let mut breaking = StalemateBreaking::default();
+        breaking.enabled = true;
+        world.insert_resource(breaking);
+        world.insert_resource(SteeringStagger::default());
+        world.insert_resource(SpatialGrid::default());
+        let mut spawn = |x| {
+            world
+                .spawn()
+                .insert_bundle((
+                    Transform::from_xyz(x, 0.0, 0.0),
+                    Velocity {
+                        vector: Vec2::ZERO,
+                        max: BOID_MAX_SPEED,
+                    },
+                    Force {
+                        vector: Vec2::ZERO,
+                        max: BOID_MAX_FORCE,
+                    },
+                    SpawnIndex(0),
+                ))
+                .id()
+        };
+        let left = spawn(-5.0);
+        let right = spawn(5.0);
+        world.entity_mut(left).insert(Neighbors(vec![right]));
+        world.entity_mut(right).insert(Neighbors(vec![left]));
+
+        let mut stages = [
+            SystemStage::single(flocking::break_stalemates),
+            SystemStage::single(apply_force),
+        ];
+        for _ in 0..5 {
+            // Everything else the boids steer by cancels out, facing each other like this
+            for boid in [left, right] {
+                world.get_mut::<Force>(boid).unwrap().vector = Vec2::ZERO;
+            }
+            for stage in stages.iter_mut() {
+                stage.run(&mut world);
+            }
+        }
+
+        // Each of them has stepped off to its own right, out of the other's way
+        let y = |world: &World, boid| world.get::<Transform>(boid).unwrap().translation.y;
+        assert!(y(&world, left) < -0.1, "{}", y(&world, left));
+        assert!(y(&world, right) > 0.1, "{}", y(&world, right));
+    }
+
+    #[test]
+    fn boids_never_spawn_inside_obstacles() {
+        let mut world = test_world(11);
+        // Covering a good part of the world, so plenty of spots get thrown away
+        let obstacles = [-400.0, 0.0, 400.0].map(|x| Obstacle {
+            pos: Vec2::new(x, 0.0),
+            radius: 200.0,
+        });
+        for obstacle in obstacles.iter() {
+            world.spawn().insert(Obstacle {
+                pos: obstacle.pos,
+                radius: obstacle.radius,
+            });
+        }
+        SystemStage::single(regulate_population).run(&mut world);
+
+        let radius = SpawnSpacing::default().radius;
+        let positions = boid_positions(&mut world);
+        assert_eq!(positions.len(), TargetPopulation::default().0);
+        for position in positions {
+            for obstacle in obstacles.iter() {
+                assert!(
+                    !obstacle.overlaps(position, radius),
+                    "{} spawned inside the obstacle at {}",
+                    position,
+                    obstacle.pos
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn zero_sized_world_falls_back_to_the_default() {
+        // What WorldBounds would be sized to after the window was minimized
+        let mut world = test_world(5);
+        world.insert_resource(WorldBounds { size: Vec2::ZERO });
+        world.insert_resource(MinWorldSize::default());
+        world.insert_resource(ArenaShape::Rect);
+        world.insert_resource(EdgeBehaviors {
+            x: EdgeBehavior::Wrap,
+            y: EdgeBehavior::Bounce,
+        });
+        let mut stages = [
+            SystemStage::single(guard_world_bounds),
+            SystemStage::single(regulate_population),
+            SystemStage::single(apply_force),
+            SystemStage::single(handle_edges),
+        ];
+        for stage in stages.iter_mut() {
+            stage.run(&mut world);
+        }
+
+        let size = world.get_resource::<WorldBounds>().unwrap().size;
+        assert_eq!(size, WorldBounds::default().size);
+        let positions = boid_positions(&mut world);
+        assert_eq!(positions.len(), TargetPopulation::default().0);
+        assert!(positions
+            .iter()
+            .all(|position| position.is_finite() && position.abs().cmple(size / 2.0).all()));
+    }
+
+    /// Every boid's velocity after one tick of all of the behaviors that don't have a key of their
+    /// own, each set up so that it would push at least one of the boids, with toggles toggled
+    fn velocities_after_untoggled_behaviors(toggles: BehaviorToggles) -> Vec<Vec2> {
+        let mut world = test_world(0);
+        world.insert_resource(toggles);
+        world.insert_resource(WorldBounds {
+            size: Vec2::new(100.0, 100.0),
+        });
+        world.insert_resource(EdgeBehaviors {
+            x: EdgeBehavior::Steer,
+            y: EdgeBehavior::Steer,
+        });
+        let mut controller = SpeedController::default();
+        controller.enabled = true;
+        world.insert_resource(controller);
+        let mut breaking = StalemateBreaking::default();
+        breaking.enabled = true;
+        world.insert_resource(breaking);
+        let mut cruising = IdleCruising::default();
+        cruising.enabled = true;
+        world.insert_resource(cruising);
+        world.insert_resource(PredatorParams {
+            count: 1,
+            ..Default::default()
+        });
+        let mut scent = ScentField::default();
+        scent.enabled = true;
+        scent.response = scent::ScentResponse::Avoid;
+        world.insert_resource(scent);
+        world.insert_resource(CustomSteering::new(|_| Vec2::new(0.0, 0.1)));
+
+        let boid = |world: &mut World, position: Vec2, velocity: Vec2, index: u64| {
+            world
+                .spawn()
+                .insert_bundle((
+                    Transform::from_translation(position.extend(0.0)),
+                    Velocity {
+                        vector: velocity,
+                        max: 1.0,
+                    },
+                    Force {
+                        vector: Vec2::ZERO,
+                        max: 0.25,
+                    },
+                    Neighbors::default(),
+                    SpawnIndex(index),
+                ))
+                .id()
+        };
+        // A pair stalled right on top of each other, and one cruising along in the edge's margin
+        let stalled = [
+            boid(&mut world, Vec2::new(0.0, 0.0), Vec2::ZERO, 0),
+            boid(&mut world, Vec2::new(1.0, 0.0), Vec2::ZERO, 1),
+        ];
+        let cruiser = boid(&mut world, Vec2::new(0.0, 40.0), Vec2::new(0.5, 0.0), 2);
+        world.get_mut::<Neighbors>(stalled[0]).unwrap().0 = vec![stalled[1]];
+        world.get_mut::<Neighbors>(stalled[1]).unwrap().0 = vec![stalled[0]];
+
+        // Scent is only left behind as time goes by, and the first update only starts the clock
+        for _ in 0..2 {
+            std::thread::sleep(std::time::Duration::from_millis(2));
+            world.get_resource_mut::<Time>().unwrap().update();
+        }
+        let mut stages = vec![
+            SystemStage::single(predators::regulate_predators),
+            SystemStage::single(scent::deposit_scent),
+            SystemStage::single(steer_inside_edges),
+            SystemStage::single(flocking::control_average_speed),
+            SystemStage::single(flocking::break_stalemates),
+            SystemStage::single(predators::flee_predators),
+            SystemStage::single(scent::follow_scent),
+            SystemStage::single(custom::custom_steering),
+            SystemStage::single(flocking::cruise_when_idle),
+            SystemStage::single(apply_force),
+        ];
+        for stage in stages.iter_mut() {
+            stage.run(&mut world);
+        }
+
+        [stalled[0], stalled[1], cruiser]
+            .iter()
+            .map(|&boid| world.get::<Velocity>(boid).unwrap().vector)
+            .collect()
+    }
+
+    #[test]
+    fn boids_coast_with_every_behavior_toggled_off() {
+        let starting = [Vec2::ZERO, Vec2::ZERO, Vec2::new(0.5, 0.0)];
+
+        let velocities = velocities_after_untoggled_behaviors(BehaviorToggles {
+            seek: false,
+            seek_cursor: false,
+            separation: false,
+            alignment: false,
+            cohesion: false,
+            regroup: false,
+            formation: false,
+            attractors: false,
+            obstacles: false,
+            follow_path: false,
+        });
+        assert_eq!(velocities, starting);
+
+        // The same setup does push every boid while anything is on
+        let velocities = velocities_after_untoggled_behaviors(BehaviorToggles::default());
+        assert!(
+            velocities
+                .iter()
+                .zip(starting.iter())
+                .all(|(velocity, starting)| velocity != starting),
+            "{:?}",
+            velocities
+        );
+    }
+
+    #[test]
+    fn culled_boids_keep_steering() {
+        let mut world = test_world(0);
+        world.insert_resource(LodParams::default());
+        // A 100x100 window looking at the middle of the world, so with the margin everything
+        // more than 60 out along either axis is off screen
+        let mut windows = Windows::default();
+        windows.add(Window::new(
+            WindowId::primary(),
+            &WindowDescriptor::default(),
+            100,
+            100,
+            1.0,
+            None,
+        ));
+        world.insert_resource(windows);
+        world
+            .spawn()
+            .insert_bundle(OrthographicCameraBundle::new_2d());
+
+        // A pair well within separation range of each other, straddling the edge of the screen
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &world);
+        let assets = world.get_resource::<BoidAssets>().unwrap();
+        let mut spawn = |x, index| {
+            let position = Vec2::new(x, 0.0);
+            spawn_boid(
+                &mut commands,
+                assets,
+                position,
+                Vec2::ZERO,
+                SpawnIndex(index),
+                FlockId(0),
+            )
+        };
+        let shown = spawn(55.0, 0);
+        let culled = spawn(65.0, 1);
+        queue.apply(&mut world);
+
+        let mut stages = vec![
+            SystemStage::single(update_lod),
+            SystemStage::single(grid::update_spatial_grid),
+            SystemStage::single(flocking::gather_neighbors),
+            SystemStage::single(flocking::fade_awareness),
+            SystemStage::single(flocking::separate),
+        ];
+        for stage in stages.iter_mut() {
+            stage.run(&mut world);
+        }
+
+        assert!(world.get::<Visible>(shown).unwrap().is_visible);
+        assert!(!world.get::<Visible>(culled).unwrap().is_visible);
+        // It isn't drawn, but it's still seen by its neighbor and pushed away from it all the same
+        assert_eq!(world.get::<Neighbors>(culled).unwrap().0, vec![shown]);
+        let force = world.get::<Force>(culled).unwrap().vector;
+        assert!(force.x > 0.0, "{}", force);
+    }
+}
@@ -0,0 +1,312 @@
+use bevy::{
+    app::AppExit,
+    core::Time,
+    ecs::{
+        entity::Entity,
+        event::EventWriter,
+        query::With,
+        system::{Local, Query, Res, ResMut},
+    },
+    math::Vec2,
+    prelude::Transform,
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    str::FromStr,
+};
+
+use crate::{flocking::FlockingParams, grid::SpatialGrid, DeterministicMode, SpawnIndex, Velocity};
+
+/// Measurements of the flock as a whole, updated as the simulation runs
+#[derive(Default)]
+pub struct FlockMetrics {
+    /// The size of every group of boids that are connected to each other through boids within
+    /// `cohesion_radius` of one another, largest first
+    pub clusters: Vec<usize>,
+    /// The average speed across all boids
+    pub average_speed: Metric,
+    /// How much the flock is heading in the same direction, 1 when every boid is heading the
+    /// exact same way and close to 0 when they're all heading in different directions
+    pub polarization: Metric,
+    /// The average distance of boids from the flock's center
+    pub spread: Metric,
+    /// The average of every boid's heading, as long as the polarization and zero when there are
+    /// no boids or they're heading every which way
+    pub heading: Vec2,
+    /// How far along the ExploreSchedule the flock is, 0 while it's still all exploring (or the
+    /// schedule is off) and 1 once it's all exploiting
+    pub exploitation: f32,
+}
+
+/// A measurement that changes every frame, along with its average over the last few frames
+///
+/// The per-frame values are quite jittery, the average is better suited for readouts or for
+/// checking on the flock's steady state behavior
+#[derive(Default)]
+pub struct Metric {
+    /// The value on the latest frame
+    pub current: f32,
+    /// The average value over the last MetricsWindow frames
+    pub average: f32,
+    samples: VecDeque<f32>,
+}
+
+impl Metric {
+    fn record(&mut self, value: f32, window: usize) {
+        self.current = value;
+        self.samples.push_back(value);
+        while self.samples.len() > window.max(1) {
+            self.samples.pop_front();
+        }
+        self.average = self.samples.iter().sum::<f32>() / self.samples.len() as f32;
+    }
+}
+
+/// How many frames the averages in FlockMetrics are taken over
+pub struct MetricsWindow(pub usize);
+
+impl Default for MetricsWindow {
+    fn default() -> Self {
+        MetricsWindow(30)
+    }
+}
+
+/// Finding clusters is a walk over the entire flock's neighbor graph, which is too expensive to
+/// do every single frame for large flocks
+pub struct ClusterDetection {
+    /// Clusters are only recomputed once every this many frames
+    pub every_n_frames: u32,
+}
+
+impl Default for ClusterDetection {
+    fn default() -> Self {
+        ClusterDetection { every_n_frames: 10 }
+    }
+}
+
+/// Finds the root of a boid's cluster in the union-find forest, flattening the path it walked
+/// along the way so later lookups are quicker
+fn find(parents: &mut [usize], mut boid: usize) -> usize {
+    while parents[boid] != boid {
+        parents[boid] = parents[parents[boid]];
+        boid = parents[boid];
+    }
+    boid
+}
+
+pub fn detect_clusters(
+    detection: Res<ClusterDetection>,
+    params: Res<FlockingParams>,
+    grid: Res<SpatialGrid>,
+    mut metrics: ResMut<FlockMetrics>,
+    mut frame: Local<u32>,
+    query: Query<(Entity, &Transform), With<Velocity>>,
+) {
+    *frame = frame.wrapping_add(1);
+    if *frame % detection.every_n_frames.max(1) != 0 {
+        return;
+    }
+
+    let indices = query
+        .iter()
+        .enumerate()
+        .map(|(index, (entity, _))| (entity, index))
+        .collect::<HashMap<_, _>>();
+    let mut parents = (0..indices.len()).collect::<Vec<_>>();
+
+    for (entity, Transform { translation, .. }) in query.iter() {
+        let boid = find(&mut parents, indices[&entity]);
+        for (neighbor, _) in grid.within(translation.truncate(), params.cohesion_radius) {
+            // The grid is only rebuilt once per frame so it may still hold a boid that has
+            // been despawned since, skip anything that isn't part of this query
+            if let Some(&neighbor) = indices.get(&neighbor) {
+                let neighbor = find(&mut parents, neighbor);
+                parents[neighbor] = boid;
+            }
+        }
+    }
+
+    let mut sizes = HashMap::<usize, usize>::new();
+    for boid in 0..parents.len() {
+        *sizes.entry(find(&mut parents, boid)).or_default() += 1;
+    }
+    let mut clusters = sizes.into_values().collect::<Vec<_>>();
+    clusters.sort_unstable_by(|a, b| b.cmp(a));
+
+    let previous = std::mem::replace(&mut metrics.clusters, clusters);
+    // Nothing to compare against the very first time clusters are detected
+    if previous.is_empty() {
+        return;
+    }
+    if metrics.clusters.len() > previous.len() {
+        println!(
+            "The flock split from {} into {} clusters: {:?}",
+            previous.len(),
+            metrics.clusters.len(),
+            metrics.clusters
+        );
+    } else if metrics.clusters.len() < previous.len() {
+        println!(
+            "The flock merged from {} into {} clusters: {:?}",
+            previous.len(),
+            metrics.clusters.len(),
+            metrics.clusters
+        );
+    }
+}
+
+pub fn measure_flock(
+    window: Res<MetricsWindow>,
+    deterministic: Res<DeterministicMode>,
+    mut metrics: ResMut<FlockMetrics>,
+    query: Query<(&Transform, &Velocity, &SpawnIndex)>,
+) {
+    let mut boids = query
+        .iter()
+        .map(|(transform, velocity, &index)| {
+            (index, transform.translation.truncate(), velocity.vector)
+        })
+        .collect::<Vec<_>>();
+    if boids.is_empty() {
+        metrics.heading = Vec2::ZERO;
+        return;
+    }
+    if deterministic.0 {
+        boids.sort_unstable_by_key(|&(index, _, _)| index);
+    }
+    let count = boids.len() as f32;
+
+    let (speed, heading, center) = boids.iter().fold(
+        (0.0, Vec2::ZERO, Vec2::ZERO),
+        |(speed, heading, center), (_, position, velocity)| {
+            (
+                speed + velocity.length(),
+                heading + velocity.normalize_or_zero(),
+                center + *position,
+            )
+        },
+    );
+    let center = center / count;
+    let spread = boids
+        .iter()
+        .map(|(_, position, _)| position.distance(center))
+        .sum::<f32>();
+
+    let metrics = metrics.as_mut();
+    metrics.average_speed.record(speed / count, window.0);
+    metrics
+        .polarization
+        .record(heading.length() / count, window.0);
+    metrics.spread.record(spread / count, window.0);
+    metrics.heading = heading / count;
+}
+
+/// How long the app runs for before exiting on its own, for batch and CI runs that have to end
+/// without anyone closing the window. Picked on the command line with `--duration seconds` and
+/// `--ticks frames`, whichever is reached first ends the run, with neither it runs until it's
+/// closed
+///
+/// The FlockMetrics as they were on the very last frame are printed on the way out
+#[derive(Default)]
+pub struct RunDuration {
+    pub seconds: Option<f32>,
+    pub ticks: Option<u64>,
+    elapsed: f32,
+    elapsed_ticks: u64,
+    done: bool,
+}
+
+/// The value following flag on the command line, if it's there at all, with a warning when it's
+/// there but isn't understood
+fn arg<T: FromStr>(args: &[String], flag: &str) -> Option<T> {
+    let position = args.iter().position(|arg| arg == flag)?;
+    let value = args.get(position + 1);
+    let parsed = value.and_then(|value| value.parse().ok());
+    if parsed.is_none() {
+        eprintln!("Couldn't understand {} {:?}, ignoring it", flag, value);
+    }
+    parsed
+}
+
+impl RunDuration {
+    /// The duration asked for on the command line
+    pub fn from_args() -> RunDuration {
+        let args = std::env::args().collect::<Vec<_>>();
+        RunDuration {
+            seconds: arg(&args, "--duration"),
+            ticks: arg(&args, "--ticks"),
+            ..RunDuration::default()
+        }
+    }
+}
+
+/// This runs after measure_flock so the metrics printed are the ones of the final frame
+pub fn end_run(
+    time: Res<Time>,
+    metrics: Res<FlockMetrics>,
+    mut duration: ResMut<RunDuration>,
+    mut exit: EventWriter<AppExit>,
+) {
+    let duration = duration.as_mut();
+    if duration.done || (duration.seconds.is_none() && duration.ticks.is_none()) {
+        return;
+    }
+    duration.elapsed += time.delta_seconds();
+    duration.elapsed_ticks += 1;
+    let out_of_time = duration
+        .seconds
+        .map_or(false, |seconds| duration.elapsed >= seconds);
+    let out_of_ticks = duration
+        .ticks
+        .map_or(false, |ticks| duration.elapsed_ticks >= ticks);
+    if !out_of_time && !out_of_ticks {
+        return;
+    }
+
+    duration.done = true;
+    println!(
+        "Run ended after {} frames over {:.1}s: average speed {:.2}, polarization {:.2}, spread {:.1}, clusters {:?}",
+        duration.elapsed_ticks,
+        duration.elapsed,
+        metrics.average_speed.average,
+        metrics.polarization.average,
+        metrics.spread.average,
+        metrics.clusters
+    );
+    exit.send(AppExit);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_world::test_world;
+    use bevy::ecs::{
+        event::Events,
+        schedule::{Stage, SystemStage},
+    };
+
+    #[test]
+    fn runs_end_on_their_last_tick() {
+        let mut world = test_world(0);
+        world.insert_resource(Events::<AppExit>::default());
+        world.insert_resource(RunDuration {
+            ticks: Some(3),
+            ..RunDuration::default()
+        });
+        let mut reader = world
+            .get_resource::<Events<AppExit>>()
+            .unwrap()
+            .get_reader();
+        let mut stage = SystemStage::single(end_run);
+
+        // Told to exit once, on the third tick, and left alone after that
+        let exits = (0..5)
+            .map(|_| {
+                stage.run(&mut world);
+                let events = world.get_resource::<Events<AppExit>>().unwrap();
+                reader.iter(events).count()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(exits, [0, 0, 1, 0, 0]);
+    }
+}
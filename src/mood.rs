@@ -0,0 +1,224 @@
+use bevy::{
+    core::Time,
+    ecs::{
+        entity::Entity,
+        query::Without,
+        system::{Local, Query, Res, ResMut},
+    },
+    input::{keyboard::KeyCode, Input},
+    math::{Vec2, Vec3, Vec4},
+    prelude::Transform,
+    render::{camera::OrthographicProjection, color::Color, draw::Visible},
+};
+
+use crate::{
+    alarm::Alarm,
+    compass::{Corner, ScreenSpace},
+    create_boid_mesh_bundle,
+    debug::{DebugOverlay, OverlayDrawing},
+    flocking::{CruiseSpeed, FlockingParams},
+    reproduction::Genes,
+    shapes, CometTail, Force, Tint,
+};
+
+/// How worked up the flock is, from 0 for calm to 1 for frantic, [ and ] turn it down and up
+///
+/// A calm flock glides along slowly in one big loose group, a frantic one darts around fast and
+/// sharp turning, huddles in tight little bunches and hardly cares about keeping together as a
+/// whole. 0.5 is the flock's usual self
+pub struct Mood(pub f32);
+
+impl Default for Mood {
+    fn default() -> Self {
+        Mood(0.5)
+    }
+}
+
+/// How far [ and ] move the mood with every press
+const MOOD_STEP: f32 = 0.1;
+
+/// Blends from calm at a mood of 0 through neutral at 0.5 to frantic at 1
+///
+/// Each half is eased with a smoothstep, so the flock settles into neutral without a kink and
+/// anything close to it stays close to the usual, while the extremes pick up quickly
+fn dial(calm: f32, neutral: f32, frantic: f32, mood: f32) -> f32 {
+    let smoothstep = |t: f32| t * t * (3.0 - 2.0 * t);
+    let mood = mood.clamp(0.0, 1.0);
+    if mood < 0.5 {
+        neutral + (calm - neutral) * smoothstep(1.0 - mood * 2.0)
+    } else {
+        neutral + (frantic - neutral) * smoothstep(mood * 2.0 - 1.0)
+    }
+}
+
+pub fn adjust_mood(keys: Res<Input<KeyCode>>, mut mood: ResMut<Mood>) {
+    let change = if keys.just_pressed(KeyCode::LBracket) {
+        -MOOD_STEP
+    } else if keys.just_pressed(KeyCode::RBracket) {
+        MOOD_STEP
+    } else {
+        return;
+    };
+    mood.0 = (mood.0 + change).clamp(0.0, 1.0);
+}
+
+/// Sets every boid's CruiseSpeed and Force.max from the mood and its Genes, and FlockingParams
+/// whenever the mood changes, so presets picked afterwards still take over until the mood is
+/// changed again
+pub fn apply_mood(
+    mood: Res<Mood>,
+    mut params: ResMut<FlockingParams>,
+    mut boids: Query<(&mut CruiseSpeed, &mut Force, &Genes)>,
+) {
+    let changed = mood.is_changed();
+    let mood = mood.0;
+    if changed {
+        let params = params.as_mut();
+        params.separation_radius = dial(20.0, 25.0, 15.0, mood);
+        params.alignment_radius = dial(70.0, 50.0, 25.0, mood);
+        params.cohesion_radius = dial(90.0, 50.0, 20.0, mood);
+        params.max_perceived_neighbors = dial(10.0, 7.0, 4.0, mood).round() as usize;
+    }
+
+    let speed = dial(0.6, 1.0, 1.8, mood);
+    let force = dial(0.1, 0.25, 0.6, mood);
+    for (mut cruise_speed, mut boid_force, genes) in boids.iter_mut() {
+        cruise_speed.0 = speed * genes.speed;
+        boid_force.max = force * genes.force;
+    }
+}
+
+/// A gauge in a corner of the window showing the mood for linger seconds after it's changed,
+/// toggled with DebugOverlays::mood_gauge
+///
+/// It fills up from calm at its left end to frantic at its right, shading from calm_color to
+/// frantic_color on the way, so half full is the flock's usual self
+pub struct MoodGauge {
+    pub corner: Corner,
+    /// The gauge's length and thickness in pixels
+    pub size: Vec2,
+    /// How far in from the edges of the window the gauge sits, in pixels
+    pub margin: f32,
+    /// How many seconds the gauge stays up after the mood last changed
+    pub linger: f32,
+    pub track_color: Color,
+    pub calm_color: Color,
+    pub frantic_color: Color,
+}
+
+impl Default for MoodGauge {
+    fn default() -> Self {
+        MoodGauge {
+            corner: Corner::TopLeft,
+            size: Vec2::new(120.0, 8.0),
+            margin: 20.0,
+            linger: 2.0,
+            track_color: Color::DARK_GRAY,
+            calm_color: Color::rgb(0.3, 0.6, 1.0),
+            frantic_color: Color::rgb(1.0, 0.3, 0.2),
+        }
+    }
+}
+
+/// The entities the gauge is drawn with, and how much longer it stays up
+#[derive(Default)]
+pub struct GaugeState {
+    /// The track and the bar filling it
+    parts: Option<(Entity, Entity)>,
+    /// The mood as of the last frame, the gauge shows up whenever it's different from this
+    seen: Option<f32>,
+    remaining: f32,
+}
+
+pub fn draw_mood_gauge(
+    drawing: OverlayDrawing,
+    gauge: Res<MoodGauge>,
+    mood: Res<Mood>,
+    time: Res<Time>,
+    screen: ScreenSpace,
+    mut state: Local<GaugeState>,
+    mut parts: Query<(&mut Transform, &mut Visible, &mut Tint), Without<OrthographicProjection>>,
+) {
+    let OverlayDrawing {
+        overlays,
+        mut commands,
+        assets,
+        mut meshes,
+    } = drawing;
+    let state = &mut *state;
+    // The mood the flock starts out in isn't news, so that alone doesn't bring the gauge up
+    if state.seen.map_or(false, |seen| seen != mood.0) {
+        state.remaining = gauge.linger;
+    } else {
+        state.remaining = (state.remaining - time.delta_seconds()).max(0.0);
+    }
+    state.seen = Some(mood.0);
+    let show = overlays.shows(DebugOverlay::MoodGauge) && state.remaining > 0.0;
+
+    let (track, fill) = match state.parts {
+        Some(parts) => parts,
+        None if show => {
+            let bar = meshes.add(shapes::create_bar_mesh());
+            let mut spawn = |color| {
+                let mut bundle =
+                    create_boid_mesh_bundle(assets.pipeline.clone(), bar.clone(), Vec2::ZERO);
+                bundle.visible.is_visible = false;
+                commands
+                    .spawn_bundle(bundle)
+                    .insert(CometTail::default())
+                    .insert(Tint { color })
+                    .insert(Alarm::default())
+                    .id()
+            };
+            // The gauge shows up from the next frame on, once it has actually been spawned
+            state.parts = Some((spawn(gauge.track_color), spawn(gauge.calm_color)));
+            return;
+        }
+        None => return,
+    };
+
+    let mapping = match screen.mapping() {
+        Some(mapping) if show => mapping,
+        _ => {
+            for part in [track, fill] {
+                if let Ok((_, mut visible, _)) = parts.get_mut(part) {
+                    visible.is_visible = false;
+                }
+            }
+            return;
+        }
+    };
+
+    // Both bars are stretched out from the gauge's left end and turned along with the camera,
+    // so the gauge always reads left to right on screen
+    let half_size = gauge.size / 2.0;
+    let center = gauge.corner.place(mapping.size, half_size, gauge.margin);
+    let left = mapping.point(center - Vec2::new(half_size.x, 0.0));
+    let z = DebugOverlay::MoodGauge.z();
+    let bar = |length: f32, z: f32| Transform {
+        translation: left.extend(z),
+        rotation: mapping.rotation,
+        scale: Vec3::new(length * mapping.scale, gauge.size.y * mapping.scale, 1.0),
+    };
+    let mood = mood.0.clamp(0.0, 1.0);
+    let calm = Vec4::from(gauge.calm_color.as_rgba_linear());
+    let frantic = Vec4::from(gauge.frantic_color.as_rgba_linear());
+    let color = calm.lerp(frantic, mood);
+
+    for (part, transform, color, visible) in [
+        (track, bar(gauge.size.x, z), gauge.track_color, true),
+        (
+            fill,
+            // Drawn over the track, and left out entirely when there's nothing to fill
+            bar(gauge.size.x * mood, z + 0.5),
+            Color::rgba_linear(color.x, color.y, color.z, color.w),
+            mood > 0.0,
+        ),
+    ] {
+        if let Ok((mut part_transform, mut part_visible, mut tint)) = parts.get_mut(part) {
+            *part_transform = transform;
+            part_visible.is_visible = visible;
+            tint.color = color;
+        }
+    }
+}
@@ -0,0 +1,652 @@
+use bevy::{
+    asset::{Assets, Handle},
+    ecs::{
+        component::Component,
+        entity::Entity,
+        query::Without,
+        system::{Commands, Local, Query, Res, ResMut, SystemParam},
+    },
+    input::{keyboard::KeyCode, mouse::MouseButton},
+    math::{Vec2, Vec3},
+    prelude::Transform,
+    render::{color::Color, mesh::Mesh},
+};
+
+use crate::{
+    alarm::Alarm,
+    create_boid_mesh_bundle,
+    grid::{SpatialGrid, SpatialIndex},
+    shapes::create_circle_mesh,
+    sleep::Sleeping,
+    stagger::SteeringStagger,
+    BehaviorToggles, BoidAssets, CometTail, Force, MouseInput, SpawnIndex, Tint, Velocity,
+    WorldBounds,
+};
+
+/// A round obstacle boids with AvoidObstacles steer around
+#[derive(Component)]
+pub struct Obstacle {
+    pub pos: Vec2,
+    pub radius: f32,
+}
+
+impl Obstacle {
+    /// Whether something radius big sitting at position would overlap the obstacle
+    pub fn overlaps(&self, position: Vec2, radius: f32) -> bool {
+        position.distance_squared(self.pos) < (self.radius + radius).powi(2)
+    }
+
+    /// Moves a boid that has ended up inside the obstacle back out to its edge and takes away
+    /// the part of its velocity heading further in, so it slides along the edge instead. Returns
+    /// whether the boid was inside
+    pub fn push_out(&self, position: &mut Vec2, velocity: &mut Vec2) -> bool {
+        let offset = *position - self.pos;
+        if offset.length_squared() >= self.radius * self.radius {
+            return false;
+        }
+        // A boid right on the center is no closer to any part of the edge, so it goes back the
+        // way it came
+        let mut normal = offset.normalize_or_zero();
+        if normal == Vec2::ZERO {
+            normal = -velocity.normalize_or_zero();
+        }
+        if normal == Vec2::ZERO {
+            normal = Vec2::X;
+        }
+        *position = self.pos + normal * self.radius;
+        let inwards = velocity.dot(normal);
+        if inwards < 0.0 {
+            *velocity -= normal * inwards;
+        }
+        true
+    }
+}
+
+/// A boid with AvoidObstacles looks ahead along its heading and steers sideways away from the
+/// nearest obstacle it's about to fly into
+#[derive(Component)]
+pub struct AvoidObstacles {
+    /// A number between 0 and 1 to determine how interested the boid is in not crashing,
+    /// see Seek's interest for how it interacts with other behaviors
+    pub interest: f32,
+    /// The obstacle the boid is following the wall of with ObstacleResponse::FollowWall, and
+    /// which way around it's going (1 for counter-clockwise, -1 for clockwise)
+    ///
+    /// The side is picked once when the boid first meets the obstacle and then kept, going by
+    /// the approach angle every frame would flip it back and forth as the boid rounds the far
+    /// side of the obstacle where the surface turns away from it
+    pub following: Option<(Entity, f32)>,
+}
+
+impl AvoidObstacles {
+    pub fn new(interest: f32) -> Self {
+        AvoidObstacles {
+            interest,
+            following: None,
+        }
+    }
+}
+
+/// What a boid does when it's about to fly into an obstacle
+#[derive(Clone, Copy)]
+pub enum ObstacleResponse {
+    /// Swerve sideways away from the obstacle and carry on
+    SteerAway,
+    /// Turn to fly along the obstacle's edge, keeping standoff away from its surface, until
+    /// something else pulls the boid away from it, handy for maze-like scenes
+    FollowWall { standoff: f32 },
+}
+
+pub struct ObstacleParams {
+    /// The radius given to obstacles placed by clicking
+    pub radius: f32,
+    /// How many triangles obstacles are drawn with
+    pub segments: u32,
+    /// How far ahead a boid looks for every unit of speed, so faster boids that need more room
+    /// to turn also see obstacles coming sooner
+    pub lookahead_per_speed: f32,
+    /// The shortest a boid's lookahead gets, so even a boid that's barely moving notices an
+    /// obstacle right in front of it
+    pub min_lookahead: f32,
+    /// The longest a boid's lookahead gets, so fast boids don't swerve around obstacles far off
+    pub max_lookahead: f32,
+    pub response: ObstacleResponse,
+    /// How many of the obstacles nearest to a boid it looks at, so a boid in a thicket of
+    /// obstacles only worries about the closest few, 0 looks at every obstacle in reach
+    pub nearest_considered: usize,
+}
+
+impl Default for ObstacleParams {
+    fn default() -> Self {
+        ObstacleParams {
+            radius: 30.0,
+            segments: 32,
+            lookahead_per_speed: 60.0,
+            min_lookahead: 10.0,
+            max_lookahead: 120.0,
+            response: ObstacleResponse::SteerAway,
+            nearest_considered: 8,
+        }
+    }
+}
+
+impl ObstacleParams {
+    /// How far ahead a boid flying at speed looks for obstacles
+    pub fn lookahead(&self, speed: f32) -> f32 {
+        (speed * self.lookahead_per_speed).clamp(self.min_lookahead, self.max_lookahead)
+    }
+}
+
+/// Every obstacle bucketed by its center in a SpatialGrid of its own, so boids only have to look
+/// at the obstacles around them instead of at every obstacle in the world
+pub struct ObstacleGrid {
+    grid: SpatialGrid,
+    /// The radius of the biggest obstacle, a boid has to look this much further out than its
+    /// lookahead to find the centers of obstacles whose edges are within reach
+    largest_radius: f32,
+}
+
+impl Default for ObstacleGrid {
+    fn default() -> Self {
+        // About as far as the longest lookahead reaches with a default sized obstacle at the end
+        ObstacleGrid {
+            grid: SpatialGrid::new(150.0),
+            largest_radius: 0.0,
+        }
+    }
+}
+
+impl ObstacleGrid {
+    /// Every obstacle a boid at position with lookahead could fly into
+    ///
+    /// An obstacle the boid would hit is no more than lookahead ahead of its edge and no more
+    /// than its radius to the side, so its center can't be further than lookahead plus twice
+    /// its radius away. Nothing avoid_obstacles would have found by looking at every obstacle is
+    /// missed
+    fn in_reach(&self, position: Vec2, lookahead: f32) -> impl Iterator<Item = Entity> + '_ {
+        self.grid
+            .within(position, lookahead + 2.0 * self.largest_radius)
+            .map(|(entity, _)| entity)
+    }
+}
+
+/// The obstacles around wherever a boid is, for systems that need to keep boids out of them
+#[derive(SystemParam)]
+pub struct NearbyObstacles<'a> {
+    grid: Res<'a, ObstacleGrid>,
+    obstacles: Query<'a, &'static Obstacle>,
+}
+
+impl<'a> NearbyObstacles<'a> {
+    /// Pushes a boid that has ended up inside obstacles back out of them, see Obstacle::push_out.
+    /// Returns whether the boid was inside any
+    pub fn push_out(&self, position: &mut Vec2, velocity: &mut Vec2) -> bool {
+        let mut inside = false;
+        for entity in self.grid.in_reach(*position, 0.0) {
+            if let Ok(obstacle) = self.obstacles.get(entity) {
+                inside |= obstacle.push_out(position, velocity);
+            }
+        }
+        inside
+    }
+}
+
+/// Obstacles only come and go when clicked, but rebuilding the grid is cheap next to every boid
+/// checking every obstacle, so it's just done every frame
+pub fn update_obstacle_grid(mut grid: ResMut<ObstacleGrid>, obstacles: Query<(Entity, &Obstacle)>) {
+    let grid = grid.as_mut();
+    grid.largest_radius = obstacles
+        .iter()
+        .map(|(_, obstacle)| obstacle.radius)
+        .fold(0.0, f32::max);
+    grid.grid.rebuild(
+        SpatialIndex::UniformGrid,
+        obstacles
+            .iter()
+            .map(|(entity, obstacle)| (entity, obstacle.pos))
+            .collect(),
+    );
+}
+
+/// A fixed arrangement of obstacles placed at startup, for scenes that play out the same every
+/// time. Picked on the command line with `--obstacles corridor`, `pillars` or `funnel`
+///
+/// Every layout is laid out relative to WorldBounds, so it fills the world whatever its size
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ObstacleLayout {
+    None,
+    /// Two walls running the length of the world, leaving a channel through its middle third
+    Corridor,
+    /// A grid of evenly spaced pillars for the flock to weave through
+    Pillars,
+    /// Two walls closing in from the left edge to a narrow gap right of the center
+    Funnel,
+}
+
+impl Default for ObstacleLayout {
+    fn default() -> Self {
+        ObstacleLayout::None
+    }
+}
+
+impl ObstacleLayout {
+    /// The layout asked for on the command line, anything that isn't understood falls back to
+    /// no obstacles with a warning
+    pub fn from_args() -> ObstacleLayout {
+        let args = std::env::args().collect::<Vec<_>>();
+        let name = match args.iter().position(|arg| arg == "--obstacles") {
+            Some(position) => args.get(position + 1),
+            None => return ObstacleLayout::None,
+        };
+        match name.map(String::as_str) {
+            Some("corridor") => ObstacleLayout::Corridor,
+            Some("pillars") => ObstacleLayout::Pillars,
+            Some("funnel") => ObstacleLayout::Funnel,
+            Some("none") => ObstacleLayout::None,
+            _ => {
+                eprintln!("Unknown obstacle layout {:?}, placing no obstacles", name);
+                ObstacleLayout::None
+            }
+        }
+    }
+
+    /// Where the layout's obstacles go in a world of size
+    pub fn obstacles(&self, size: Vec2) -> Vec<Obstacle> {
+        let half = size / 2.0;
+        // Walls are rows of obstacles overlapping by half so boids can't slip between them
+        let wall = |from: Vec2, to: Vec2, radius: f32| {
+            let count = (from.distance(to) / radius).ceil().max(1.0) as usize;
+            (0..=count)
+                .map(|i| Obstacle {
+                    pos: from.lerp(to, i as f32 / count as f32),
+                    radius,
+                })
+                .collect::<Vec<_>>()
+        };
+        match *self {
+            ObstacleLayout::None => Vec::new(),
+            ObstacleLayout::Corridor => {
+                let radius = size.y / 12.0;
+                let y = size.y / 6.0 + radius;
+                let mut obstacles = wall(Vec2::new(-half.x, y), Vec2::new(half.x, y), radius);
+                obstacles.extend(wall(Vec2::new(-half.x, -y), Vec2::new(half.x, -y), radius));
+                obstacles
+            }
+            ObstacleLayout::Pillars => {
+                let (columns, rows) = (6, 4);
+                let cell = size / Vec2::new(columns as f32, rows as f32);
+                let radius = cell.min_element() / 6.0;
+                (0..rows)
+                    .flat_map(|row| (0..columns).map(move |column| (column, row)))
+                    .map(|(column, row)| Obstacle {
+                        pos: -half + cell * Vec2::new(column as f32 + 0.5, row as f32 + 0.5),
+                        radius,
+                    })
+                    .collect()
+            }
+            ObstacleLayout::Funnel => {
+                let radius = size.y / 20.0;
+                let gap = Vec2::new(size.x / 6.0, size.y / 10.0 + radius);
+                let mut obstacles = wall(Vec2::new(-half.x, half.y), gap, radius);
+                obstacles.extend(wall(
+                    Vec2::new(-half.x, -half.y),
+                    gap * Vec2::new(1.0, -1.0),
+                    radius,
+                ));
+                obstacles
+            }
+        }
+    }
+}
+
+pub fn place_obstacle_layout(
+    mut commands: Commands,
+    layout: Res<ObstacleLayout>,
+    params: Res<ObstacleParams>,
+    bounds: Res<WorldBounds>,
+    assets: Res<BoidAssets>,
+    mut meshes: ResMut<Assets<Mesh>>,
+) {
+    let obstacles = layout.obstacles(bounds.size);
+    if obstacles.is_empty() {
+        return;
+    }
+    let mesh = meshes.add(create_circle_mesh(params.segments));
+    for obstacle in obstacles {
+        let mut bundle =
+            create_boid_mesh_bundle(assets.pipeline.clone(), mesh.clone(), obstacle.pos);
+        bundle.transform.scale = Vec3::new(obstacle.radius, obstacle.radius, 1.0);
+        commands
+            .spawn_bundle(bundle)
+            .insert(CometTail::default())
+            .insert(Tint { color: Color::GRAY })
+            .insert(Alarm::default())
+            .insert(obstacle);
+    }
+}
+
+/// Right clicking places an obstacle under the cursor and pressing O clears them all, other than
+/// the ones from the ObstacleLayout
+pub fn place_obstacles(
+    mut commands: Commands,
+    params: Res<ObstacleParams>,
+    assets: Res<BoidAssets>,
+    input: MouseInput,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut mesh: Local<Option<Handle<Mesh>>>,
+    mut placed: Local<Vec<Entity>>,
+) {
+    if input.keys.just_pressed(KeyCode::O) {
+        for obstacle in placed.drain(..) {
+            commands.entity(obstacle).despawn();
+        }
+    }
+
+    if !input.buttons.just_pressed(MouseButton::Right) {
+        return;
+    }
+    let cursor = match input.cursor() {
+        Some(cursor) => cursor,
+        None => return,
+    };
+
+    let mesh = mesh
+        .get_or_insert_with(|| meshes.add(create_circle_mesh(params.segments)))
+        .clone();
+    let mut bundle = create_boid_mesh_bundle(assets.pipeline.clone(), mesh, cursor);
+    bundle.transform.scale = Vec3::new(params.radius, params.radius, 1.0);
+    placed.push(
+        commands
+            .spawn_bundle(bundle)
+            .insert(CometTail::default())
+            .insert(Tint { color: Color::GRAY })
+            .insert(Alarm::default())
+            .insert(Obstacle {
+                pos: cursor,
+                radius: params.radius,
+            })
+            .id(),
+    );
+}
+
+pub fn avoid_obstacles(
+    toggles: Res<BehaviorToggles>,
+    stagger: Res<SteeringStagger>,
+    params: Res<ObstacleParams>,
+    grid: Res<ObstacleGrid>,
+    obstacles: Query<(Entity, &Obstacle)>,
+    mut boids: Query<
+        (
+            &mut Force,
+            &Velocity,
+            &Transform,
+            &mut AvoidObstacles,
+            &SpawnIndex,
+        ),
+        Without<Sleeping>,
+    >,
+) {
+    if !toggles.obstacles {
+        return;
+    }
+
+    for (mut force, velocity, transform, mut avoid, index) in boids.iter_mut() {
+        if !stagger.is_due(index) {
+            continue;
+        }
+        let position = transform.translation.truncate();
+        let heading = velocity.vector.normalize_or_zero();
+        if heading == Vec2::ZERO {
+            continue;
+        }
+        let lookahead = params.lookahead(velocity.vector.length());
+
+        let mut nearby = grid
+            .in_reach(position, lookahead)
+            .filter_map(|entity| obstacles.get(entity).ok())
+            .map(|(entity, obstacle)| {
+                let gap = position.distance(obstacle.pos) - obstacle.radius;
+                (gap, entity, obstacle)
+            })
+            .collect::<Vec<_>>();
+        let considered = params.nearest_considered;
+        if considered > 0 && nearby.len() > considered {
+            nearby.select_nth_unstable_by(considered - 1, |(a, ..), (b, ..)| {
+                a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal)
+            });
+            nearby.truncate(considered);
+        }
+
+        // The obstacle that the boid would hit first if it kept flying straight, along with how
+        // far ahead it is and which side of the boid's path its center is on
+        let threat = nearby
+            .into_iter()
+            .filter_map(|(_, entity, obstacle)| {
+                let offset = obstacle.pos - position;
+                let ahead = offset.dot(heading);
+                let side = offset.dot(heading.perp());
+                if ahead < 0.0
+                    || ahead - obstacle.radius > lookahead
+                    || side.abs() > obstacle.radius
+                {
+                    return None;
+                }
+                Some((entity, obstacle, ahead, side))
+            })
+            .min_by(|(_, _, a, _), (_, _, b, _)| {
+                a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal)
+            });
+
+        let force = force.as_mut();
+        match params.response {
+            ObstacleResponse::SteerAway => {
+                avoid.following = None;
+                if let Some((_, obstacle, ahead, side)) = threat {
+                    // Steer to whichever side of the obstacle is closer, harder the closer it is
+                    let away = if side > 0.0 {
+                        -heading.perp()
+                    } else {
+                        heading.perp()
+                    };
+                    let gap = (ahead - obstacle.radius).max(0.0);
+                    let urgency = 1.0 - (gap / lookahead).clamp(0.0, 1.0);
+                    force.vector += away * force.max * urgency * avoid.interest;
+                }
+            }
+            ObstacleResponse::FollowWall { standoff } => {
+                let standoff = standoff.max(f32::EPSILON);
+                // Keep following the same obstacle for as long as the boid stays near its wall,
+                // and only start following a new one once the feeler runs into it
+                let nearby = |obstacle: &Obstacle| {
+                    position.distance(obstacle.pos) - obstacle.radius <= standoff * 2.0
+                };
+                let followed = avoid
+                    .following
+                    .and_then(|(entity, side)| {
+                        let (_, obstacle) = obstacles.get(entity).ok()?;
+                        nearby(obstacle).then(|| (entity, obstacle, side))
+                    })
+                    .or_else(|| {
+                        threat.map(|(entity, obstacle, ..)| {
+                            // Go around whichever way is closest to where the boid is already
+                            // heading
+                            let normal = (position - obstacle.pos).normalize_or_zero();
+                            let side = if normal.perp().dot(heading) >= 0.0 {
+                                1.0
+                            } else {
+                                -1.0
+                            };
+                            (entity, obstacle, side)
+                        })
+                    });
+
+                avoid.following = followed.map(|(entity, _, side)| (entity, side));
+                if let Some((_, obstacle, side)) = followed {
+                    let offset = position - obstacle.pos;
+                    let normal = offset.normalize_or_zero();
+                    let tangent = normal.perp() * side;
+                    // Push out when closer than standoff to the surface and pull in when further,
+                    // so the boid hugs the wall instead of drifting off it as the surface curves
+                    // away
+                    let error = (standoff - (offset.length() - obstacle.radius)) / standoff;
+                    let desired = (tangent + normal * error.clamp(-1.0, 1.0)).normalize_or_zero()
+                        * velocity.max;
+                    let steer = (desired - velocity.vector).clamp_length_max(force.max);
+                    force.vector += steer * avoid.interest;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_world::test_world;
+    use bevy::ecs::schedule::{Stage, SystemStage};
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use std::collections::HashSet;
+
+    /// The force a boid flying along x at speed puts into steering around an obstacle whose
+    /// near edge is gap ahead of it
+    fn avoidance(speed: f32, gap: f32) -> Vec2 {
+        let mut world = test_world(0);
+        // Slightly to the left of the boid's path, so which way it swerves is settled
+        world.spawn().insert(Obstacle {
+            pos: Vec2::new(gap + 10.0, 2.0),
+            radius: 10.0,
+        });
+        let boid = world
+            .spawn()
+            .insert_bundle((
+                Transform::default(),
+                Velocity {
+                    vector: Vec2::new(speed, 0.0),
+                    max: 3.0,
+                },
+                Force {
+                    vector: Vec2::ZERO,
+                    max: 1.0,
+                },
+                AvoidObstacles::new(1.0),
+                SpawnIndex(0),
+            ))
+            .id();
+        SystemStage::single(update_obstacle_grid).run(&mut world);
+        SystemStage::single(avoid_obstacles).run(&mut world);
+        world.get::<Force>(boid).unwrap().vector
+    }
+
+    #[test]
+    fn boids_overlap_obstacles_they_touch_past_the_edge() {
+        let obstacle = Obstacle {
+            pos: Vec2::new(10.0, 0.0),
+            radius: 5.0,
+        };
+        assert!(obstacle.overlaps(Vec2::new(10.0, 0.0), 0.0));
+        assert!(obstacle.overlaps(Vec2::new(1.0, 0.0), 5.0));
+        // Just touching isn't overlapping
+        assert!(!obstacle.overlaps(Vec2::new(0.0, 0.0), 5.0));
+        assert!(!obstacle.overlaps(Vec2::new(10.0, 20.0), 5.0));
+    }
+
+    #[test]
+    fn obstacle_layouts_lay_out_their_obstacles_across_the_world() {
+        let size = Vec2::new(1200.0, 720.0);
+        let half = size / 2.0;
+        assert!(ObstacleLayout::None.obstacles(size).is_empty());
+
+        // Two walls of 21 obstacles, 60 across and 60 apart, each end to end along the world
+        let corridor = ObstacleLayout::Corridor.obstacles(size);
+        assert_eq!(corridor.len(), 42);
+        for (wall, y) in [(&corridor[..21], 180.0), (&corridor[21..], -180.0)] {
+            assert_eq!(wall[0].pos, Vec2::new(-half.x, y));
+            assert_eq!(wall[20].pos, Vec2::new(half.x, y));
+            assert!(wall.iter().all(|obstacle| obstacle.pos.y == y));
+            assert!(wall.iter().all(|obstacle| obstacle.radius == 60.0));
+        }
+        // Leaving the middle third of the world open
+        assert!(corridor
+            .iter()
+            .all(|obstacle| obstacle.pos.y.abs() - obstacle.radius >= size.y / 6.0));
+
+        // A pillar in the middle of every cell of a 6 by 4 grid
+        let pillars = ObstacleLayout::Pillars.obstacles(size);
+        assert_eq!(pillars.len(), 24);
+        assert_eq!(pillars[0].pos, Vec2::new(-500.0, -270.0));
+        assert_eq!(pillars[23].pos, Vec2::new(500.0, 270.0));
+        assert!(pillars.iter().all(|obstacle| obstacle.radius == 30.0));
+
+        // Two mirrored walls from the left corners in to a gap right of the center
+        let funnel = ObstacleLayout::Funnel.obstacles(size);
+        assert_eq!(funnel.len() % 2, 0);
+        let (top, bottom) = funnel.split_at(funnel.len() / 2);
+        for (top, bottom) in top.iter().zip(bottom) {
+            assert_eq!(top.pos, bottom.pos * Vec2::new(1.0, -1.0));
+        }
+        assert_eq!(top[0].pos, Vec2::new(-half.x, half.y));
+        assert!((top[top.len() - 1].pos - Vec2::new(200.0, 108.0)).length() < 1e-3);
+        assert!(funnel
+            .iter()
+            .all(|obstacle| !obstacle.overlaps(Vec2::new(200.0, 0.0), 30.0)));
+    }
+
+    #[test]
+    fn lookahead_scales_with_speed_within_its_bounds() {
+        let params = ObstacleParams::default();
+        assert_eq!(params.lookahead(0.0), params.min_lookahead);
+        assert_eq!(params.lookahead(1.0), params.lookahead_per_speed);
+        assert_eq!(params.lookahead(100.0), params.max_lookahead);
+    }
+
+    #[test]
+    fn fast_boids_start_avoiding_obstacles_further_out() {
+        // Up close both of them swerve away, to the right
+        assert!(avoidance(1.0, 30.0).y < 0.0);
+        assert!(avoidance(2.0, 30.0).y < 0.0);
+        // Further out only the fast one has seen the obstacle yet
+        assert_eq!(avoidance(1.0, 90.0), Vec2::ZERO);
+        assert!(avoidance(2.0, 90.0).y < 0.0);
+    }
+
+    #[test]
+    fn obstacle_grid_finds_every_obstacle_in_reach() {
+        let mut world = test_world(0);
+        let mut rng = StdRng::seed_from_u64(11);
+        // Mostly small ones, with a few big enough to cover several of the grid's cells
+        let obstacles = (0..60)
+            .map(|i| {
+                let pos = Vec2::new(rng.gen_range(-640.0..640.0), rng.gen_range(-360.0..360.0));
+                let radius = if i % 10 == 0 {
+                    rng.gen_range(150.0..300.0)
+                } else {
+                    rng.gen_range(5.0..30.0)
+                };
+                let entity = world.spawn().insert(Obstacle { pos, radius }).id();
+                (entity, pos, radius)
+            })
+            .collect::<Vec<_>>();
+        SystemStage::single(update_obstacle_grid).run(&mut world);
+        let grid = world.get_resource::<ObstacleGrid>().unwrap();
+
+        for _ in 0..200 {
+            let position = Vec2::new(rng.gen_range(-700.0..700.0), rng.gen_range(-400.0..400.0));
+            // 0 being what push_out looks with
+            for lookahead in [0.0, 10.0, 60.0, 120.0] {
+                let found = grid.in_reach(position, lookahead).collect::<HashSet<_>>();
+                for &(entity, pos, radius) in obstacles.iter() {
+                    if position.distance(pos) - radius <= lookahead {
+                        assert!(
+                            found.contains(&entity),
+                            "missed the obstacle at {} of radius {} from {} looking {} ahead",
+                            pos,
+                            radius,
+                            position,
+                            lookahead
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
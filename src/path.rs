@@ -0,0 +1,186 @@
+use bevy::{
+    ecs::{
+        component::Component,
+        query::Without,
+        system::{Query, Res},
+    },
+    math::Vec2,
+    prelude::Transform,
+};
+
+use crate::{
+    seek_force, sleep::Sleeping, stagger::SteeringStagger, BehaviorToggles, Force, SpawnIndex,
+    Velocity,
+};
+
+/// How many evenly spaced samples of each segment are checked when looking for the closest point
+/// on a path, before narrowing it down between the samples around the closest one
+const SAMPLES_PER_SEGMENT: usize = 16;
+
+/// A smooth path through every one of its points, boids with FollowPath fly along it
+///
+/// The path is a Catmull-Rom spline, which curves through each point heading towards the next
+/// one in line. A closed path loops back from its last point to its first while an open one
+/// ends at its last point
+pub struct Path {
+    pub points: Vec<Vec2>,
+    pub closed: bool,
+    /// How far ahead along the path a follower aims, further ahead cuts corners more but
+    /// weaves less
+    pub lead: f32,
+}
+
+impl Default for Path {
+    /// A loop around the middle of the default window
+    fn default() -> Self {
+        let points = (0..8)
+            .map(|i| {
+                let angle = i as f32 * std::f32::consts::TAU / 8.0;
+                Vec2::new(angle.cos() * 400.0, angle.sin() * 200.0)
+            })
+            .collect();
+        Path {
+            points,
+            closed: true,
+            lead: 30.0,
+        }
+    }
+}
+
+/// A spot on a Path
+pub struct PathPoint {
+    pub position: Vec2,
+    /// Which way the path heads at this spot, normalized
+    pub tangent: Vec2,
+    /// Whether this is the very end of an open path
+    pub end: bool,
+}
+
+impl Path {
+    fn segments(&self) -> usize {
+        match self.points.len() {
+            0 | 1 => 0,
+            points if self.closed => points,
+            points => points - 1,
+        }
+    }
+
+    /// The spline's control point at index, wrapping around closed paths and repeating the first
+    /// and last points past the ends of open ones
+    fn control(&self, index: isize) -> Vec2 {
+        let points = self.points.len() as isize;
+        let index = if self.closed {
+            index.rem_euclid(points)
+        } else {
+            index.clamp(0, points - 1)
+        };
+        self.points[index as usize]
+    }
+
+    /// The four control points shaping a segment
+    fn controls(&self, segment: usize) -> [Vec2; 4] {
+        let segment = segment as isize;
+        [-1, 0, 1, 2].map(|offset| self.control(segment + offset))
+    }
+
+    /// The position t of the way (from 0 to 1) through a segment
+    pub fn sample(&self, segment: usize, t: f32) -> Vec2 {
+        let [p0, p1, p2, p3] = self.controls(segment);
+        0.5 * (2.0 * p1
+            + (p2 - p0) * t
+            + (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t * t
+            + (3.0 * p1 - p0 - 3.0 * p2 + p3) * t * t * t)
+    }
+
+    /// Which way the path heads t of the way (from 0 to 1) through a segment, not normalized
+    pub fn tangent(&self, segment: usize, t: f32) -> Vec2 {
+        let [p0, p1, p2, p3] = self.controls(segment);
+        0.5 * ((p2 - p0)
+            + 2.0 * (2.0 * p0 - 5.0 * p1 + 4.0 * p2 - p3) * t
+            + 3.0 * (3.0 * p1 - p0 - 3.0 * p2 + p3) * t * t)
+    }
+
+    /// The point on the path closest to position, a path with fewer than 2 points has none
+    pub fn nearest(&self, position: Vec2) -> Option<PathPoint> {
+        let segments = self.segments();
+        let step = 1.0 / SAMPLES_PER_SEGMENT as f32;
+        let distance = |segment: usize, t: f32| self.sample(segment, t).distance_squared(position);
+
+        let (segment, t) = (0..segments)
+            .flat_map(|segment| {
+                (0..=SAMPLES_PER_SEGMENT).map(move |sample| (segment, sample as f32 * step))
+            })
+            .min_by(|&(a, ta), &(b, tb)| {
+                distance(a, ta)
+                    .partial_cmp(&distance(b, tb))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })?;
+
+        // The closest point is somewhere between the samples on either side of the closest
+        // sample, a few rounds of ternary search find it without sampling the whole segment finely
+        let (mut low, mut high) = ((t - step).max(0.0), (t + step).min(1.0));
+        for _ in 0..8 {
+            let third = (high - low) / 3.0;
+            if distance(segment, low + third) < distance(segment, high - third) {
+                high -= third;
+            } else {
+                low += third;
+            }
+        }
+        let t = (low + high) / 2.0;
+
+        Some(PathPoint {
+            position: self.sample(segment, t),
+            tangent: self.tangent(segment, t).normalize_or_zero(),
+            end: !self.closed && segment == segments - 1 && t >= 1.0 - step / 2.0,
+        })
+    }
+}
+
+/// A boid with FollowPath flies along the Path, steering towards a spot a little ahead of its
+/// closest point on the path so it both heads the way the path goes and drifts back onto it
+///
+/// Followers of an open path gather at its end
+#[derive(Component)]
+pub struct FollowPath {
+    /// A number between 0 and 1 to determine how interested the boid is in following the path,
+    /// see Seek's interest for how it interacts with other behaviors
+    pub interest: f32,
+}
+
+pub fn follow_path(
+    toggles: Res<BehaviorToggles>,
+    stagger: Res<SteeringStagger>,
+    path: Res<Path>,
+    mut query: Query<
+        (&mut Force, &Velocity, &Transform, &FollowPath, &SpawnIndex),
+        Without<Sleeping>,
+    >,
+) {
+    if !toggles.follow_path {
+        return;
+    }
+    for (mut force, velocity, Transform { translation, .. }, follow, index) in query.iter_mut() {
+        if !stagger.is_due(index) {
+            continue;
+        }
+        let position = translation.truncate();
+        let nearest = match path.nearest(position) {
+            Some(nearest) => nearest,
+            None => return,
+        };
+        let target = if nearest.end {
+            nearest.position
+        } else {
+            nearest.position + nearest.tangent * path.lead
+        };
+        let force = force.as_mut();
+        force.vector += seek_force(
+            target,
+            position,
+            velocity.vector,
+            force.max,
+            follow.interest,
+        );
+    }
+}
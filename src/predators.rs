@@ -0,0 +1,274 @@
+use bevy::{
+    ecs::{
+        component::Component,
+        entity::Entity,
+        query::{With, Without},
+        system::{Commands, Local, Query, Res, ResMut},
+    },
+    math::{Vec2, Vec3},
+    prelude::Transform,
+    render::color::Color,
+};
+use rand::Rng;
+
+use crate::{
+    alarm::Alarm,
+    create_boid_mesh_bundle,
+    grid::{self, SpatialGrid},
+    random_in_circle,
+    sleep::Sleeping,
+    stagger::SteeringStagger,
+    ArenaEdges, BehaviorToggles, BoidAssets, CometTail, Force, HeadingOffset, SimRng, SpawnIndex,
+    Tint, Velocity, WorldBounds,
+};
+
+/// A hunter that chases down boids, it can only see boids that are moving though, a boid that
+/// holds still is hidden from it
+///
+/// Boids flee from predators that come close, see flee_predators. Predators aren't boids, they
+/// don't flock, aren't part of the SpatialGrid and don't count towards the TargetPopulation
+#[derive(Component)]
+pub struct Predator {
+    pub velocity: Vec2,
+    /// The boid being chased, kept for as long as the predator can still see it
+    pub target: Option<Entity>,
+    /// Which way the predator is drifting while it has nothing to chase
+    wander: f32,
+}
+
+pub struct PredatorParams {
+    /// How many predators there are
+    pub count: usize,
+    pub max_speed: f32,
+    /// How hard a predator can turn, like a boid's Force.max
+    pub max_force: f32,
+    /// How far away a predator can see boids from
+    pub vision_radius: f32,
+    /// Boids moving slower than this are invisible to predators
+    pub min_prey_speed: f32,
+    /// How many radians a wandering predator's heading drifts by at most every frame
+    pub wander_jitter: f32,
+    /// How close a predator has to get before boids flee from it
+    pub flee_radius: f32,
+    /// How hard boids flee from predators as a fraction of their Force.max, 0 leaves boids
+    /// oblivious to predators
+    pub flee_strength: f32,
+    /// How much bigger predators are drawn than boids
+    pub scale: f32,
+}
+
+impl Default for PredatorParams {
+    fn default() -> Self {
+        PredatorParams {
+            count: 0,
+            max_speed: 1.2,
+            max_force: 0.05,
+            vision_radius: 250.0,
+            min_prey_speed: 0.3,
+            wander_jitter: 0.2,
+            flee_radius: 150.0,
+            flee_strength: 1.0,
+            scale: 2.0,
+        }
+    }
+}
+
+/// Spawns predators anywhere in the world (or despawns the newest) until there are
+/// PredatorParams::count of them
+pub fn regulate_predators(
+    mut commands: Commands,
+    params: Res<PredatorParams>,
+    assets: Res<BoidAssets>,
+    bounds: Res<WorldBounds>,
+    mut rng: ResMut<SimRng>,
+    mut spawned: Local<Vec<Entity>>,
+) {
+    while spawned.len() > params.count {
+        if let Some(predator) = spawned.pop() {
+            commands.entity(predator).despawn();
+        }
+    }
+
+    let rng = &mut rng.as_mut().0;
+    let half_size = bounds.size / 2.0;
+    while spawned.len() < params.count {
+        let position = Vec2::new(
+            rng.gen_range(-half_size.x..half_size.x),
+            rng.gen_range(-half_size.y..half_size.y),
+        );
+        let mut bundle =
+            create_boid_mesh_bundle(assets.pipeline.clone(), assets.mesh.clone(), position);
+        bundle.transform.scale = Vec3::new(params.scale, params.scale, 1.0);
+        spawned.push(
+            commands
+                .spawn_bundle(bundle)
+                .insert(CometTail::default())
+                .insert(Tint { color: Color::RED })
+                .insert(Alarm::default())
+                .insert(Predator {
+                    velocity: random_in_circle(rng, params.max_speed),
+                    target: None,
+                    wander: rng.gen_range(-std::f32::consts::PI..std::f32::consts::PI),
+                })
+                .id(),
+        );
+    }
+}
+
+/// Every predator chases the boid it's after, or the nearest one it can see when it isn't after
+/// one yet, and wanders around looking for one when every boid in sight is holding still
+pub fn hunt(
+    params: Res<PredatorParams>,
+    grid: Res<SpatialGrid>,
+    arena: ArenaEdges,
+    heading_offset: Res<HeadingOffset>,
+    mut rng: ResMut<SimRng>,
+    prey: Query<(&Transform, &Velocity), Without<Predator>>,
+    mut predators: Query<(&mut Transform, &mut Predator)>,
+) {
+    let metric = grid.metric();
+    let rng = &mut rng.as_mut().0;
+
+    for (mut transform, mut predator) in predators.iter_mut() {
+        let position = transform.translation.truncate();
+        // Where a boid is relative to the predator, as long as the predator can see it
+        let sighting = |entity: Entity| {
+            let (transform, velocity) = prey.get(entity).ok()?;
+            let offset = metric.offset(position, transform.translation.truncate());
+            (velocity.vector.length() >= params.min_prey_speed
+                && offset.length() <= params.vision_radius)
+                .then(|| offset)
+        };
+
+        let chased = predator
+            .target
+            .and_then(|target| Some((target, sighting(target)?)))
+            .or_else(|| {
+                grid::query_neighbors(&grid, position, params.vision_radius)
+                    .into_iter()
+                    .filter_map(|entity| Some((entity, sighting(entity)?)))
+                    .min_by(|(_, a), (_, b)| {
+                        a.length_squared()
+                            .partial_cmp(&b.length_squared())
+                            .unwrap_or(std::cmp::Ordering::Equal)
+                    })
+            });
+        predator.target = chased.map(|(target, _)| target);
+
+        let desired = match chased {
+            Some((_, offset)) => offset.normalize_or_zero() * params.max_speed,
+            None => {
+                predator.wander += rng.gen_range(-1.0..=1.0) * params.wander_jitter;
+                Vec2::new(predator.wander.cos(), predator.wander.sin()) * params.max_speed / 2.0
+            }
+        };
+        let steer = (desired - predator.velocity).clamp_length_max(params.max_force);
+        predator.velocity = (predator.velocity + steer).clamp_length_max(params.max_speed);
+        if chased.is_some() {
+            // Picking the wander back up from wherever the chase left the predator facing
+            predator.wander = predator.velocity.y.atan2(predator.velocity.x);
+        }
+
+        let mut position = transform.translation.truncate() + predator.velocity;
+        let velocity = &mut predator.velocity;
+        arena.handle(&mut position, velocity);
+        transform.translation.x = position.x;
+        transform.translation.y = position.y;
+        if *velocity != Vec2::ZERO {
+            transform.rotation = heading_offset.rotation(Vec2::X.angle_between(*velocity));
+        }
+    }
+}
+
+/// Boids with predators within PredatorParams::flee_radius steer straight away from them, harder
+/// the closer they are
+///
+/// Fleeing keeps a boid moving, so a predator that has seen it keeps seeing it, while a boid that
+/// freezes instead drops out of sight
+pub fn flee_predators(
+    toggles: Res<BehaviorToggles>,
+    params: Res<PredatorParams>,
+    stagger: Res<SteeringStagger>,
+    grid: Res<SpatialGrid>,
+    predators: Query<&Transform, With<Predator>>,
+    mut boids: Query<(&mut Force, &Velocity, &Transform, &SpawnIndex), Without<Sleeping>>,
+) {
+    if params.flee_strength <= 0.0 || params.flee_radius <= 0.0 || toggles.all_off() {
+        return;
+    }
+    let metric = grid.metric();
+
+    for (mut force, velocity, transform, index) in boids.iter_mut() {
+        if !stagger.is_due(index) {
+            continue;
+        }
+        let position = transform.translation.truncate();
+        // Every predator in range pushes the boid away from itself, from nothing at the edge of
+        // the range up to 1 right on top of it
+        let away = predators
+            .iter()
+            .map(|predator| metric.offset(predator.translation.truncate(), position))
+            .filter(|offset| offset.length() < params.flee_radius)
+            .fold(Vec2::ZERO, |away, offset| {
+                away + offset.normalize_or_zero() * (1.0 - offset.length() / params.flee_radius)
+            });
+        if away == Vec2::ZERO {
+            continue;
+        }
+
+        let force = force.as_mut();
+        let desired_velocity = away.normalize() * velocity.max;
+        force.vector += Vec2::clamp_length_max(desired_velocity - velocity.vector, force.max)
+            * away.length().min(1.0)
+            * params.flee_strength;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_world::test_world;
+    use bevy::ecs::schedule::{Stage, SystemStage};
+
+    /// The force a boid standing at the origin puts into fleeing from a predator at predator
+    fn flight(predator: Vec2) -> Vec2 {
+        let mut world = test_world(0);
+        world.spawn().insert_bundle((
+            Transform::from_xyz(predator.x, predator.y, 0.0),
+            Predator {
+                velocity: Vec2::ZERO,
+                target: None,
+                wander: 0.0,
+            },
+        ));
+        let boid = world
+            .spawn()
+            .insert_bundle((
+                Transform::default(),
+                Velocity {
+                    vector: Vec2::ZERO,
+                    max: 1.0,
+                },
+                Force {
+                    vector: Vec2::ZERO,
+                    max: 1.0,
+                },
+                SpawnIndex(0),
+            ))
+            .id();
+        SystemStage::single(flee_predators).run(&mut world);
+        world.get::<Force>(boid).unwrap().vector
+    }
+
+    #[test]
+    fn boids_flee_predators_that_come_close() {
+        let near = flight(Vec2::new(30.0, 0.0));
+        let further = flight(Vec2::new(120.0, 0.0));
+        // Straight away from the predator, and harder the closer it is
+        assert!(near.x < 0.0 && near.y == 0.0, "{}", near);
+        assert!(further.x < 0.0 && further.y == 0.0, "{}", further);
+        assert!(near.length() > further.length());
+        // And a predator out of range goes unnoticed
+        assert_eq!(flight(Vec2::new(200.0, 0.0)), Vec2::ZERO);
+    }
+}
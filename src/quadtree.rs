@@ -0,0 +1,121 @@
+use bevy::{ecs::entity::Entity, math::Vec2};
+
+/// A node stops splitting past this depth, so a pile of boids sitting on the exact same spot
+/// can't split it forever
+const MAX_DEPTH: u32 = 12;
+
+/// A spatial index that splits the space into quarters, then those quarters into quarters and so
+/// on wherever there are more than capacity entities, so it's fine grained where boids crowd
+/// together and stays coarse wherever there's hardly anyone
+///
+/// Unlike SpatialGrid's uniform cells this doesn't spend anything on empty space, and a dense
+/// clump doesn't pile hundreds of boids into the same few cells
+pub struct QuadTree {
+    capacity: usize,
+    root: Node,
+}
+
+struct Node {
+    min: Vec2,
+    max: Vec2,
+    depth: u32,
+    entries: Vec<(Entity, Vec2)>,
+    /// The four quarters of this node once it has been split, with x growing with the first bit
+    /// of the index and y with the second
+    children: Option<Box<[Node; 4]>>,
+}
+
+impl Node {
+    fn new(min: Vec2, max: Vec2, depth: u32) -> Self {
+        Node {
+            min,
+            max,
+            depth,
+            entries: Vec::new(),
+            children: None,
+        }
+    }
+
+    fn child_of(&self, position: Vec2) -> usize {
+        let middle = (self.min + self.max) / 2.0;
+        (position.x >= middle.x) as usize | ((position.y >= middle.y) as usize) << 1
+    }
+
+    fn insert(&mut self, entry: (Entity, Vec2), capacity: usize) {
+        let index = self.child_of(entry.1);
+        if let Some(children) = &mut self.children {
+            children[index].insert(entry, capacity);
+            return;
+        }
+
+        self.entries.push(entry);
+        if self.entries.len() > capacity && self.depth < MAX_DEPTH {
+            let (min, max, depth) = (self.min, self.max, self.depth + 1);
+            let middle = (min + max) / 2.0;
+            self.children = Some(Box::new([
+                Node::new(min, middle, depth),
+                Node::new(
+                    Vec2::new(middle.x, min.y),
+                    Vec2::new(max.x, middle.y),
+                    depth,
+                ),
+                Node::new(
+                    Vec2::new(min.x, middle.y),
+                    Vec2::new(middle.x, max.y),
+                    depth,
+                ),
+                Node::new(middle, max, depth),
+            ]));
+            for entry in std::mem::take(&mut self.entries) {
+                self.insert(entry, capacity);
+            }
+        }
+    }
+
+    fn within(&self, position: Vec2, radius: f32, found: &mut Vec<(Entity, Vec2)>) {
+        // Nothing in a node is in range if even its closest point to position isn't
+        let closest = position.clamp(self.min, self.max);
+        if closest.distance_squared(position) > radius * radius {
+            return;
+        }
+        found.extend(
+            self.entries
+                .iter()
+                .filter(|(_, other)| other.distance_squared(position) <= radius * radius),
+        );
+        if let Some(children) = &self.children {
+            for child in children.iter() {
+                child.within(position, radius, found);
+            }
+        }
+    }
+}
+
+impl QuadTree {
+    /// A tree holding every one of entries, its root spans just enough to fit all of them
+    pub fn build(entries: &[(Entity, Vec2)], capacity: usize) -> Self {
+        let (min, max) = entries.iter().fold(
+            (Vec2::splat(f32::MAX), Vec2::splat(f32::MIN)),
+            |(min, max), (_, position)| (min.min(*position), max.max(*position)),
+        );
+        let (min, max) = if entries.is_empty() {
+            (Vec2::ZERO, Vec2::ZERO)
+        } else {
+            (min, max)
+        };
+
+        let mut root = Node::new(min, max, 0);
+        let capacity = capacity.max(1);
+        for &entry in entries {
+            root.insert(entry, capacity);
+        }
+        QuadTree { capacity, root }
+    }
+
+    /// Every entity (and its position) within radius of position
+    pub fn within(&self, position: Vec2, radius: f32) -> Vec<(Entity, Vec2)> {
+        let mut found = Vec::with_capacity(self.capacity);
+        self.root.within(position, radius, &mut found);
+        found
+    }
+}
@@ -0,0 +1,124 @@
+use bevy::{
+    app::App,
+    ecs::{
+        schedule::ShouldRun,
+        system::{Res, ResMut},
+    },
+};
+
+/// Only renders once every `every` frames while the simulation keeps running every frame, for
+/// profiling the simulation without the cost of rendering muddying the numbers
+///
+/// Skipped frames skip the render graph's systems, which is what syncs transforms and the other
+/// render resources over to the GPU, along with drawing and the render itself, so the window
+/// keeps showing the last rendered frame in between. Anything that changed while rendering was
+/// skipped is picked up on the next rendered frame
+///
+/// Asset uploads aren't skipped, an asset added on a skipped frame would otherwise be missed for
+/// good once its event is gone
+pub struct RenderThrottle {
+    /// How many frames go by between rendered frames, 1 renders every frame
+    pub every: u32,
+    /// How many frames ago the last rendered frame was
+    phase: u32,
+}
+
+impl Default for RenderThrottle {
+    fn default() -> Self {
+        RenderThrottle { every: 1, phase: 0 }
+    }
+}
+
+/// How many frames the simulation runs for before anything is rendered at all, so the flock is
+/// already flying about when it first shows up rather than sitting where it was spawned
+///
+/// The warm-up frames are all run by warm_up before the app is run and its window is opened, so
+/// the very first frame the window shows is the flock already flying. They skip rendering just
+/// like RenderThrottle's skipped frames, there's no window to render them to anyway, so they go
+/// by about as fast as the simulation can run them
+#[derive(Default)]
+pub struct WarmUp {
+    pub ticks: u32,
+    /// How many frames have started so far, counting stops one frame past the warm-up
+    started: u32,
+}
+
+impl WarmUp {
+    fn is_done(&self) -> bool {
+        self.started > self.ticks
+    }
+}
+
+/// Runs the WarmUp's frames one after the other, this has to be called right before App::run so
+/// that the startup systems and everything they set up are in place and the window isn't open
+/// yet
+pub fn warm_up(app: &mut App) {
+    let ticks = app
+        .world
+        .get_resource::<WarmUp>()
+        .map_or(0, |warm_up| warm_up.ticks);
+    // The frame after these is the one that finishes the warm-up and gets rendered
+    for _ in 0..ticks {
+        app.update();
+    }
+}
+
+/// Runs in CoreStage::First so the whole frame agrees on whether it gets rendered
+pub fn advance_render_throttle(mut throttle: ResMut<RenderThrottle>, mut warm_up: ResMut<WarmUp>) {
+    let throttle = throttle.as_mut();
+    throttle.phase = (throttle.phase + 1) % throttle.every.max(1);
+    // The frame the warm-up finishes on is the first one rendered, whatever the throttle says
+    if !warm_up.is_done() {
+        warm_up.started += 1;
+        throttle.phase = 0;
+    }
+}
+
+/// Run criteria for the render stages that get skipped
+pub fn is_render_frame(throttle: Res<RenderThrottle>, warm_up: Res<WarmUp>) -> ShouldRun {
+    if warm_up.is_done() && throttle.phase == 0 {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::schedule::SystemStage;
+
+    /// How many frames have been rendered
+    #[derive(Default)]
+    struct Rendered(u32);
+
+    fn render(mut rendered: ResMut<Rendered>) {
+        rendered.0 += 1;
+    }
+
+    #[test]
+    fn warm_up_is_over_before_the_app_runs() {
+        let mut app = App::empty();
+        app.insert_resource(RenderThrottle::default())
+            .insert_resource(WarmUp {
+                ticks: 30,
+                ..Default::default()
+            })
+            .init_resource::<Rendered>()
+            .add_stage("first", SystemStage::single(advance_render_throttle))
+            .add_stage(
+                "render",
+                SystemStage::single(render).with_run_criteria(is_render_frame),
+            );
+
+        warm_up(&mut app);
+        assert_eq!(app.world.get_resource::<Rendered>().unwrap().0, 0);
+        assert_eq!(app.world.get_resource::<WarmUp>().unwrap().started, 30);
+
+        // So the first frame the running app goes through is rendered, and every one after it
+        app.update();
+        assert_eq!(app.world.get_resource::<Rendered>().unwrap().0, 1);
+        app.update();
+        assert_eq!(app.world.get_resource::<Rendered>().unwrap().0, 2);
+    }
+}
@@ -0,0 +1,236 @@
+use bevy::{
+    core::Time,
+    ecs::{
+        component::Component,
+        entity::Entity,
+        query::With,
+        system::{Query, Res, ResMut},
+    },
+    math::Vec2,
+    prelude::Transform,
+};
+use rand::{rngs::StdRng, Rng};
+
+use crate::{
+    flocking::CruiseSpeed, flocks::FlockId, random_in_circle, spawn_boid, BoidSpawner,
+    DeterministicMode, Force, SpawnIndex, TargetPopulation, Velocity,
+};
+
+/// Boids every so often split off a copy of themselves, for an artificial life feel
+///
+/// A boid has its first offspring once it has been alive for interval seconds and another one
+/// every interval seconds after that. Offspring take after their parent's Genes, each nudged by
+/// up to mutation (as a fraction) either way, so over many generations the flock drifts towards
+/// faster or slower, twitchier or lazier boids
+///
+/// Offspring count towards the TargetPopulation so they aren't taken back out by DespawnExcess,
+/// no more are born once there are max_boids
+pub struct Reproduction {
+    pub enabled: bool,
+    pub interval: f32,
+    pub mutation: f32,
+    pub max_boids: usize,
+    /// How far from its parent an offspring is placed
+    pub spread: f32,
+}
+
+impl Default for Reproduction {
+    fn default() -> Self {
+        Reproduction {
+            enabled: false,
+            interval: 20.0,
+            mutation: 0.1,
+            max_boids: 500,
+            spread: 10.0,
+        }
+    }
+}
+
+/// However far mutation takes them over the generations, offspring's Genes stay within these
+/// multiples of the first boids'
+const MUTATION_BOUNDS: (f32, f32) = (0.25, 4.0);
+
+/// Seconds since a boid last had an offspring, or since it was spawned
+#[derive(Component, Default)]
+pub struct BirthTimer(f32);
+
+/// What a boid inherited from its parent, as multipliers on top of the CruiseSpeed and Force.max
+/// every boid gets from the Mood, so an offspring stays faster or twitchier than the rest however
+/// the mood changes. The first boids have no parent and are all 1
+#[derive(Component, Clone, Copy, PartialEq, Debug)]
+pub struct Genes {
+    pub speed: f32,
+    pub force: f32,
+}
+
+impl Default for Genes {
+    fn default() -> Self {
+        Genes {
+            speed: 1.0,
+            force: 1.0,
+        }
+    }
+}
+
+impl Genes {
+    /// An offspring's genes, each of the parent's nudged by up to mutation (as a fraction) either
+    /// way and kept within MUTATION_BOUNDS
+    fn inherit(&self, rng: &mut StdRng, mutation: f32) -> Genes {
+        Genes {
+            speed: mutate(rng, self.speed, mutation),
+            force: mutate(rng, self.force, mutation),
+        }
+    }
+}
+
+/// value nudged by up to mutation of itself either way, kept within MUTATION_BOUNDS
+fn mutate(rng: &mut StdRng, value: f32, mutation: f32) -> f32 {
+    let mutation = mutation.clamp(0.0, 1.0);
+    let nudged = if mutation > 0.0 {
+        value * (1.0 + rng.gen_range(-mutation..=mutation))
+    } else {
+        value
+    };
+    nudged.clamp(MUTATION_BOUNDS.0, MUTATION_BOUNDS.1)
+}
+
+pub fn reproduce(
+    spawner: BoidSpawner,
+    time: Res<Time>,
+    params: Res<Reproduction>,
+    deterministic: Res<DeterministicMode>,
+    mut target: ResMut<TargetPopulation>,
+    mut boids: Query<(
+        Entity,
+        &mut BirthTimer,
+        &Transform,
+        &Velocity,
+        &Force,
+        &CruiseSpeed,
+        &Genes,
+        &FlockId,
+        &SpawnIndex,
+    )>,
+    others: Query<Entity, With<Velocity>>,
+) {
+    if !params.enabled {
+        return;
+    }
+    let population = others.iter().count();
+    let room = params.max_boids.saturating_sub(population);
+
+    let mut parents = Vec::new();
+    for (entity, mut timer, transform, velocity, force, cruise_speed, &genes, &flock, &index) in
+        boids.iter_mut()
+    {
+        timer.0 += time.delta_seconds();
+        // A boid that's ready but can't fit keeps waiting for room rather than starting over
+        if timer.0 >= params.interval.max(f32::EPSILON) {
+            parents.push((
+                index,
+                entity,
+                transform.translation.truncate(),
+                velocity.vector,
+                // What the mood gives every boid, with the parent's own genes taken back out
+                cruise_speed.0 / genes.speed,
+                force.max / genes.force,
+                genes,
+                flock,
+            ));
+        }
+    }
+    // Which boids make it in under max_boids depends on the order they're visited in, which is
+    // only the same every run when they're sorted
+    if deterministic.0 {
+        parents.sort_unstable_by_key(|&(index, ..)| index);
+    }
+    parents.truncate(room);
+
+    // Offspring join their parent's flock rather than being handed one round-robin
+    let BoidSpawner {
+        mut commands,
+        assets,
+        mut rng,
+        mut next_index,
+        flocks: _,
+    } = spawner;
+    let rng = &mut rng.as_mut().0;
+    for &(_, parent, position, velocity, cruise_speed, max_force, genes, flock) in parents.iter() {
+        if let Ok((_, mut timer, ..)) = boids.get_mut(parent) {
+            timer.0 = 0.0;
+        }
+        let genes = genes.inherit(rng, params.mutation);
+        // apply_mood keeps these up to date from the next frame on, they're only set here so the
+        // offspring doesn't spend its first frame as an average boid
+        let cruise_speed = cruise_speed * genes.speed;
+        let max_force = max_force * genes.force;
+        let offspring = spawn_boid(
+            &mut commands,
+            &assets,
+            position + random_in_circle(rng, params.spread),
+            velocity,
+            next_index.take(),
+            flock,
+        );
+        commands
+            .entity(offspring)
+            .insert(Velocity {
+                vector: velocity.clamp_length_max(cruise_speed),
+                max: cruise_speed,
+            })
+            .insert(CruiseSpeed(cruise_speed))
+            .insert(Force {
+                vector: Vec2::ZERO,
+                max: max_force,
+            })
+            .insert(genes);
+    }
+    target.0 = target.0.max(population + parents.len());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+
+    #[test]
+    fn offspring_stay_within_the_mutation_range_of_their_parent() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let parent = Genes {
+            speed: 1.5,
+            force: 0.8,
+        };
+        let mutation = 0.1;
+        for _ in 0..1000 {
+            let offspring = parent.inherit(&mut rng, mutation);
+            for (child, parent) in [
+                (offspring.speed, parent.speed),
+                (offspring.force, parent.force),
+            ] {
+                assert!(child >= parent * (1.0 - mutation) - f32::EPSILON);
+                assert!(child <= parent * (1.0 + mutation) + f32::EPSILON);
+            }
+        }
+    }
+
+    #[test]
+    fn offspring_never_mutate_past_the_bounds() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let mut genes = Genes::default();
+        for _ in 0..1000 {
+            genes = genes.inherit(&mut rng, 1.0);
+            assert!((MUTATION_BOUNDS.0..=MUTATION_BOUNDS.1).contains(&genes.speed));
+            assert!((MUTATION_BOUNDS.0..=MUTATION_BOUNDS.1).contains(&genes.force));
+        }
+    }
+
+    #[test]
+    fn no_mutation_copies_the_parent() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let parent = Genes {
+            speed: 1.2,
+            force: 0.9,
+        };
+        assert_eq!(parent.inherit(&mut rng, 0.0), parent);
+    }
+}
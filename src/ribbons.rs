@@ -0,0 +1,175 @@
+use bevy::{
+    asset::{Assets, Handle},
+    ecs::{
+        entity::Entity,
+        query::With,
+        system::{Commands, Local, Query, Res, ResMut},
+    },
+    math::Vec2,
+    prelude::Transform,
+    render::{
+        color::Color,
+        draw::Visible,
+        mesh::{Indices, Mesh},
+        pipeline::PrimitiveTopology,
+    },
+};
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    alarm::Alarm, color::vertex_color, create_boid_mesh_bundle, BoidAssets, CometTail, Tint,
+    Velocity, ATTRIBUTE_TAIL,
+};
+
+/// Ribbons sit behind the boids and their glow, but still in front of the arena's floor
+const RIBBON_Z: f32 = -0.03;
+
+/// Draws a ribbon behind every boid along where it has been, widest right behind the boid and
+/// tapering off towards its end
+///
+/// Every ribbon is its own triangle strip mesh rebuilt every frame from the boid's trail, so long
+/// ribbons on a big flock get expensive
+pub struct RibbonTrails {
+    pub enabled: bool,
+    /// How many of a boid's past positions, one per frame, its ribbon runs through
+    pub length: usize,
+    /// How wide the ribbon is right behind the boid
+    pub max_width: f32,
+    /// How quickly the ribbon narrows, the width is max_width * t^taper where t goes from 1
+    /// right behind the boid to 0 at the end, so 0 doesn't taper at all and 1 tapers evenly
+    pub taper: f32,
+    pub color: Color,
+    /// A boid that moves further than this in a single frame has wrapped around an edge or been
+    /// teleported, its ribbon starts over rather than being stretched across the world
+    pub max_jump: f32,
+}
+
+impl Default for RibbonTrails {
+    fn default() -> Self {
+        RibbonTrails {
+            enabled: false,
+            length: 30,
+            max_width: 6.0,
+            taper: 1.0,
+            color: Color::rgb(0.4, 0.7, 1.0),
+            max_jump: 50.0,
+        }
+    }
+}
+
+/// Where a boid has been, newest first, along with the entity and mesh its ribbon is drawn with
+pub struct Ribbon {
+    trail: VecDeque<Vec2>,
+    entity: Entity,
+    mesh: Handle<Mesh>,
+}
+
+/// The two edges of a ribbon through points, newest first, as a triangle strip zigzagging from
+/// one edge to the other, along with how bright each vertex is
+fn ribbon_strip(points: &VecDeque<Vec2>, trails: &RibbonTrails) -> (Vec<[f32; 3]>, Vec<f32>) {
+    let last = points.len().saturating_sub(1).max(1) as f32;
+    let mut positions = Vec::with_capacity(points.len() * 2);
+    let mut brightness = Vec::with_capacity(points.len() * 2);
+    for (i, &point) in points.iter().enumerate() {
+        // The ribbon runs along the line through its neighboring points, ends only have one
+        let ahead = i
+            .checked_sub(1)
+            .and_then(|ahead| points.get(ahead))
+            .copied()
+            .unwrap_or(point);
+        let behind = points.get(i + 1).copied().unwrap_or(point);
+        let t = 1.0 - i as f32 / last;
+        let width = trails.max_width * t.powf(trails.taper.max(0.0));
+        let side = (ahead - behind).normalize_or_zero().perp() * width / 2.0;
+        for edge in [point + side, point - side] {
+            positions.push([edge.x, edge.y, 0.0]);
+            brightness.push(t);
+        }
+    }
+    (positions, brightness)
+}
+
+pub fn draw_ribbon_trails(
+    mut commands: Commands,
+    trails: Res<RibbonTrails>,
+    assets: Res<BoidAssets>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut ribbons: Local<HashMap<Entity, Ribbon>>,
+    mut drawn: Query<(&mut Visible, &mut Tint)>,
+    boids: Query<(Entity, &Transform), With<Velocity>>,
+) {
+    // Ribbons of boids that are gone, or all of them when ribbons were turned off, are taken
+    // down along with their meshes
+    ribbons.retain(|&boid, ribbon| {
+        let keep = trails.enabled && boids.get(boid).is_ok();
+        if !keep {
+            commands.entity(ribbon.entity).despawn();
+            meshes.remove(&ribbon.mesh);
+        }
+        keep
+    });
+    if !trails.enabled {
+        return;
+    }
+
+    for (boid, transform) in boids.iter() {
+        let position = transform.translation.truncate();
+        let ribbon = ribbons.entry(boid).or_insert_with(|| {
+            let mesh = meshes.add(Mesh::new(PrimitiveTopology::TriangleStrip));
+            let mut bundle =
+                create_boid_mesh_bundle(assets.pipeline.clone(), mesh.clone(), Vec2::ZERO);
+            bundle.transform.translation.z = RIBBON_Z;
+            bundle.visible.is_visible = false;
+            let entity = commands
+                .spawn_bundle(bundle)
+                .insert(CometTail::default())
+                .insert(Tint {
+                    color: trails.color,
+                })
+                .insert(Alarm::default())
+                .id();
+            Ribbon {
+                trail: VecDeque::new(),
+                entity,
+                mesh,
+            }
+        });
+
+        let jumped = ribbon
+            .trail
+            .front()
+            .map_or(false, |&last| last.distance(position) > trails.max_jump);
+        if jumped {
+            ribbon.trail.clear();
+        }
+        ribbon.trail.push_front(position);
+        ribbon.trail.truncate(trails.length);
+
+        // A ribbon needs at least two points to go anywhere
+        let shown = ribbon.trail.len() >= 2;
+        if let Ok((mut visible, mut tint)) = drawn.get_mut(ribbon.entity) {
+            visible.is_visible = shown;
+            tint.color = trails.color;
+        }
+        if !shown {
+            continue;
+        }
+
+        if let Some(mesh) = meshes.get_mut(&ribbon.mesh) {
+            let (positions, brightness) = ribbon_strip(&ribbon.trail, &trails);
+            let vertices = positions.len();
+            mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+            // The color comes from the Tint, the vertex colors fade it out towards the end
+            mesh.set_attribute(
+                Mesh::ATTRIBUTE_COLOR,
+                brightness
+                    .into_iter()
+                    .map(|t| vertex_color(Color::rgb(t, t, t)))
+                    .collect::<Vec<_>>(),
+            );
+            // The boid pipeline expects a comet tail, the ribbons just don't have one
+            mesh.set_attribute(ATTRIBUTE_TAIL, vec![0.0; vertices]);
+            mesh.set_indices(Some(Indices::U32((0..vertices as u32).collect())));
+        }
+    }
+}
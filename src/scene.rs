@@ -0,0 +1,151 @@
+use bevy::{
+    app::AppExit,
+    core::Time,
+    ecs::{
+        event::EventWriter,
+        query::With,
+        system::{Local, Query, Res, ResMut},
+    },
+    prelude::Transform,
+    render::camera::OrthographicProjection,
+};
+
+use crate::{
+    custom::{spiral_inward, CustomSteering},
+    BehaviorToggles, TargetPopulation, WorldBounds,
+};
+
+/// What the simulation starts out as, picked on the command line with `--scene default`,
+/// `--scene empty`, `--scene spiral` or `--scene stress [boids]`
+pub enum Scene {
+    /// The usual flock
+    Default,
+    /// No boids at all, for setting things up by hand
+    Empty,
+    /// The usual flock pulled into a spiral by a CustomSteering, showing off how to plug one in
+    Spiral,
+    /// As many boids as the machine can take with every behavior on, while the camera sweeps
+    /// back and forth across the world. Frame times are measured for duration seconds, then a
+    /// summary is printed and the app exits
+    Stress { boids: usize, duration: f32 },
+}
+
+impl Scene {
+    /// The scene asked for on the command line, anything that isn't understood falls back to
+    /// the default scene with a warning
+    pub fn from_args() -> Scene {
+        let args = std::env::args().collect::<Vec<_>>();
+        let scene = match args.iter().position(|arg| arg == "--scene") {
+            Some(position) => &args[position + 1..],
+            None => return Scene::Default,
+        };
+        match scene {
+            [name, ..] if name == "default" => Scene::Default,
+            [name, ..] if name == "empty" => Scene::Empty,
+            [name, ..] if name == "spiral" => Scene::Spiral,
+            [name, rest @ ..] if name == "stress" => Scene::Stress {
+                boids: rest
+                    .first()
+                    .and_then(|boids| boids.parse().ok())
+                    .unwrap_or(5000),
+                duration: 20.0,
+            },
+            _ => {
+                eprintln!("Unknown scene {:?}, using the default scene", scene.first());
+                Scene::Default
+            }
+        }
+    }
+}
+
+pub fn setup_scene(
+    scene: Res<Scene>,
+    mut target: ResMut<TargetPopulation>,
+    mut toggles: ResMut<BehaviorToggles>,
+    mut custom: ResMut<CustomSteering>,
+) {
+    match *scene {
+        Scene::Default => {}
+        Scene::Empty => target.0 = 0,
+        Scene::Spiral => *custom = CustomSteering::new(spiral_inward),
+        Scene::Stress { boids, .. } => {
+            target.0 = boids;
+            *toggles = BehaviorToggles {
+                seek: true,
+                seek_cursor: true,
+                separation: true,
+                alignment: true,
+                cohesion: true,
+                regroup: true,
+                formation: true,
+                attractors: true,
+                obstacles: true,
+                follow_path: true,
+            };
+        }
+    }
+}
+
+/// Frame times seen so far in a stress run
+#[derive(Default)]
+pub struct StressRun {
+    elapsed: f32,
+    frames: u32,
+    total: f32,
+    min: f32,
+    max: f32,
+    done: bool,
+}
+
+pub fn run_stress_scene(
+    time: Res<Time>,
+    scene: Res<Scene>,
+    bounds: Res<WorldBounds>,
+    mut run: Local<StressRun>,
+    mut exit: EventWriter<AppExit>,
+    mut cameras: Query<&mut Transform, With<OrthographicProjection>>,
+) {
+    let duration = match *scene {
+        Scene::Stress { duration, .. } => duration,
+        _ => return,
+    };
+    if run.done {
+        return;
+    }
+
+    let frame_time = time.delta_seconds();
+    run.elapsed += frame_time;
+    // The very first frame includes all of startup, which would throw off the max
+    if time.seconds_since_startup() > frame_time as f64 {
+        run.frames += 1;
+        run.total += frame_time;
+        run.min = if run.frames == 1 {
+            frame_time
+        } else {
+            run.min.min(frame_time)
+        };
+        run.max = run.max.max(frame_time);
+    }
+
+    // A figure of eight across the world, so every part of it gets drawn from every side
+    let t = run.elapsed * 0.5;
+    for mut camera in cameras.iter_mut() {
+        camera.translation.x = t.sin() * bounds.size.x / 4.0;
+        camera.translation.y = (2.0 * t).sin() * bounds.size.y / 8.0;
+    }
+
+    if run.elapsed >= duration {
+        run.done = true;
+        if run.frames > 0 {
+            println!(
+                "Stress scene: {} frames over {:.1}s, frame time avg {:.2}ms, min {:.2}ms, max {:.2}ms",
+                run.frames,
+                run.elapsed,
+                run.total / run.frames as f32 * 1000.0,
+                run.min * 1000.0,
+                run.max * 1000.0
+            );
+        }
+        exit.send(AppExit);
+    }
+}
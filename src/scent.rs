@@ -0,0 +1,198 @@
+use bevy::{
+    core::Time,
+    ecs::{
+        query::{With, Without},
+        system::{Query, Res, ResMut},
+    },
+    math::Vec2,
+    prelude::Transform,
+};
+
+use crate::{
+    sleep::Sleeping, stagger::SteeringStagger, BehaviorToggles, DeterministicMode, Force,
+    SpawnIndex, Velocity, WorldBounds,
+};
+
+/// What boids do about the scent they sense ahead of them
+#[derive(Clone, Copy, PartialEq)]
+pub enum ScentResponse {
+    /// Turn towards the strongest scent, so boids end up travelling along each other's trails
+    /// like ants do
+    Follow,
+    /// Turn towards the weakest scent, so boids keep to places nobody has been lately
+    Avoid,
+}
+
+/// Boids leave a scent behind wherever they go which fades over time, and the boids coming after
+/// them weakly follow (or avoid) it, so they end up steering each other through the world rather
+/// than just through who they can see
+///
+/// The scent is kept in a grid of cell_size cells over WorldBounds, which starts over empty
+/// whenever WorldBounds changes size. Nothing is deposited or sensed outside of WorldBounds
+pub struct ScentField {
+    pub enabled: bool,
+    pub response: ScentResponse,
+    pub cell_size: f32,
+    /// How much scent a boid leaves in the cell it's in every second
+    pub deposit: f32,
+    /// How quickly scent fades, every cell keeps e^(-decay) of its scent each second
+    pub decay: f32,
+    /// How hard boids turn towards (or away from) scent, as a fraction of their Force.max
+    pub strength: f32,
+    /// How far ahead of itself a boid senses scent
+    pub sense_distance: f32,
+    /// How far to either side of its heading a boid senses scent, in radians
+    pub sense_angle: f32,
+    /// How much scent is in each cell, row by row from the bottom left corner of the world
+    cells: Vec<f32>,
+    columns: usize,
+    rows: usize,
+    /// The WorldBounds size the cells were laid out over
+    size: Vec2,
+}
+
+impl Default for ScentField {
+    fn default() -> Self {
+        ScentField {
+            enabled: false,
+            response: ScentResponse::Follow,
+            cell_size: 10.0,
+            deposit: 1.0,
+            decay: 0.2,
+            strength: 0.3,
+            sense_distance: 20.0,
+            sense_angle: std::f32::consts::FRAC_PI_4,
+            cells: Vec::new(),
+            columns: 0,
+            rows: 0,
+            size: Vec2::ZERO,
+        }
+    }
+}
+
+impl ScentField {
+    /// Lays the cells out over a world of size, throwing away all of the scent unless they're
+    /// already laid out over it
+    fn fit(&mut self, size: Vec2) {
+        let cell_size = self.cell_size.max(1.0);
+        let columns = (size.x / cell_size).ceil().max(1.0) as usize;
+        let rows = (size.y / cell_size).ceil().max(1.0) as usize;
+        if self.size == size && self.columns == columns && self.rows == rows {
+            return;
+        }
+        self.cells = vec![0.0; columns * rows];
+        self.columns = columns;
+        self.rows = rows;
+        self.size = size;
+    }
+
+    /// Which cell position is in, if it's inside the world at all
+    fn cell(&self, position: Vec2) -> Option<usize> {
+        let cell_size = self.cell_size.max(1.0);
+        let from_corner = (position + self.size / 2.0) / cell_size;
+        if from_corner.x < 0.0 || from_corner.y < 0.0 {
+            return None;
+        }
+        let (column, row) = (from_corner.x as usize, from_corner.y as usize);
+        if column >= self.columns || row >= self.rows {
+            return None;
+        }
+        Some(row * self.columns + column)
+    }
+
+    /// How much scent there is at position, there's none at all outside the world
+    pub fn scent_at(&self, position: Vec2) -> f32 {
+        self.cell(position).map_or(0.0, |cell| self.cells[cell])
+    }
+}
+
+/// Fades the scent already in the field and has every boid leave some more where it is now, this
+/// runs once boids have moved for the frame
+pub fn deposit_scent(
+    time: Res<Time>,
+    bounds: Res<WorldBounds>,
+    deterministic: Res<DeterministicMode>,
+    mut field: ResMut<ScentField>,
+    boids: Query<(&Transform, &SpawnIndex), With<Velocity>>,
+) {
+    let field = field.as_mut();
+    if !field.enabled {
+        // Turning scent back on starts from a clean slate rather than from wherever it was left
+        field.cells.clear();
+        field.size = Vec2::ZERO;
+        return;
+    }
+    field.fit(bounds.size);
+
+    let delta = time.delta_seconds();
+    let fade = (-field.decay.max(0.0) * delta).exp();
+    for scent in field.cells.iter_mut() {
+        *scent *= fade;
+    }
+
+    let mut deposits = boids
+        .iter()
+        .filter_map(|(transform, &index)| {
+            field
+                .cell(transform.translation.truncate())
+                .map(|cell| (index, cell))
+        })
+        .collect::<Vec<_>>();
+    // Two boids in the same cell add to it one after the other, which only comes out the same
+    // every run when they're sorted
+    if deterministic.0 {
+        deposits.sort_unstable_by_key(|&(index, _)| index);
+    }
+    let deposit = field.deposit * delta;
+    for (_, cell) in deposits {
+        field.cells[cell] += deposit;
+    }
+}
+
+/// Senses scent ahead of each boid, to its left and to its right, and turns towards whichever
+/// of them has the most scent when following or the least when avoiding. A boid that's not
+/// moving has no ahead to sense in, so it's left for other behaviors to get it going
+pub fn follow_scent(
+    toggles: Res<BehaviorToggles>,
+    stagger: Res<SteeringStagger>,
+    field: Res<ScentField>,
+    mut query: Query<(&mut Force, &Velocity, &Transform, &SpawnIndex), Without<Sleeping>>,
+) {
+    if !field.enabled || field.cells.is_empty() || toggles.all_off() {
+        return;
+    }
+    for (mut force, velocity, Transform { translation, .. }, index) in query.iter_mut() {
+        if !stagger.is_due(index) {
+            continue;
+        }
+        let heading = velocity.vector.normalize_or_zero();
+        if heading == Vec2::ZERO {
+            continue;
+        }
+        let position = translation.truncate();
+        let sense = |angle: f32| {
+            let direction = heading * angle.cos() + heading.perp() * angle.sin();
+            let scent = field.scent_at(position + direction * field.sense_distance);
+            match field.response {
+                ScentResponse::Follow => (direction, scent),
+                ScentResponse::Avoid => (direction, -scent),
+            }
+        };
+        let ahead = sense(0.0);
+        let (direction, preference) = [sense(field.sense_angle), sense(-field.sense_angle)]
+            .into_iter()
+            .fold(
+                ahead,
+                |best, side| if side.1 > best.1 { side } else { best },
+            );
+        // Nothing to the sides beats carrying on straight ahead
+        if preference <= ahead.1 {
+            continue;
+        }
+
+        let force = force.as_mut();
+        let desired_velocity = direction * velocity.max;
+        force.vector +=
+            Vec2::clamp_length_max(desired_velocity - velocity.vector, force.max) * field.strength;
+    }
+}
@@ -0,0 +1,340 @@
+use bevy::render::{
+    color::Color,
+    mesh::{Indices, Mesh},
+    pipeline::PrimitiveTopology,
+};
+
+use crate::{color::vertex_color, BoidGradient, ATTRIBUTE_TAIL};
+
+/// The shape boids are drawn as
+///
+/// Every silhouette is modeled with its nose at the origin pointing along +x and its tail 10
+/// units behind it, so they all work with update_boids without a HeadingOffset. Each also has a
+/// comet tail tip (marked with ATTRIBUTE_TAIL) that sits on the silhouette's back edge so the
+/// tail has no area until the boid picks up speed
+///
+/// Triangles are wound counter-clockwise seen from the camera so they survive BackFaceCulling
+pub enum BoidSilhouette {
+    /// A plain isosceles triangle
+    Triangle,
+    /// A chevron, a triangle with a notch cut out of its back
+    Arrow,
+    /// A round head tapering back to a point at the tail
+    Teardrop,
+}
+
+impl Default for BoidSilhouette {
+    fn default() -> Self {
+        BoidSilhouette::Triangle
+    }
+}
+
+/// How many triangles make up the teardrop's round head
+const TEARDROP_SEGMENTS: u32 = 8;
+
+impl BoidSilhouette {
+    pub fn mesh(&self, gradient: &BoidGradient) -> Mesh {
+        let (positions, tail, indices) = match self {
+            // The last vertex is the comet tail's tip, it starts right in the middle of the
+            // tail's edge so without any speed the tail is a triangle with no area and the boid
+            // looks like a plain triangle
+            BoidSilhouette::Triangle => (
+                vec![
+                    [-10.0, -5.0, 0.0],
+                    [0.0, 0.0, 0.0],
+                    [-10.0, 5.0, 0.0],
+                    [-10.0, 0.0, 0.0],
+                ],
+                vec![0.0, 0.0, 0.0, 1.0],
+                vec![0, 1, 2, 2, 3, 0],
+            ),
+            // The same as the triangle, with the notch's vertex pushed in from the middle of
+            // the tail's edge
+            BoidSilhouette::Arrow => (
+                vec![
+                    [-10.0, -5.0, 0.0],
+                    [0.0, 0.0, 0.0],
+                    [-10.0, 5.0, 0.0],
+                    [-6.0, 0.0, 0.0],
+                    [-10.0, 0.0, 0.0],
+                ],
+                vec![0.0, 0.0, 0.0, 0.0, 1.0],
+                vec![0, 1, 3, 3, 1, 2, 2, 4, 0],
+            ),
+            // A fan around the center of the head, starting from the point of the tail (which
+            // doubles as the comet tail's tip) and sweeping around the head from the bottom,
+            // through the nose and to the top before coming back to the tail
+            BoidSilhouette::Teardrop => {
+                let radius = 4.0;
+                let center = [-radius, 0.0, 0.0];
+                let head = (0..=TEARDROP_SEGMENTS).map(|i| {
+                    let angle = std::f32::consts::PI * (i as f32 / TEARDROP_SEGMENTS as f32 - 0.5);
+                    [-radius + radius * angle.cos(), radius * angle.sin(), 0.0]
+                });
+                let positions = [center, [-10.0, 0.0, 0.0]]
+                    .into_iter()
+                    .chain(head)
+                    .collect::<Vec<_>>();
+
+                let mut tail = vec![0.0; positions.len()];
+                tail[1] = 1.0;
+
+                // The outline is every vertex but the center, in order and wrapping back around
+                let outline = positions.len() as u32 - 1;
+                let indices = (0..outline)
+                    .flat_map(|i| [0, 1 + i, 1 + (i + 1) % outline])
+                    .collect();
+
+                (positions, tail, indices)
+            }
+        };
+
+        let mut mesh = Mesh::new(PrimitiveTopology::TriangleList);
+        mesh.set_attribute(Mesh::ATTRIBUTE_COLOR, gradient.vertex_colors(&positions));
+        mesh.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+        mesh.set_attribute(ATTRIBUTE_TAIL, tail);
+        mesh.set_indices(Some(Indices::U32(indices)));
+        mesh
+    }
+}
+
+/// A ring with an outer radius of 1 and a band thickness as wide, centered on the origin, made of
+/// segments quads going around it
+pub fn create_ring_mesh(segments: u32, thickness: f32) -> Mesh {
+    // Anything less than a triangle doesn't have any area
+    let segments = segments.max(3);
+    let inner = 1.0 - thickness;
+    let positions = (0..segments)
+        .flat_map(|i| {
+            let angle = i as f32 * std::f32::consts::TAU / segments as f32;
+            let (sin, cos) = angle.sin_cos();
+            [[cos, sin, 0.0], [cos * inner, sin * inner, 0.0]]
+        })
+        .collect::<Vec<_>>();
+    let vertices = positions.len();
+
+    // Every segment is a quad between this segment's outer and inner vertices and the next one's,
+    // wound counter-clockwise like the rest of the meshes
+    let indices = (0..segments)
+        .flat_map(|i| {
+            let outer = 2 * i;
+            let next = 2 * ((i + 1) % segments);
+            [outer, next, next + 1, next + 1, outer + 1, outer]
+        })
+        .collect();
+
+    let mut ring = Mesh::new(PrimitiveTopology::TriangleList);
+    ring.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    // The color comes entirely from the Tint
+    ring.set_attribute(
+        Mesh::ATTRIBUTE_COLOR,
+        vec![vertex_color(Color::WHITE); vertices],
+    );
+    // The boid pipeline expects a comet tail, the ring just doesn't have one
+    ring.set_attribute(ATTRIBUTE_TAIL, vec![0.0; vertices]);
+    ring.set_indices(Some(Indices::U32(indices)));
+    ring
+}
+
+/// A circle with a radius of 1 centered on the origin, made of segments triangles fanning out
+/// from its center
+pub fn create_circle_mesh(segments: u32) -> Mesh {
+    // Anything less than a triangle doesn't have any area
+    let segments = segments.max(3);
+    let positions = std::iter::once([0.0, 0.0, 0.0])
+        .chain((0..segments).map(|i| {
+            let angle = i as f32 * std::f32::consts::TAU / segments as f32;
+            [angle.cos(), angle.sin(), 0.0]
+        }))
+        .collect::<Vec<_>>();
+    let vertices = positions.len();
+
+    // Every triangle goes from the center to a vertex and then counter-clockwise to the next one,
+    // the first triangle closes the circle between its last and first vertices
+    let indices = std::iter::once([0, segments, 1])
+        .chain((2..=segments).map(|i| [0, i - 1, i]))
+        .flatten()
+        .collect();
+
+    let mut circle = Mesh::new(PrimitiveTopology::TriangleList);
+    circle.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    // The color comes entirely from the Tint
+    circle.set_attribute(
+        Mesh::ATTRIBUTE_COLOR,
+        vec![vertex_color(Color::WHITE); vertices],
+    );
+    // The boid pipeline expects a comet tail, the circle just doesn't have one
+    circle.set_attribute(ATTRIBUTE_TAIL, vec![0.0; vertices]);
+    circle.set_indices(Some(Indices::U32(indices)));
+    circle
+}
+
+/// A slice of a circle with a radius of 1 centered on the origin, spanning angle radians and
+/// centered on +x, made of segments triangles fanning out from its tip
+pub fn create_sector_mesh(segments: u32, angle: f32) -> Mesh {
+    let segments = segments.max(1);
+    let angle = angle.clamp(0.0, std::f32::consts::TAU);
+    let positions = std::iter::once([0.0, 0.0, 0.0])
+        .chain((0..=segments).map(|i| {
+            let angle = -angle / 2.0 + i as f32 * angle / segments as f32;
+            [angle.cos(), angle.sin(), 0.0]
+        }))
+        .collect::<Vec<_>>();
+    let vertices = positions.len();
+
+    // Every triangle goes from the tip to a vertex on the arc and counter-clockwise to the next
+    let indices = (1..=segments).flat_map(|i| [0, i, i + 1]).collect();
+
+    let mut sector = Mesh::new(PrimitiveTopology::TriangleList);
+    sector.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    // The color comes entirely from the Tint
+    sector.set_attribute(
+        Mesh::ATTRIBUTE_COLOR,
+        vec![vertex_color(Color::WHITE); vertices],
+    );
+    // The boid pipeline expects a comet tail, the sector just doesn't have one
+    sector.set_attribute(ATTRIBUTE_TAIL, vec![0.0; vertices]);
+    sector.set_indices(Some(Indices::U32(indices)));
+    sector
+}
+
+/// An arrow pointing along +x, 1 unit long so it can be stretched to any length while keeping
+/// its width in pixels
+pub fn create_arrow_mesh() -> Mesh {
+    let positions = vec![
+        // The shaft
+        [0.0, -0.5, 0.0],
+        [0.75, -0.5, 0.0],
+        [0.75, 0.5, 0.0],
+        [0.0, 0.5, 0.0],
+        // The head
+        [0.75, -2.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [0.75, 2.0, 0.0],
+    ];
+    let vertices = positions.len();
+
+    let mut arrow = Mesh::new(PrimitiveTopology::TriangleList);
+    arrow.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    // The color comes entirely from the Tint
+    arrow.set_attribute(
+        Mesh::ATTRIBUTE_COLOR,
+        vec![vertex_color(Color::WHITE); vertices],
+    );
+    // The boid pipeline expects a comet tail, the arrow just doesn't have one
+    arrow.set_attribute(ATTRIBUTE_TAIL, vec![0.0; vertices]);
+    arrow.set_indices(Some(Indices::U32(vec![0, 1, 2, 2, 3, 0, 4, 5, 6])));
+    arrow
+}
+
+/// A bar along +x from the origin, 1 unit long and 1 unit wide so it can be stretched to any
+/// length and width, e.g. to fill a gauge up from its left end
+pub fn create_bar_mesh() -> Mesh {
+    let positions = vec![
+        [0.0, -0.5, 0.0],
+        [1.0, -0.5, 0.0],
+        [1.0, 0.5, 0.0],
+        [0.0, 0.5, 0.0],
+    ];
+    let vertices = positions.len();
+
+    let mut bar = Mesh::new(PrimitiveTopology::TriangleList);
+    bar.set_attribute(Mesh::ATTRIBUTE_POSITION, positions);
+    // The color comes entirely from the Tint
+    bar.set_attribute(
+        Mesh::ATTRIBUTE_COLOR,
+        vec![vertex_color(Color::WHITE); vertices],
+    );
+    // The boid pipeline expects a comet tail, the bar just doesn't have one
+    bar.set_attribute(ATTRIBUTE_TAIL, vec![0.0; vertices]);
+    bar.set_indices(Some(Indices::U32(vec![0, 1, 2, 2, 3, 0])));
+    bar
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::math::Vec2;
+
+    fn indices(mesh: &Mesh) -> Vec<u32> {
+        match mesh.indices() {
+            Some(Indices::U32(indices)) => indices.clone(),
+            _ => panic!("the mesh should have u32 indices"),
+        }
+    }
+
+    #[test]
+    fn circle_has_a_center_and_a_triangle_per_segment() {
+        for segments in [3, 8, 128] {
+            let circle = create_circle_mesh(segments);
+            let vertices = circle.attribute(Mesh::ATTRIBUTE_POSITION).unwrap().len();
+            assert_eq!(vertices, segments as usize + 1);
+            let indices = indices(&circle);
+            assert_eq!(indices.len(), segments as usize * 3);
+            assert!(indices.iter().all(|&index| (index as usize) < vertices));
+        }
+    }
+
+    #[test]
+    fn circle_has_at_least_three_segments() {
+        let circle = create_circle_mesh(1);
+        assert_eq!(circle.attribute(Mesh::ATTRIBUTE_POSITION).unwrap().len(), 4);
+        assert_eq!(indices(&circle).len(), 9);
+    }
+
+    /// The x and y of every vertex of mesh, read straight out of its position buffer
+    fn positions(mesh: &Mesh) -> Vec<Vec2> {
+        let bytes = mesh
+            .attribute(Mesh::ATTRIBUTE_POSITION)
+            .unwrap()
+            .get_bytes();
+        bytes
+            .chunks_exact(12)
+            .map(|vertex| {
+                let coordinate =
+                    |i: usize| f32::from_ne_bytes(vertex[i * 4..i * 4 + 4].try_into().unwrap());
+                Vec2::new(coordinate(0), coordinate(1))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_triangle_is_wound_counter_clockwise() {
+        let meshes = [
+            (
+                "triangle",
+                BoidSilhouette::Triangle.mesh(&BoidGradient::default()),
+            ),
+            (
+                "arrow silhouette",
+                BoidSilhouette::Arrow.mesh(&BoidGradient::default()),
+            ),
+            (
+                "teardrop",
+                BoidSilhouette::Teardrop.mesh(&BoidGradient::default()),
+            ),
+            ("ring", create_ring_mesh(32, 0.05)),
+            ("circle", create_circle_mesh(3)),
+            ("circle", create_circle_mesh(32)),
+            ("sector", create_sector_mesh(8, 1.0)),
+            ("sector", create_sector_mesh(8, std::f32::consts::TAU)),
+            ("arrow", create_arrow_mesh()),
+            ("bar", create_bar_mesh()),
+        ];
+        for (name, mesh) in meshes.iter() {
+            let positions = positions(mesh);
+            for triangle in indices(mesh).chunks_exact(3) {
+                let [a, b, c] = [0, 1, 2].map(|i| positions[triangle[i] as usize]);
+                // Twice the triangle's signed area, positive when it goes around counter-clockwise.
+                // Degenerate triangles (like a comet tail at rest) have none, which is fine
+                let area = (b - a).perp_dot(c - a);
+                assert!(
+                    area >= 0.0,
+                    "the {}'s triangle {:?} is wound clockwise",
+                    name,
+                    triangle
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use bevy::{
+    core::Time,
+    ecs::{
+        component::Component,
+        entity::Entity,
+        query::{With, Without},
+        system::{Commands, Query, Res},
+    },
+    math::Vec2,
+    prelude::Transform,
+};
+
+use crate::{grid::SpatialGrid, Force, Velocity};
+
+/// Marks a boid that has settled down, its flocking behaviors are skipped until something
+/// wakes it up again, see SleepParams
+///
+/// Whatever comes from outside the flock (the cursor, attractors) still pushes sleeping boids
+/// around, which is what gets them to wake up
+#[derive(Component)]
+pub struct Sleeping;
+
+/// How long a boid has been barely moving with barely anything pushing it, in seconds
+#[derive(Component, Default)]
+pub struct Settling(pub f32);
+
+/// Boids that hold still for a while fall asleep to save the work of steering them, which adds
+/// up in big flocks that have come to rest. Only one that's slower than sleep_speed with a force
+/// weaker than sleep_force for settle_time seconds in a row falls asleep
+///
+/// A sleeping boid wakes up when it's pushed harder than wake_force, or when an awake boid moving
+/// faster than wake_speed comes within wake_radius of it. Boids that wake up start moving and so
+/// wake up the ones sleeping around them in turn, so a disturbance spreads through a sleeping
+/// flock instead of leaving most of it frozen
+pub struct SleepParams {
+    pub enabled: bool,
+    pub sleep_speed: f32,
+    pub sleep_force: f32,
+    pub settle_time: f32,
+    pub wake_force: f32,
+    pub wake_speed: f32,
+    pub wake_radius: f32,
+}
+
+impl Default for SleepParams {
+    fn default() -> Self {
+        SleepParams {
+            enabled: false,
+            sleep_speed: 0.02,
+            sleep_force: 0.01,
+            settle_time: 2.0,
+            wake_force: 0.05,
+            wake_speed: 0.1,
+            wake_radius: 40.0,
+        }
+    }
+}
+
+/// This has to run after every steering system, whether a boid is being pushed is decided by
+/// its total force
+pub fn sleep_and_wake(
+    mut commands: Commands,
+    time: Res<Time>,
+    params: Res<SleepParams>,
+    grid: Res<SpatialGrid>,
+    mut awake: Query<(Entity, &mut Force, &mut Velocity, &mut Settling), Without<Sleeping>>,
+    sleeping: Query<(Entity, &Force, &Transform), With<Sleeping>>,
+) {
+    if !params.enabled {
+        // Nobody is left sleeping with no way to wake up when sleep gets turned off
+        for (entity, ..) in sleeping.iter() {
+            commands.entity(entity).remove::<Sleeping>();
+        }
+        return;
+    }
+
+    for (entity, force, transform) in sleeping.iter() {
+        let position = transform.translation.truncate();
+        let disturbed = grid
+            .within(position, params.wake_radius)
+            .filter(|&(other, _)| other != entity)
+            .any(|(other, _)| {
+                awake.get(other).map_or(false, |(_, _, velocity, _)| {
+                    velocity.vector.length() > params.wake_speed
+                })
+            });
+        if disturbed || force.vector.length() > params.wake_force {
+            commands.entity(entity).remove::<Sleeping>();
+        }
+    }
+
+    for (entity, mut force, mut velocity, mut settling) in awake.iter_mut() {
+        if velocity.vector.length() >= params.sleep_speed
+            || force.vector.length() >= params.sleep_force
+        {
+            settling.0 = 0.0;
+            continue;
+        }
+        settling.0 += time.delta_seconds();
+        if settling.0 >= params.settle_time {
+            settling.0 = 0.0;
+            // Sleeping boids hold perfectly still rather than drifting off ever so slowly
+            velocity.vector = Vec2::ZERO;
+            force.vector = Vec2::ZERO;
+            commands.entity(entity).insert(Sleeping);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{grid::SpatialIndex, test_world::test_world};
+    use bevy::ecs::{
+        schedule::{Stage, SystemStage},
+        world::World,
+    };
+
+    fn sleep_world() -> World {
+        let mut world = test_world(0);
+        world.insert_resource(SleepParams {
+            enabled: true,
+            settle_time: 0.01,
+            ..Default::default()
+        });
+        world
+    }
+
+    fn spawn_boid(world: &mut World, position: Vec2, velocity: Vec2, force: Vec2) -> Entity {
+        world
+            .spawn()
+            .insert_bundle((
+                Transform::from_xyz(position.x, position.y, 0.0),
+                Velocity {
+                    vector: velocity,
+                    max: 1.0,
+                },
+                Force {
+                    vector: force,
+                    max: 1.0,
+                },
+                Settling::default(),
+            ))
+            .id()
+    }
+
+    /// Lets some time pass and runs sleep_and_wake once
+    fn tick(world: &mut World, stage: &mut SystemStage) {
+        std::thread::sleep(std::time::Duration::from_millis(4));
+        world.get_resource_mut::<Time>().unwrap().update();
+        stage.run(world);
+    }
+
+    #[test]
+    fn still_boids_fall_asleep_after_settle_time() {
+        let mut world = sleep_world();
+        let still = spawn_boid(&mut world, Vec2::ZERO, Vec2::ZERO, Vec2::ZERO);
+        let moving = spawn_boid(&mut world, Vec2::ZERO, Vec2::new(0.5, 0.0), Vec2::ZERO);
+        let mut stage = SystemStage::single(sleep_and_wake);
+
+        // The first update only starts the clock, so no time has been spent holding still yet
+        tick(&mut world, &mut stage);
+        assert!(world.get::<Sleeping>(still).is_none());
+
+        // Each tick after it is at least 4ms, so this is well past settle_time
+        for _ in 0..4 {
+            tick(&mut world, &mut stage);
+        }
+        assert!(world.get::<Sleeping>(still).is_some());
+        assert!(world.get::<Sleeping>(moving).is_none());
+    }
+
+    #[test]
+    fn strong_forces_wake_sleeping_boids() {
+        let mut world = sleep_world();
+        let pushed = spawn_boid(&mut world, Vec2::ZERO, Vec2::ZERO, Vec2::new(0.1, 0.0));
+        let nudged = spawn_boid(&mut world, Vec2::ZERO, Vec2::ZERO, Vec2::new(0.01, 0.0));
+        world.entity_mut(pushed).insert(Sleeping);
+        world.entity_mut(nudged).insert(Sleeping);
+
+        SystemStage::single(sleep_and_wake).run(&mut world);
+
+        // Only the one pushed harder than wake_force wakes up
+        assert!(world.get::<Sleeping>(pushed).is_none());
+        assert!(world.get::<Sleeping>(nudged).is_some());
+    }
+
+    #[test]
+    fn moving_boids_wake_sleeping_ones_nearby() {
+        let mut world = sleep_world();
+        let mover = spawn_boid(
+            &mut world,
+            Vec2::new(20.0, 0.0),
+            Vec2::new(0.5, 0.0),
+            Vec2::ZERO,
+        );
+        let near = spawn_boid(&mut world, Vec2::ZERO, Vec2::ZERO, Vec2::ZERO);
+        // Further away than wake_radius
+        let far = spawn_boid(&mut world, Vec2::new(200.0, 0.0), Vec2::ZERO, Vec2::ZERO);
+        world.entity_mut(near).insert(Sleeping);
+        world.entity_mut(far).insert(Sleeping);
+        let mut grid = SpatialGrid::default();
+        grid.rebuild(
+            SpatialIndex::UniformGrid,
+            vec![
+                (mover, Vec2::new(20.0, 0.0)),
+                (near, Vec2::ZERO),
+                (far, Vec2::new(200.0, 0.0)),
+            ],
+        );
+        world.insert_resource(grid);
+
+        SystemStage::single(sleep_and_wake).run(&mut world);
+
+        assert!(world.get::<Sleeping>(near).is_none());
+        assert!(world.get::<Sleeping>(far).is_some());
+    }
+}
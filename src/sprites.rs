@@ -0,0 +1,101 @@
+use bevy::{
+    asset::{AssetServer, Assets, Handle, LoadState},
+    ecs::{
+        entity::Entity,
+        query::With,
+        system::{Commands, Query, Res, ResMut},
+    },
+    math::Vec2,
+    prelude::Transform,
+    render::texture::Texture,
+    sprite::{entity::SpriteBundle, ColorMaterial, Sprite},
+};
+
+use crate::{create_boid_mesh_bundle, BoidAssets, Velocity};
+
+/// What boids are drawn as
+pub enum SpriteMode {
+    /// The BoidSilhouette mesh, shaded with BoidGradient and Tint
+    Mesh,
+    /// A picture loaded from path (relative to the assets folder) stretched to size, turned by
+    /// update_boids just like the mesh so the picture should face +x or have a matching
+    /// HeadingOffset
+    ///
+    /// Sprites are drawn with bevy's own sprite pipeline, so none of the boid shader's effects
+    /// (gradient, Tint, comet tails, alarm flashes) show up on them
+    Sprite { path: String, size: Vec2 },
+}
+
+impl Default for SpriteMode {
+    fn default() -> Self {
+        SpriteMode::Mesh
+    }
+}
+
+/// What sprite boids are drawn with
+pub struct BoidSprite {
+    texture: Handle<Texture>,
+    material: Handle<ColorMaterial>,
+    size: Vec2,
+}
+
+impl BoidSprite {
+    /// Starts loading the SpriteMode's picture, if it has one
+    pub fn load(
+        mode: &SpriteMode,
+        asset_server: &AssetServer,
+        materials: &mut Assets<ColorMaterial>,
+    ) -> Option<BoidSprite> {
+        match mode {
+            SpriteMode::Mesh => None,
+            SpriteMode::Sprite { path, size } => {
+                let texture = asset_server.load(path.as_str());
+                Some(BoidSprite {
+                    material: materials.add(ColorMaterial::texture(texture.clone())),
+                    texture,
+                    size: *size,
+                })
+            }
+        }
+    }
+
+    pub fn bundle(&self, position: Vec2) -> SpriteBundle {
+        SpriteBundle {
+            sprite: Sprite::new(self.size),
+            material: self.material.clone(),
+            transform: Transform::from_xyz(position.x, position.y, 0.0),
+            ..Default::default()
+        }
+    }
+}
+
+/// Picture files load in the background, so a missing or broken one only shows up a few frames
+/// in, at which point every boid goes back to being drawn as the mesh, including the ones that
+/// were spawned as sprites while it was loading
+pub fn fall_back_from_failed_sprite(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut assets: ResMut<BoidAssets>,
+    boids: Query<(Entity, &Transform), (With<Sprite>, With<Velocity>)>,
+) {
+    let failed = match &assets.sprite {
+        Some(sprite) => asset_server.get_load_state(&sprite.texture) == LoadState::Failed,
+        None => false,
+    };
+    if !failed {
+        return;
+    }
+
+    eprintln!("Couldn't load the boid sprite, drawing boids as meshes instead");
+    assets.sprite = None;
+    for (boid, transform) in boids.iter() {
+        let mut bundle =
+            create_boid_mesh_bundle(assets.pipeline.clone(), assets.mesh.clone(), Vec2::ZERO);
+        bundle.transform = *transform;
+        commands
+            .entity(boid)
+            .remove::<Sprite>()
+            .remove::<Handle<ColorMaterial>>()
+            .insert_bundle(bundle);
+    }
+}
@@ -0,0 +1,165 @@
+use bevy::{
+    ecs::{
+        component::Component,
+        system::{Query, Res, ResMut},
+    },
+    math::Vec2,
+};
+
+use crate::{Force, FreezeSteering, SpawnIndex};
+
+/// Spreads the cost of steering over several frames for large flocks, every boid only works out
+/// a new force once every `every` frames and keeps pushing with the last one it worked out in
+/// between
+///
+/// Boids take turns round-robin by spawn order, so each frame an even 1/every of the flock
+/// steers. The tradeoff is that boids react up to every - 1 frames late to whatever is around
+/// them, which is hard to notice at a handful of frames but makes near misses overshoot more
+pub struct SteeringStagger {
+    /// How many frames go by between a boid's steering updates, 1 updates every boid every frame
+    pub every: u32,
+    /// Which boids take their turn this frame
+    phase: u32,
+}
+
+impl Default for SteeringStagger {
+    fn default() -> Self {
+        SteeringStagger { every: 1, phase: 0 }
+    }
+}
+
+impl SteeringStagger {
+    /// Whether the boid spawned at index works out its steering this frame
+    pub fn is_due(&self, index: &SpawnIndex) -> bool {
+        self.every <= 1 || index.0 % self.every as u64 == self.phase as u64
+    }
+}
+
+/// The force a boid last worked out for itself, kept around to be pushed with until its next turn
+#[derive(Component, Default)]
+pub struct StaggeredForce(Vec2);
+
+/// Moves the turn on to the next group of boids, before any of them start steering
+pub fn advance_stagger(mut stagger: ResMut<SteeringStagger>) {
+    let stagger = stagger.as_mut();
+    stagger.phase = (stagger.phase + 1) % stagger.every.max(1);
+}
+
+/// This has to run after every steering system and before apply_force, boids that had their
+/// turn this frame remember their force and the ones that didn't get their last one back
+///
+/// Being the last word on the steering forces this is also where FreezeSteering throws them all
+/// away, without touching what boids remember for when steering is unfrozen
+pub fn hold_staggered_forces(
+    stagger: Res<SteeringStagger>,
+    freeze: Res<FreezeSteering>,
+    mut query: Query<(&mut Force, &mut StaggeredForce, &SpawnIndex)>,
+) {
+    for (mut force, mut staggered, index) in query.iter_mut() {
+        if freeze.0 {
+            force.vector = Vec2::ZERO;
+        } else if stagger.is_due(index) {
+            staggered.0 = force.vector;
+        } else {
+            force.vector = staggered.0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        apply_force, flocking, grid, regulate_population, test_world::test_world, CapMode,
+        Velocity, BOID_MAX_FORCE,
+    };
+    use bevy::{
+        ecs::{
+            schedule::{Stage, SystemStage},
+            world::World,
+        },
+        prelude::Transform,
+    };
+
+    /// Every boid's position and velocity after each of ticks ticks of flocking, in spawn order,
+    /// with steering staggered over every frames
+    fn flock(every: u32, ticks: usize) -> Vec<Vec<(Vec2, Vec2)>> {
+        let mut world = test_world(4);
+        world.insert_resource(SteeringStagger { every, phase: 0 });
+        // So that every frame turns a boid by no more than its Force.max
+        world.insert_resource(CapMode::Both);
+
+        SystemStage::single(regulate_population).run(&mut world);
+        let mut stages = vec![
+            SystemStage::single(advance_stagger),
+            SystemStage::single(grid::update_spatial_grid),
+            SystemStage::single(flocking::gather_neighbors),
+            SystemStage::single(flocking::fade_awareness),
+            SystemStage::single(flocking::separate),
+            SystemStage::single(flocking::align),
+            SystemStage::single(flocking::cohere),
+            SystemStage::single(hold_staggered_forces),
+            SystemStage::single(apply_force),
+        ];
+        (0..ticks)
+            .map(|_| {
+                for stage in stages.iter_mut() {
+                    stage.run(&mut world);
+                }
+                let mut boids = world
+                    .query::<(&SpawnIndex, &Transform, &Velocity)>()
+                    .iter(&world)
+                    .map(|(&index, transform, velocity)| {
+                        (index, transform.translation.truncate(), velocity.vector)
+                    })
+                    .collect::<Vec<_>>();
+                boids.sort_unstable_by_key(|&(index, ..)| index);
+                boids
+                    .into_iter()
+                    .map(|(_, position, velocity)| (position, velocity))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn staggered_flocks_fly_like_unstaggered_ones() {
+        let ticks = 20;
+        let unstaggered = flock(1, ticks);
+        let staggered = flock(2, ticks);
+
+        // Holding on to a force for an extra frame never turns a boid any harder than steering
+        // every frame could, which is at most as far as Force.max gets it off its old heading
+        for frames in staggered.windows(2) {
+            for (&(_, before), &(_, after)) in frames[0].iter().zip(&frames[1]) {
+                assert!(
+                    before.distance(after) <= BOID_MAX_FORCE + 1e-4,
+                    "turned from {} to {} in one frame",
+                    before,
+                    after
+                );
+                if before.length() > BOID_MAX_FORCE {
+                    let turn = before.angle_between(after).abs();
+                    assert!(turn <= (BOID_MAX_FORCE / before.length()).asin() + 1e-4);
+                }
+            }
+        }
+
+        // And being a frame late to react only puts boids a little off of where they would be
+        let mut moved = false;
+        for (unstaggered, staggered) in unstaggered.iter().zip(&staggered) {
+            let drift = unstaggered
+                .iter()
+                .zip(staggered)
+                .map(|(&(a, _), &(b, _))| a.distance(b))
+                .sum::<f32>()
+                / unstaggered.len() as f32;
+            assert!(drift < 2.0, "boids drifted {} apart on average", drift);
+            moved |= unstaggered
+                .iter()
+                .any(|&(_, velocity)| velocity != Vec2::ZERO);
+        }
+        // Boids that never moved would trivially stay together
+        assert!(moved);
+    }
+}
@@ -0,0 +1,94 @@
+//! The world tests run the simulation's systems in, with neither a window nor a renderer
+
+use bevy::{asset::Handle, core::Time, ecs::world::World};
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::{
+    custom::CustomSteering,
+    flocking::{
+        AwarenessFade, CollisionMatrix, FlockingParams, IdleCruising, NeighborWeighting,
+        SeparationMath, SpeedController, SpeedScaledSeparation, StalemateBreaking, TargetAvgSpeed,
+    },
+    flocks::FlockCount,
+    force_log::SteeringTotals,
+    grid::{SpatialGrid, SpatialIndex},
+    metrics::{FlockMetrics, MetricsWindow},
+    obstacles::{ObstacleGrid, ObstacleParams},
+    predators::PredatorParams,
+    scent::ScentField,
+    stagger::SteeringStagger,
+    ArenaShape, BehaviorToggles, BoidAssets, CapMode, DespawnExcess, DeterministicMode,
+    EdgeBehaviors, FreezeSteering, Gravity, HeadingOffset, InitialHeading, MaxAcceleration,
+    MinSpeed, NextSpawnIndex, RotationOnly, RotationSmoothing, SimRng, SoftEdge, SpawnJitter,
+    SpawnMass, SpawnPattern, SpawnRate, SpawnSpacing, SpeedCapMode, Substeps, TargetPopulation,
+    WorldBounds,
+};
+
+/// A world with every resource the simulation's systems read at its default and SimRng seeded
+/// with seed, swap any of them out by inserting them again
+pub fn test_world(seed: u64) -> World {
+    let mut world = World::new();
+    world.insert_resource(Time::default());
+    world.insert_resource(SimRng(StdRng::seed_from_u64(seed)));
+    world.insert_resource(DeterministicMode::default());
+    world.insert_resource(BoidAssets {
+        pipeline: Handle::default(),
+        mesh: Handle::default(),
+        sprite: None,
+    });
+
+    // Moving boids
+    world.insert_resource(Substeps::default());
+    world.insert_resource(CapMode::default());
+    world.insert_resource(SpeedCapMode::default());
+    world.insert_resource(MinSpeed::default());
+    world.insert_resource(Gravity::default());
+    world.insert_resource(MaxAcceleration::default());
+    world.insert_resource(RotationOnly::default());
+    world.insert_resource(RotationSmoothing::default());
+    world.insert_resource(HeadingOffset::default());
+    world.insert_resource(ArenaShape::default());
+    world.insert_resource(EdgeBehaviors::default());
+    world.insert_resource(SoftEdge::default());
+    world.insert_resource(WorldBounds::default());
+
+    // Spawning them
+    world.insert_resource(TargetPopulation::default());
+    world.insert_resource(DespawnExcess::default());
+    world.insert_resource(FlockCount::default());
+    world.insert_resource(SpawnRate::default());
+    world.insert_resource(SpawnSpacing::default());
+    world.insert_resource(SpawnPattern::default());
+    world.insert_resource(SpawnJitter::default());
+    world.insert_resource(SpawnMass::default());
+    world.insert_resource(InitialHeading::default());
+    world.insert_resource(NextSpawnIndex::default());
+
+    // Steering them
+    world.insert_resource(BehaviorToggles::default());
+    world.insert_resource(FreezeSteering::default());
+    world.insert_resource(SteeringStagger::default());
+    world.insert_resource(SpatialIndex::default());
+    world.insert_resource(SpatialGrid::default());
+    world.insert_resource(FlockingParams::default());
+    world.insert_resource(SeparationMath::default());
+    world.insert_resource(SpeedScaledSeparation::default());
+    world.insert_resource(CollisionMatrix::default());
+    world.insert_resource(NeighborWeighting::default());
+    world.insert_resource(AwarenessFade::default());
+    world.insert_resource(SteeringTotals::default());
+    world.insert_resource(ObstacleParams::default());
+    world.insert_resource(ObstacleGrid::default());
+    world.insert_resource(PredatorParams::default());
+    world.insert_resource(TargetAvgSpeed::default());
+    world.insert_resource(SpeedController::default());
+    world.insert_resource(StalemateBreaking::default());
+    world.insert_resource(IdleCruising::default());
+    world.insert_resource(ScentField::default());
+    world.insert_resource(CustomSteering::default());
+
+    // Measuring them
+    world.insert_resource(MetricsWindow::default());
+    world.insert_resource(FlockMetrics::default());
+    world
+}
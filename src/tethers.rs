@@ -0,0 +1,136 @@
+use bevy::{
+    ecs::{
+        component::Component,
+        entity::Entity,
+        query::With,
+        system::{Commands, Query, Res},
+    },
+    math::Vec2,
+    prelude::Transform,
+};
+use std::collections::HashMap;
+
+use crate::{grid::SpatialGrid, inspect::Pinned, Mass, SpawnIndex, Velocity};
+
+/// Ties a boid to its partner like a string would, the two can fly as close together as they
+/// like but never further apart than max_len
+///
+/// A tether only holds the boid it's on, tying two boids together both ways takes a Tether on
+/// each of them pointing at the other
+#[derive(Component)]
+pub struct Tether {
+    pub partner: Entity,
+    pub max_len: f32,
+}
+
+/// How many pairs of boids tie_tethers ties together, and how long their tethers are
+///
+/// Boids are paired up in the order they spawned, lowering pairs later doesn't untie anyone
+pub struct TetherParams {
+    pub pairs: usize,
+    pub max_len: f32,
+}
+
+impl Default for TetherParams {
+    fn default() -> Self {
+        TetherParams {
+            pairs: 0,
+            max_len: 40.0,
+        }
+    }
+}
+
+pub fn tie_tethers(
+    mut commands: Commands,
+    params: Res<TetherParams>,
+    boids: Query<(Entity, &SpawnIndex, Option<&Tether>), With<Velocity>>,
+) {
+    let tied = boids
+        .iter()
+        .filter(|(_, _, tether)| tether.is_some())
+        .count()
+        / 2;
+    if tied >= params.pairs {
+        return;
+    }
+    let mut loose = boids
+        .iter()
+        .filter(|(_, _, tether)| tether.is_none())
+        .map(|(entity, &index, _)| (index, entity))
+        .collect::<Vec<_>>();
+    loose.sort_unstable_by_key(|&(index, _)| index);
+
+    for pair in loose.chunks_exact(2).take(params.pairs - tied) {
+        let (a, b) = (pair[0].1, pair[1].1);
+        commands.entity(a).insert(Tether {
+            partner: b,
+            max_len: params.max_len,
+        });
+        commands.entity(b).insert(Tether {
+            partner: a,
+            max_len: params.max_len,
+        });
+    }
+}
+
+/// This has to run after boids have moved, it pulls every tethered boid that ended up too far
+/// from its partner back in along the tether
+///
+/// The pull is split between the two by mass with the lighter boid moving more, a tether whose
+/// partner is gone is untied
+pub fn enforce_tethers(
+    mut commands: Commands,
+    grid: Res<SpatialGrid>,
+    tethers: Query<(Entity, &Tether)>,
+    mut boids: Query<(Entity, &mut Transform, Option<&Mass>, Option<&Pinned>), With<Velocity>>,
+) {
+    if tethers.iter().next().is_none() {
+        return;
+    }
+    let metric = grid.metric();
+    // Where every boid is and how easily it's moved, updated as tethers are pulled tight so a
+    // pair tied both ways is only pulled together once
+    let mut boid_states = boids
+        .iter_mut()
+        .map(|(entity, transform, mass, pinned)| {
+            let mass = mass.map_or(1.0, |mass| mass.0).max(f32::EPSILON);
+            // A Pinned boid can't be moved at all, so its partner does all of the moving
+            let give = if pinned.is_some() { 0.0 } else { 1.0 / mass };
+            (entity, (transform.translation.truncate(), give))
+        })
+        .collect::<HashMap<_, _>>();
+
+    for (entity, tether) in tethers.iter() {
+        let (&(position, give), &(partner_position, partner_give)) =
+            match (boid_states.get(&entity), boid_states.get(&tether.partner)) {
+                (Some(state), Some(partner_state)) => (state, partner_state),
+                (_, None) => {
+                    commands.entity(entity).remove::<Tether>();
+                    continue;
+                }
+                // Something that isn't a boid (anymore) has nothing to be pulled
+                (None, _) => continue,
+            };
+        let offset = metric.offset(position, partner_position);
+        let distance = offset.length();
+        let max_len = tether.max_len.max(0.0);
+        // Two Pinned boids stay where they are however far apart they are
+        if distance <= max_len || give + partner_give <= 0.0 {
+            continue;
+        }
+        let pull = offset / distance * (distance - max_len) / (give + partner_give);
+        if let Some((position, _)) = boid_states.get_mut(&entity) {
+            *position += pull * give;
+        }
+        if let Some((position, _)) = boid_states.get_mut(&tether.partner) {
+            *position -= pull * partner_give;
+        }
+    }
+
+    for (entity, mut transform, ..) in boids.iter_mut() {
+        if let Some(&(position, _)) = boid_states.get(&entity) {
+            transform.translation.x = position.x;
+            transform.translation.y = position.y;
+        }
+    }
+}
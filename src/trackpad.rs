@@ -0,0 +1,84 @@
+use bevy::{
+    ecs::{
+        event::EventReader,
+        system::{Query, Res},
+    },
+    input::{
+        keyboard::KeyCode,
+        mouse::{MouseScrollUnit, MouseWheel},
+        Input,
+    },
+    math::Vec2,
+    prelude::Transform,
+    render::camera::OrthographicProjection,
+};
+
+/// How many pixels a single line of scrolling is worth, for devices that scroll in lines
+const PIXELS_PER_LINE: f32 = 20.0;
+
+/// Camera controls that feel natural on a laptop trackpad, scrolling with two fingers pans the
+/// camera and pinching (or scrolling while holding control, which is how a pinch reaches the app
+/// on most platforms) zooms it in and out around the center of the window
+///
+/// Only the scroll wheel is used, so this never gets in the way of anything following the
+/// cursor. Just like with CameraFraming though, the cursor is still treated as if the camera
+/// hadn't moved
+pub struct TrackpadCamera {
+    pub enabled: bool,
+    /// How many pixels on screen the camera pans for every pixel scrolled
+    pub pan_sensitivity: f32,
+    /// How much the camera zooms for every pixel pinched, the camera's scale is multiplied by
+    /// e^(-pixels * zoom_sensitivity) so zooming in and back out again ends up where it started
+    pub zoom_sensitivity: f32,
+    /// The closest the camera can zoom in to
+    pub min_scale: f32,
+    /// The furthest the camera can zoom out to
+    pub max_scale: f32,
+}
+
+impl Default for TrackpadCamera {
+    fn default() -> Self {
+        TrackpadCamera {
+            enabled: true,
+            pan_sensitivity: 1.0,
+            zoom_sensitivity: 0.01,
+            min_scale: 0.1,
+            max_scale: 10.0,
+        }
+    }
+}
+
+pub fn control_camera_with_trackpad(
+    controls: Res<TrackpadCamera>,
+    keys: Res<Input<KeyCode>>,
+    mut scrolls: EventReader<MouseWheel>,
+    mut cameras: Query<(&mut Transform, &mut OrthographicProjection)>,
+) {
+    // Scrolls still have to be read while disabled, or they'd all be applied at once when the
+    // controls are turned back on
+    let scrolled = scrolls.iter().fold(Vec2::ZERO, |total, scroll| {
+        let delta = Vec2::new(scroll.x, scroll.y);
+        total
+            + match scroll.unit {
+                MouseScrollUnit::Line => delta * PIXELS_PER_LINE,
+                MouseScrollUnit::Pixel => delta,
+            }
+    });
+    if !controls.enabled || scrolled == Vec2::ZERO {
+        return;
+    }
+    let zooming = keys.pressed(KeyCode::LControl) || keys.pressed(KeyCode::RControl);
+
+    for (mut transform, mut projection) in cameras.iter_mut() {
+        if zooming {
+            let scale = projection.scale * (-scrolled.y * controls.zoom_sensitivity).exp();
+            projection.scale = scale.clamp(controls.min_scale, controls.max_scale);
+        } else {
+            // The world follows the fingers so the camera moves against them, which for y is
+            // already taken care of by y pointing down on screen but up in the world
+            let pan =
+                Vec2::new(-scrolled.x, scrolled.y) * controls.pan_sensitivity * projection.scale;
+            transform.translation += pan.extend(0.0);
+        }
+    }
+}